@@ -0,0 +1,347 @@
+use {
+    crate::{
+        notifier::{Notifier, notify},
+        store::Store,
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warn,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "INFO"),
+            Severity::Warn => write!(f, "WARN"),
+            Severity::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+/// A condition that has been raised and not yet resolved.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ActiveAlert {
+    /// Number of consecutive cycles the condition was raised in
+    pub cycles: u32,
+    /// Unix timestamp of the first time the condition was raised
+    pub first_raised: u64,
+    /// Unix timestamp and severity of the last notification that went out
+    pub last_sent: Option<(u64, Severity)>,
+}
+
+/// Deduplicates, escalates and resolves notifications for recurring
+/// conditions such as RPC outages. Alert state lives in the [`Store`] so a
+/// restart does not re-announce an ongoing incident.
+#[derive(Clone)]
+pub struct Alerts {
+    store: Store,
+    dedup_window: Duration,
+    escalate_after_cycles: u32,
+}
+
+impl Alerts {
+    pub fn new(store: Store, dedup_window: Duration, escalate_after_cycles: u32) -> Self {
+        Self {
+            store,
+            dedup_window,
+            escalate_after_cycles,
+        }
+    }
+
+    /// Send a one-off message that does not describe an ongoing condition.
+    pub async fn info(&self, notifier: &dyn Notifier, message: &str) {
+        notify(notifier, &format!("[{}] {}", Severity::Info, message)).await;
+    }
+
+    /// Report that the condition identified by `key` is present this cycle.
+    ///
+    /// The first occurrence is always sent. Repeats are suppressed for the
+    /// dedup window unless the severity goes up, and a condition that
+    /// persists for `escalate_after_cycles` cycles is escalated to critical.
+    pub async fn raise(
+        &self,
+        notifier: &dyn Notifier,
+        key: &str,
+        severity: Severity,
+        message: &str,
+    ) {
+        let now = unix_timestamp();
+        let dedup_window = self.dedup_window.as_secs();
+        let escalate_after_cycles = self.escalate_after_cycles;
+
+        let result = self
+            .store
+            .update(|data| {
+                let alert = data
+                    .alerts
+                    .entry(key.to_string())
+                    .or_insert_with(|| ActiveAlert {
+                        cycles: 0,
+                        first_raised: now,
+                        last_sent: None,
+                    });
+                alert.cycles = alert.cycles.saturating_add(1);
+
+                let severity = if escalate_after_cycles > 0 && alert.cycles >= escalate_after_cycles
+                {
+                    Severity::Critical
+                } else {
+                    severity
+                };
+                let send = match alert.last_sent {
+                    None => true,
+                    Some((sent_at, sent_severity)) => {
+                        severity > sent_severity || now.saturating_sub(sent_at) >= dedup_window
+                    }
+                };
+                if send {
+                    alert.last_sent = Some((now, severity));
+                }
+                send.then_some((severity, alert.cycles))
+            })
+            .await;
+
+        match result {
+            Ok(Some((severity, cycles))) if cycles > 1 => {
+                notify(
+                    notifier,
+                    &format!("[{severity}] {message} (ongoing for {cycles} cycles)"),
+                )
+                .await
+            }
+            Ok(Some((severity, _))) => notify(notifier, &format!("[{severity}] {message}")).await,
+            Ok(None) => tracing::debug!("Suppressed duplicate alert {}: {}", key, message),
+            Err(err) => {
+                tracing::error!("Failed to record alert {}: {:#}", key, err);
+                notify(notifier, &format!("[{severity}] {message}")).await;
+            }
+        }
+    }
+
+    /// Report that the condition identified by `key` is not present. Sends a
+    /// single resolution message if the condition had been announced.
+    pub async fn resolve(&self, notifier: &dyn Notifier, key: &str, message: &str) {
+        if !self.store.read(|data| data.alerts.contains_key(key)).await {
+            return;
+        }
+        let result = self.store.update(|data| data.alerts.remove(key)).await;
+        match result {
+            Ok(Some(ActiveAlert {
+                last_sent: Some(_),
+                cycles,
+                ..
+            })) => {
+                notify(
+                    notifier,
+                    &format!("[RESOLVED] {message} (after {cycles} cycles)"),
+                )
+                .await
+            }
+            Ok(_) => {}
+            Err(err) => tracing::error!("Failed to clear alert {}: {:#}", key, err),
+        }
+    }
+
+    /// Keys of the active alerts `matches` accepts.
+    pub async fn active_keys(&self, matches: impl Fn(&str) -> bool) -> Vec<String> {
+        self.store
+            .read(|data| {
+                data.alerts
+                    .keys()
+                    .filter(|key| matches(key))
+                    .cloned()
+                    .collect()
+            })
+            .await
+    }
+
+    /// Drop the alerts `keys` without a resolution message, for conditions
+    /// superseded by a new alert that announces the change itself.
+    pub async fn clear(&self, keys: &[String]) {
+        if keys.is_empty() {
+            return;
+        }
+        let result = self
+            .store
+            .update(|data| {
+                for key in keys {
+                    data.alerts.remove(key);
+                }
+            })
+            .await;
+        if let Err(err) = result {
+            tracing::error!("Failed to clear alerts {:?}: {:#}", keys, err);
+        }
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::notifier::MockNotifier};
+
+    const KEY: &str = "rpc-down";
+
+    fn alerts(dedup_window: Duration, escalate_after_cycles: u32) -> Alerts {
+        Alerts::new(Store::temp(), dedup_window, escalate_after_cycles)
+    }
+
+    fn messages(notifier: &MockNotifier) -> Vec<String> {
+        notifier.messages.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn repeats_are_suppressed_within_the_dedup_window() {
+        let alerts = alerts(Duration::from_secs(3600), 0);
+        let notifier = MockNotifier::default();
+        for _ in 0..3 {
+            alerts
+                .raise(&notifier, KEY, Severity::Warn, "RPC down")
+                .await;
+        }
+        assert_eq!(messages(&notifier), vec!["[WARN] RPC down"]);
+    }
+
+    #[tokio::test]
+    async fn repeats_are_sent_again_after_the_dedup_window() {
+        let alerts = alerts(Duration::ZERO, 0);
+        let notifier = MockNotifier::default();
+        alerts
+            .raise(&notifier, KEY, Severity::Warn, "RPC down")
+            .await;
+        alerts
+            .raise(&notifier, KEY, Severity::Warn, "RPC down")
+            .await;
+        assert_eq!(
+            messages(&notifier),
+            vec!["[WARN] RPC down", "[WARN] RPC down (ongoing for 2 cycles)"]
+        );
+    }
+
+    #[tokio::test]
+    async fn higher_severity_is_sent_within_the_dedup_window() {
+        let alerts = alerts(Duration::from_secs(3600), 0);
+        let notifier = MockNotifier::default();
+        alerts
+            .raise(&notifier, KEY, Severity::Warn, "RPC slow")
+            .await;
+        alerts
+            .raise(&notifier, KEY, Severity::Critical, "RPC down")
+            .await;
+        alerts
+            .raise(&notifier, KEY, Severity::Warn, "RPC slow")
+            .await;
+        assert_eq!(
+            messages(&notifier),
+            vec![
+                "[WARN] RPC slow",
+                "[CRITICAL] RPC down (ongoing for 2 cycles)"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn escalates_to_critical_after_the_configured_cycles() {
+        let alerts = alerts(Duration::from_secs(3600), 3);
+        let notifier = MockNotifier::default();
+        for _ in 0..4 {
+            alerts
+                .raise(&notifier, KEY, Severity::Warn, "RPC down")
+                .await;
+        }
+        assert_eq!(
+            messages(&notifier),
+            vec![
+                "[WARN] RPC down",
+                "[CRITICAL] RPC down (ongoing for 3 cycles)"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_is_sent_once_for_an_announced_alert() {
+        let alerts = alerts(Duration::from_secs(3600), 0);
+        let notifier = MockNotifier::default();
+        alerts
+            .raise(&notifier, KEY, Severity::Warn, "RPC down")
+            .await;
+        alerts
+            .raise(&notifier, KEY, Severity::Warn, "RPC down")
+            .await;
+        alerts.resolve(&notifier, KEY, "RPC back").await;
+        alerts.resolve(&notifier, KEY, "RPC back").await;
+        assert_eq!(
+            messages(&notifier),
+            vec!["[WARN] RPC down", "[RESOLVED] RPC back (after 2 cycles)"]
+        );
+        assert!(alerts.store.read(|data| data.alerts.is_empty()).await);
+    }
+
+    #[tokio::test]
+    async fn resolve_without_a_raise_is_silent() {
+        let alerts = alerts(Duration::from_secs(3600), 0);
+        let notifier = MockNotifier::default();
+        alerts.resolve(&notifier, KEY, "RPC back").await;
+        assert!(messages(&notifier).is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_resolved_alert_is_announced_again() {
+        let alerts = alerts(Duration::from_secs(3600), 0);
+        let notifier = MockNotifier::default();
+        alerts
+            .raise(&notifier, KEY, Severity::Warn, "RPC down")
+            .await;
+        alerts.resolve(&notifier, KEY, "RPC back").await;
+        alerts
+            .raise(&notifier, KEY, Severity::Warn, "RPC down")
+            .await;
+        assert_eq!(messages(&notifier).last().unwrap(), "[WARN] RPC down");
+    }
+
+    #[tokio::test]
+    async fn cleared_alerts_are_dropped_silently() {
+        let alerts = alerts(Duration::from_secs(3600), 0);
+        let notifier = MockNotifier::default();
+        alerts
+            .raise(&notifier, "fee:10", Severity::Warn, "Fee 10%")
+            .await;
+        alerts
+            .raise(&notifier, "other", Severity::Warn, "Other")
+            .await;
+
+        let superseded = alerts.active_keys(|key| key.starts_with("fee:")).await;
+        assert_eq!(superseded, vec!["fee:10"]);
+        alerts.clear(&superseded).await;
+        alerts.resolve(&notifier, "fee:10", "Fee lowered").await;
+        assert_eq!(messages(&notifier), vec!["[WARN] Fee 10%", "[WARN] Other"]);
+        assert_eq!(alerts.active_keys(|_| true).await, vec!["other"]);
+    }
+
+    #[tokio::test]
+    async fn info_is_never_deduplicated() {
+        let alerts = alerts(Duration::from_secs(3600), 0);
+        let notifier = MockNotifier::default();
+        alerts.info(&notifier, "Epoch done").await;
+        alerts.info(&notifier, "Epoch done").await;
+        assert_eq!(
+            messages(&notifier),
+            vec!["[INFO] Epoch done", "[INFO] Epoch done"]
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use {
+    crate::{
+        AppState, build_config,
+        config::StakePoolConfig,
+        manager::fee_schedule::{self, FeeSchedule},
+        rates::PoolApy,
+        report::ChunkCounters,
+        rewards::{self, EpochRewards, ValidatorYield},
+        validator_info::ValidatorMetadata,
+    },
+    actix_web::{HttpRequest, HttpResponse, get, http::header, post, web},
+    serde::{Deserialize, Serialize},
+    solana_pubkey::Pubkey,
+    std::{collections::BTreeMap, str::FromStr},
+};
+
+/// Parse `address` if it is one of the configured stake pools.
+fn configured_pool(config: &StakePoolConfig, address: &str) -> Option<Pubkey> {
+    config
+        .stake_pool_address
+        .iter()
+        .any(|configured| configured == address)
+        .then(|| Pubkey::from_str(address).ok())
+        .flatten()
+}
+
+/// Check the bearer token of a request to an endpoint that sends transactions.
+fn authorize(config: &StakePoolConfig, request: &HttpRequest) -> Result<(), HttpResponse> {
+    let Some(token) = &config.admin_api_token else {
+        return Err(HttpResponse::Forbidden().body("ADMIN_API_TOKEN is not configured"));
+    };
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| value == token);
+    if authorized {
+        Ok(())
+    } else {
+        Err(HttpResponse::Unauthorized().body("Invalid or missing bearer token"))
+    }
+}
+
+#[get("/summaries")]
+pub async fn get_summaries(state: web::Data<AppState>) -> HttpResponse {
+    let summaries = state.store.read(|data| data.summaries.clone()).await;
+    HttpResponse::Ok().json(summaries)
+}
+
+#[get("/summaries/latest")]
+pub async fn get_latest_summary(state: web::Data<AppState>) -> HttpResponse {
+    match state
+        .store
+        .read(|data| data.summaries.last().cloned())
+        .await
+    {
+        Some(summary) => HttpResponse::Ok().json(summary),
+        None => HttpResponse::NotFound().body("No crank summary recorded yet"),
+    }
+}
+
+#[derive(Serialize)]
+struct ValidatorMetadataResponse {
+    /// Unix timestamp of the last metadata refresh, `None` before the first
+    refreshed_at: Option<u64>,
+    /// Metadata keyed by vote account
+    validators: BTreeMap<String, ValidatorMetadata>,
+}
+
+#[get("/validators/metadata")]
+pub async fn get_validator_metadata(state: web::Data<AppState>) -> HttpResponse {
+    let (validators, refreshed_at) = state.validator_info.all().await;
+    HttpResponse::Ok().json(ValidatorMetadataResponse {
+        refreshed_at,
+        validators: validators.into_iter().collect(),
+    })
+}
+
+#[get("/validators/{vote_account}/commission")]
+pub async fn get_commission_history(
+    state: web::Data<AppState>,
+    vote_account: web::Path<String>,
+) -> HttpResponse {
+    match state
+        .store
+        .read(|data| data.commissions.get(vote_account.as_str()).cloned())
+        .await
+    {
+        Some(history) => HttpResponse::Ok().json(history),
+        None => HttpResponse::NotFound().body("No commission recorded for this vote account"),
+    }
+}
+
+#[get("/pools/{address}/audit")]
+pub async fn get_audit(state: web::Data<AppState>, address: web::Path<String>) -> HttpResponse {
+    match state
+        .store
+        .read(|data| data.audits.get(address.as_str()).cloned())
+        .await
+    {
+        Some(report) => HttpResponse::Ok().json(report),
+        None => HttpResponse::NotFound().body("No audit recorded for this stake pool"),
+    }
+}
+
+#[get("/pools/{address}/apy")]
+pub async fn get_apy(state: web::Data<AppState>, address: web::Path<String>) -> HttpResponse {
+    let apy = state
+        .store
+        .read(|data| {
+            data.rates
+                .get(address.as_str())
+                .and_then(|history| PoolApy::from_history(&address, history))
+        })
+        .await;
+    match apy {
+        Some(apy) => HttpResponse::Ok().json(apy),
+        None => HttpResponse::NotFound().body("No exchange rate recorded for this stake pool"),
+    }
+}
+
+#[get("/pools/{address}/rate-history")]
+pub async fn get_rate_history(
+    state: web::Data<AppState>,
+    address: web::Path<String>,
+) -> HttpResponse {
+    match state
+        .store
+        .read(|data| data.rates.get(address.as_str()).cloned())
+        .await
+    {
+        Some(history) => HttpResponse::Ok().json(history),
+        None => HttpResponse::NotFound().body("No exchange rate recorded for this stake pool"),
+    }
+}
+
+#[derive(Serialize)]
+struct ValidatorRewards {
+    pool: String,
+    /// Validators ranked by average epoch yield, best first
+    ranking: Vec<ValidatorYield>,
+    latest: Option<EpochRewards>,
+}
+
+#[get("/pools/{address}/validator-rewards")]
+pub async fn get_validator_rewards(
+    state: web::Data<AppState>,
+    address: web::Path<String>,
+) -> HttpResponse {
+    let rewards = state
+        .store
+        .read(|data| {
+            data.validator_rewards
+                .get(address.as_str())
+                .map(|history| ValidatorRewards {
+                    pool: address.to_string(),
+                    ranking: rewards::rank(history),
+                    latest: history.last().cloned(),
+                })
+        })
+        .await;
+    match rewards {
+        Some(rewards) => HttpResponse::Ok().json(rewards),
+        None => HttpResponse::NotFound().body("No validator rewards recorded for this stake pool"),
+    }
+}
+
+#[derive(Serialize)]
+struct LeaderStatus {
+    holder: String,
+    leader: bool,
+}
+
+#[get("/leader")]
+pub async fn get_leader(
+    config: web::Data<StakePoolConfig>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(LeaderStatus {
+        holder: config.lease_holder.clone(),
+        leader: state.leadership.is_leader(),
+    })
+}
+
+#[get("/worker-restarts")]
+pub async fn get_worker_restarts(state: web::Data<AppState>) -> HttpResponse {
+    let restarts = state.store.read(|data| data.worker_restarts.clone()).await;
+    HttpResponse::Ok().json(restarts)
+}
+
+#[get("/metrics")]
+pub async fn get_metrics(state: web::Data<AppState>) -> HttpResponse {
+    let counters: BTreeMap<String, ChunkCounters> = state
+        .store
+        .read(|data| data.chunk_counters.clone())
+        .await
+        .into_iter()
+        .collect();
+    let mut body = String::from(
+        "# HELP cranker_update_chunks_total Update chunks per pool, sent or skipped because another cranker already ran them\n\
+         # TYPE cranker_update_chunks_total counter\n",
+    );
+    for (pool, counters) in counters {
+        body.push_str(&format!(
+            "cranker_update_chunks_total{{pool=\"{pool}\",result=\"sent\"}} {}\n",
+            counters.sent
+        ));
+        body.push_str(&format!(
+            "cranker_update_chunks_total{{pool=\"{pool}\",result=\"skipped\"}} {}\n",
+            counters.skipped
+        ));
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+#[get("/pools/{address}/fees")]
+pub async fn get_fees(
+    config: web::Data<StakePoolConfig>,
+    address: web::Path<String>,
+) -> HttpResponse {
+    let Some(stake_pool_address) = configured_pool(&config, &address) else {
+        return HttpResponse::NotFound().body("Unknown stake pool");
+    };
+    let result = match build_config(&config) {
+        Ok(pool_config) => fee_schedule::fee_schedule(&pool_config, &stake_pool_address).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(schedule) => HttpResponse::Ok().json(schedule),
+        Err(err) => HttpResponse::InternalServerError().body(format!("{err:#}")),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FeeChange {
+    /// Fee name as accepted by `fees set`, e.g. `epoch` or `sol-deposit`
+    pub fee: String,
+    /// `<numerator>/<denominator>`, or a percentage for referral fees
+    pub value: String,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct FeeChangeResponse {
+    signature: Option<String>,
+    schedule: FeeSchedule,
+}
+
+#[post("/pools/{address}/fees")]
+pub async fn set_fee(
+    request: HttpRequest,
+    config: web::Data<StakePoolConfig>,
+    state: web::Data<AppState>,
+    address: web::Path<String>,
+    change: web::Json<FeeChange>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&config, &request) {
+        return response;
+    }
+    // the worker advances the same nonce, two transactions built on it at
+    // once would invalidate each other
+    if config.nonce_account.is_some() {
+        return HttpResponse::Conflict()
+            .body("Fees cannot be set through the API while NONCE_ACCOUNT is set");
+    }
+    if !state.leadership.is_leader() {
+        return HttpResponse::ServiceUnavailable()
+            .body("This replica is on standby, send the request to the leader");
+    }
+    let Some(stake_pool_address) = configured_pool(&config, &address) else {
+        return HttpResponse::NotFound().body("Unknown stake pool");
+    };
+    let fee = match fee_schedule::parse_fee(&change.fee, &change.value) {
+        Ok(fee) => fee,
+        Err(err) => return HttpResponse::BadRequest().body(format!("{err:#}")),
+    };
+    let mut pool_config = match build_config(&config) {
+        Ok(pool_config) => pool_config,
+        Err(err) => return HttpResponse::InternalServerError().body(format!("{err:#}")),
+    };
+    pool_config.leadership = Some(state.leadership.clone());
+
+    let result = async {
+        let signature =
+            fee_schedule::set_fee(&pool_config, &stake_pool_address, fee, change.dry_run).await?;
+        let schedule = fee_schedule::fee_schedule(&pool_config, &stake_pool_address).await?;
+        anyhow::Ok(FeeChangeResponse {
+            signature,
+            schedule,
+        })
+    }
+    .await;
+    match result {
+        Ok(response) => {
+            if !change.dry_run {
+                state
+                    .alerts
+                    .info(
+                        state.notifiers.for_pool(&address),
+                        &format!(
+                            "The {} fee of stake pool {} was set to {} through the API",
+                            change.fee, address, change.value
+                        ),
+                    )
+                    .await;
+            }
+            HttpResponse::Ok().json(response)
+        }
+        Err(err) => HttpResponse::InternalServerError().body(format!("{err:#}")),
+    }
+}
@@ -0,0 +1,263 @@
+use {
+    crate::{
+        AppState, Config,
+        alerts::Severity,
+        client::{get_stake_pool, get_validator_list},
+        notifier::Notifier,
+    },
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    solana_native_token::Sol,
+    solana_program::stake::{self, state::StakeStateV2},
+    solana_pubkey::Pubkey,
+    std::num::NonZeroU32,
+};
+
+/// Accounts per `getMultipleAccounts` request
+const MAX_ACCOUNTS_PER_REQUEST: usize = 100;
+
+/// An account whose on-chain state does not match the pool's bookkeeping.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Finding {
+    pub account: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditReport {
+    pub pool: String,
+    pub pool_name: String,
+    pub epoch: u64,
+    /// `StakePool.total_lamports`
+    pub total_lamports: u64,
+    /// Reserve above rent plus active and transient stake of every validator
+    pub expected_total_lamports: u64,
+    pub validators_checked: usize,
+    /// Set when the pool was not updated for the epoch, in which case the
+    /// balances are expected to lag and only the stake accounts are checked
+    pub stale: bool,
+    pub findings: Vec<Finding>,
+}
+
+impl AuditReport {
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!(
+            "Audit of stake pool {} for epoch {}: total {} recorded, {} expected, {} validators checked{}",
+            self.pool_name,
+            self.epoch,
+            Sol(self.total_lamports),
+            Sol(self.expected_total_lamports),
+            self.validators_checked,
+            if self.stale {
+                " (pool not updated)"
+            } else {
+                ""
+            }
+        )];
+        if self.is_clean() {
+            lines.push("  no discrepancies".to_string());
+        }
+        for finding in &self.findings {
+            lines.push(format!("- {}: {}", finding.account, finding.message));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Cross-check the pool's totals and every validator stake account against
+/// what the validator list records.
+pub async fn audit_pool(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    pool_name: &str,
+) -> Result<AuditReport> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+    let withdraw_authority = spl_stake_pool::find_withdraw_authority_program_address(
+        &config.stake_pool_program_id,
+        stake_pool_address,
+    )
+    .0;
+    let stale = stake_pool.last_update_epoch < epoch;
+    let mut findings = Vec::new();
+
+    // the program counts the reserve without its rent exempt reserve
+    let reserve = config
+        .rpc_client
+        .get_account(&stake_pool.reserve_stake)
+        .await?;
+    let reserve_lamports = match bincode::deserialize::<StakeStateV2>(&reserve.data) {
+        Ok(StakeStateV2::Initialized(meta)) => {
+            if meta.authorized.withdrawer != withdraw_authority {
+                findings.push(Finding {
+                    account: stake_pool.reserve_stake.to_string(),
+                    message: format!(
+                        "reserve withdrawer is {}, expected the pool withdraw authority {}",
+                        meta.authorized.withdrawer, withdraw_authority
+                    ),
+                });
+            }
+            reserve.lamports.saturating_sub(meta.rent_exempt_reserve)
+        }
+        _ => {
+            findings.push(Finding {
+                account: stake_pool.reserve_stake.to_string(),
+                message: "reserve is not an initialized, undelegated stake account".to_string(),
+            });
+            reserve.lamports
+        }
+    };
+
+    let expected_total_lamports = validator_list
+        .validators
+        .iter()
+        .map(|validator| {
+            u64::from_le_bytes(validator.active_stake_lamports.0)
+                .saturating_add(u64::from_le_bytes(validator.transient_stake_lamports.0))
+        })
+        .fold(reserve_lamports, u64::saturating_add);
+    if !stale && expected_total_lamports != stake_pool.total_lamports {
+        findings.push(Finding {
+            account: stake_pool_address.to_string(),
+            message: format!(
+                "total_lamports is {} but the reserve and validator stake add up to {}",
+                Sol(stake_pool.total_lamports),
+                Sol(expected_total_lamports)
+            ),
+        });
+    }
+
+    let stake_addresses: Vec<Pubkey> = validator_list
+        .validators
+        .iter()
+        .map(|validator| {
+            spl_stake_pool::find_stake_program_address(
+                &config.stake_pool_program_id,
+                &validator.vote_account_address,
+                stake_pool_address,
+                NonZeroU32::new(u32::from_le_bytes(validator.validator_seed_suffix.0)),
+            )
+            .0
+        })
+        .collect();
+    let mut accounts = Vec::with_capacity(stake_addresses.len());
+    for chunk in stake_addresses.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+        accounts.extend(config.rpc_client.get_multiple_accounts(chunk).await?);
+    }
+
+    for ((validator, address), account) in validator_list
+        .validators
+        .iter()
+        .zip(&stake_addresses)
+        .zip(accounts)
+    {
+        let vote_account = validator.vote_account_address;
+        let recorded = u64::from_le_bytes(validator.active_stake_lamports.0);
+        let Some(account) = account else {
+            // removed validators are dropped from the list at the next update
+            if recorded > 0 {
+                findings.push(Finding {
+                    account: address.to_string(),
+                    message: format!(
+                        "stake account of {vote_account} does not exist, {} recorded",
+                        Sol(recorded)
+                    ),
+                });
+            }
+            continue;
+        };
+        if account.owner != stake::program::id() {
+            findings.push(Finding {
+                account: address.to_string(),
+                message: format!(
+                    "stake account of {vote_account} is not owned by the stake program"
+                ),
+            });
+            continue;
+        }
+        // balances move at the epoch boundary and are only recorded by the update
+        if u64::from_le_bytes(validator.last_update_epoch.0) == epoch
+            && account.lamports != recorded
+        {
+            findings.push(Finding {
+                account: address.to_string(),
+                message: format!(
+                    "stake account of {vote_account} holds {}, {} recorded",
+                    Sol(account.lamports),
+                    Sol(recorded)
+                ),
+            });
+        }
+        match bincode::deserialize::<StakeStateV2>(&account.data) {
+            Ok(StakeStateV2::Stake(meta, stake, _)) => {
+                if stake.delegation.voter_pubkey != vote_account {
+                    findings.push(Finding {
+                        account: address.to_string(),
+                        message: format!(
+                            "stake account is delegated to {}, the list records {}",
+                            stake.delegation.voter_pubkey, vote_account
+                        ),
+                    });
+                }
+                if meta.authorized.withdrawer != withdraw_authority {
+                    findings.push(Finding {
+                        account: address.to_string(),
+                        message: format!(
+                            "stake account of {} has withdrawer {}, expected the pool withdraw authority",
+                            vote_account, meta.authorized.withdrawer
+                        ),
+                    });
+                }
+            }
+            _ => findings.push(Finding {
+                account: address.to_string(),
+                message: format!("stake account of {vote_account} is not delegated"),
+            }),
+        }
+    }
+
+    Ok(AuditReport {
+        pool: stake_pool_address.to_string(),
+        pool_name: pool_name.to_string(),
+        epoch,
+        total_lamports: stake_pool.total_lamports,
+        expected_total_lamports,
+        validators_checked: validator_list.validators.len(),
+        stale,
+        findings,
+    })
+}
+
+/// Store the report for the API and alert while it has findings.
+pub async fn publish(state: &AppState, notifier: &dyn Notifier, report: AuditReport) -> Result<()> {
+    let alert_key = format!("audit:{}", report.pool);
+    if report.is_clean() {
+        state
+            .alerts
+            .resolve(
+                notifier,
+                &alert_key,
+                &format!(
+                    "Audit of stake pool {} found no discrepancies",
+                    report.pool_name
+                ),
+            )
+            .await;
+    } else {
+        state
+            .alerts
+            .raise(notifier, &alert_key, Severity::Warn, &report.render())
+            .await;
+    }
+    state
+        .store
+        .update(|data| {
+            data.audits.insert(report.pool.clone(), report);
+        })
+        .await
+}
@@ -0,0 +1,239 @@
+use {
+    crate::{
+        Config,
+        alerts::{Alerts, Severity},
+        notifier::Notifier,
+        store::Store,
+    },
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    solana_native_token::Sol,
+    solana_signer::Signer,
+};
+
+const RUNWAY_ALERT_KEY: &str = "fee-payer-runway";
+
+/// Number of epochs of balance history kept for the spend estimate
+const MAX_BALANCE_SAMPLES: usize = 32;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BalanceSample {
+    pub epoch: u64,
+    pub lamports: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RunwayLevel {
+    Healthy,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Runway {
+    pub balance: u64,
+    /// Average lamports spent per epoch, `None` until there is enough history
+    pub spend_per_epoch: Option<u64>,
+    /// Estimated epochs until the balance runs out at the current spend rate
+    pub epochs_remaining: Option<f64>,
+    pub level: RunwayLevel,
+}
+
+/// Record `lamports` as the balance for `epoch`, keeping one sample per epoch.
+pub fn record_sample(samples: &mut Vec<BalanceSample>, epoch: u64, lamports: u64) {
+    match samples.last_mut() {
+        Some(last) if last.epoch == epoch => last.lamports = lamports,
+        _ => samples.push(BalanceSample { epoch, lamports }),
+    }
+    if samples.len() > MAX_BALANCE_SAMPLES {
+        let excess = samples.len() - MAX_BALANCE_SAMPLES;
+        samples.drain(..excess);
+    }
+}
+
+/// Estimate how many epochs the fee payer can keep cranking from its balance
+/// history. Top-ups are ignored so a refill does not look like negative spend.
+pub fn forecast(
+    samples: &[BalanceSample],
+    balance: u64,
+    warning_epochs: f64,
+    critical_epochs: f64,
+) -> Runway {
+    let spent: u64 = samples
+        .windows(2)
+        .map(|pair| pair[0].lamports.saturating_sub(pair[1].lamports))
+        .sum();
+    let epochs_spanned = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => last.epoch.saturating_sub(first.epoch),
+        _ => 0,
+    };
+
+    let spend_per_epoch = if epochs_spanned > 0 {
+        Some(spent / epochs_spanned)
+    } else {
+        None
+    };
+    let epochs_remaining = spend_per_epoch
+        .filter(|spend| *spend > 0)
+        .map(|spend| balance as f64 / spend as f64);
+
+    let level = match epochs_remaining {
+        Some(epochs) if epochs <= critical_epochs => RunwayLevel::Critical,
+        Some(epochs) if epochs <= warning_epochs => RunwayLevel::Warning,
+        _ => RunwayLevel::Healthy,
+    };
+
+    Runway {
+        balance,
+        spend_per_epoch,
+        epochs_remaining,
+        level,
+    }
+}
+
+/// Sample the fee payer balance for `epoch`, update the forecast and alert
+/// when the runway drops to the warning or critical threshold.
+pub async fn monitor_fee_payer_runway(
+    config: &Config,
+    store: &Store,
+    alerts: &Alerts,
+    notifier: &dyn Notifier,
+    epoch: u64,
+    warning_epochs: f64,
+    critical_epochs: f64,
+) -> Result<Runway> {
+    let fee_payer = config.fee_payer.pubkey();
+    let balance = config.rpc_client.get_balance(&fee_payer).await?;
+
+    let runway = store
+        .update(|data| {
+            record_sample(&mut data.fee_payer_balances, epoch, balance);
+            forecast(
+                &data.fee_payer_balances,
+                balance,
+                warning_epochs,
+                critical_epochs,
+            )
+        })
+        .await?;
+
+    tracing::info!(
+        "Fee payer {} balance {}, spend per epoch {:?}, epochs remaining {:?}",
+        fee_payer,
+        Sol(balance),
+        runway.spend_per_epoch,
+        runway.epochs_remaining
+    );
+
+    let severity = match runway.level {
+        RunwayLevel::Healthy => {
+            alerts
+                .resolve(
+                    notifier,
+                    RUNWAY_ALERT_KEY,
+                    &format!("Fee payer {} runway is healthy again", fee_payer),
+                )
+                .await;
+            return Ok(runway);
+        }
+        RunwayLevel::Warning => Severity::Warn,
+        RunwayLevel::Critical => Severity::Critical,
+    };
+
+    let message = format!(
+        "Fee payer {} has {} left, about {:.1} epochs of cranking at {} per epoch",
+        fee_payer,
+        Sol(balance),
+        runway.epochs_remaining.unwrap_or_default(),
+        Sol(runway.spend_per_epoch.unwrap_or_default())
+    );
+    tracing::warn!("{}", message);
+    alerts
+        .raise(notifier, RUNWAY_ALERT_KEY, severity, &message)
+        .await;
+
+    Ok(runway)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(history: &[(u64, u64)]) -> Vec<BalanceSample> {
+        history
+            .iter()
+            .map(|&(epoch, lamports)| BalanceSample { epoch, lamports })
+            .collect()
+    }
+
+    #[test]
+    fn no_estimate_without_history() {
+        let runway = forecast(&samples(&[(10, 1_000)]), 1_000, 10.0, 3.0);
+        assert_eq!(runway.spend_per_epoch, None);
+        assert_eq!(runway.epochs_remaining, None);
+        assert_eq!(runway.level, RunwayLevel::Healthy);
+
+        let runway = forecast(&[], 1_000, 10.0, 3.0);
+        assert_eq!(runway.spend_per_epoch, None);
+    }
+
+    #[test]
+    fn averages_spend_over_spanned_epochs() {
+        // a gap of two epochs between the last samples
+        let history = samples(&[(10, 10_000), (11, 9_000), (13, 7_000)]);
+        let runway = forecast(&history, 7_000, 10.0, 3.0);
+        assert_eq!(runway.spend_per_epoch, Some(1_000));
+        assert_eq!(runway.epochs_remaining, Some(7.0));
+        assert_eq!(runway.level, RunwayLevel::Warning);
+    }
+
+    #[test]
+    fn ignores_top_ups() {
+        let history = samples(&[(10, 2_000), (11, 1_000), (12, 11_000), (13, 10_000)]);
+        let runway = forecast(&history, 10_000, 10.0, 3.0);
+        assert_eq!(runway.spend_per_epoch, Some(666));
+        assert_eq!(runway.level, RunwayLevel::Healthy);
+    }
+
+    #[test]
+    fn levels_follow_thresholds() {
+        let history = samples(&[(10, 4_000), (11, 3_000)]);
+        assert_eq!(
+            forecast(&history, 3_000, 10.0, 3.0).level,
+            RunwayLevel::Critical
+        );
+        assert_eq!(
+            forecast(&history, 10_000, 10.0, 3.0).level,
+            RunwayLevel::Warning
+        );
+        assert_eq!(
+            forecast(&history, 10_001, 10.0, 3.0).level,
+            RunwayLevel::Healthy
+        );
+    }
+
+    #[test]
+    fn no_spend_is_healthy() {
+        let history = samples(&[(10, 5_000), (11, 5_000)]);
+        let runway = forecast(&history, 5_000, 10.0, 3.0);
+        assert_eq!(runway.spend_per_epoch, Some(0));
+        assert_eq!(runway.epochs_remaining, None);
+        assert_eq!(runway.level, RunwayLevel::Healthy);
+    }
+
+    #[test]
+    fn record_sample_keeps_one_per_epoch() {
+        let mut history = Vec::new();
+        record_sample(&mut history, 1, 100);
+        record_sample(&mut history, 1, 90);
+        record_sample(&mut history, 2, 80);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].lamports, 90);
+
+        for epoch in 3..3 + MAX_BALANCE_SAMPLES as u64 {
+            record_sample(&mut history, epoch, 0);
+        }
+        assert_eq!(history.len(), MAX_BALANCE_SAMPLES);
+        assert_eq!(history[0].epoch, 3);
+    }
+}
@@ -0,0 +1,487 @@
+use {
+    crate::{
+        Config, UpdateOutcome, checked_transaction_with_signers,
+        checked_transaction_with_signers_and_additional_fee,
+        config::{StakePoolConfig, Submission},
+        ensure_leader, send_transaction,
+    },
+    actix_web::{App, HttpResponse, HttpServer, post, web},
+    anyhow::{Context, Result},
+    base64::{Engine, engine::general_purpose::STANDARD},
+    serde::Deserialize,
+    serde_json::{Value, json},
+    solana_commitment_config::CommitmentConfig,
+    solana_instruction::Instruction,
+    solana_program::system_instruction,
+    solana_pubkey::Pubkey,
+    solana_rpc_client::nonblocking::rpc_client::RpcClient,
+    solana_signer::Signer,
+    solana_transaction::{Transaction, versioned::VersionedTransaction},
+    std::{collections::HashSet, str::FromStr},
+    tokio::time::{Duration, Instant, sleep},
+};
+
+/// Mainnet block engine tip accounts, one is picked per bundle
+const TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+];
+
+/// Most transactions a block engine accepts in one bundle
+const MAX_BUNDLE_TRANSACTIONS: usize = 5;
+
+/// How long to wait for a bundle to land before giving up
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+const BUNDLES_PATH: &str = "/api/v1/bundles";
+
+/// Sends the final update transactions of the pools that selected
+/// `Submission::Bundle` to a block engine.
+pub struct BundleSender {
+    client: reqwest::Client,
+    url: String,
+    tip_lamports: u64,
+    tip_account: Option<Pubkey>,
+    pools: HashSet<Pubkey>,
+}
+
+impl BundleSender {
+    /// `None` when no configured pool submits bundles.
+    pub fn from_config(config: &StakePoolConfig) -> Result<Option<Self>> {
+        let pools = config
+            .stake_pool_address
+            .iter()
+            .filter(|address| {
+                config
+                    .pool_submission
+                    .get(*address)
+                    .copied()
+                    .unwrap_or(config.submission)
+                    == Submission::Bundle
+            })
+            .map(|address| Pubkey::from_str(address))
+            .collect::<Result<HashSet<_>, _>>()?;
+        if pools.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            client: reqwest::Client::new(),
+            url: format!(
+                "{}{}",
+                config.block_engine_url.trim_end_matches('/'),
+                BUNDLES_PATH
+            ),
+            tip_lamports: config.bundle_tip_lamports,
+            tip_account: config.bundle_tip_account,
+            pools,
+        }))
+    }
+
+    pub fn enabled_for(&self, stake_pool_address: &Pubkey) -> bool {
+        self.pools.contains(stake_pool_address)
+    }
+
+    fn tip_instruction(&self, payer: &Pubkey) -> Instruction {
+        let tip_account = self.tip_account.unwrap_or_else(|| {
+            let index = rand::random_range(0..TIP_ACCOUNTS.len());
+            Pubkey::from_str(TIP_ACCOUNTS[index]).expect("tip accounts are valid addresses")
+        });
+        system_instruction::transfer(payer, &tip_account, self.tip_lamports)
+    }
+
+    /// Drop empty groups and add the tip to the last one, rejecting more
+    /// transactions than a bundle holds.
+    fn with_tip(
+        &self,
+        payer: &Pubkey,
+        mut batches: Vec<Vec<Instruction>>,
+    ) -> Result<Vec<Vec<Instruction>>> {
+        batches.retain(|batch| !batch.is_empty());
+        if batches.len() > MAX_BUNDLE_TRANSACTIONS {
+            anyhow::bail!(
+                "A bundle holds at most {} transactions, {} requested",
+                MAX_BUNDLE_TRANSACTIONS,
+                batches.len()
+            );
+        }
+        if let Some(last) = batches.last_mut() {
+            last.push(self.tip_instruction(payer));
+        }
+        Ok(batches)
+    }
+
+    /// Send `transactions` as one bundle and return the bundle id.
+    async fn send_bundle(&self, transactions: &[Transaction]) -> Result<String> {
+        let encoded = transactions
+            .iter()
+            .map(|transaction| Ok(STANDARD.encode(bincode::serialize(transaction)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let response: Value = self
+            .client
+            .post(&self.url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "sendBundle",
+                "params": [encoded, {"encoding": "base64"}],
+            }))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach block engine {}", self.url))?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Invalid block engine response")?;
+        if let Some(error) = response.get("error") {
+            anyhow::bail!("Block engine rejected the bundle: {}", error);
+        }
+        response["result"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("Block engine returned no bundle id: {}", response))
+    }
+}
+
+/// Send each group of `batches` as a transaction in one bundle, with the tip
+/// paid by the last transaction, and wait for it to land. A dry run only
+/// simulates the transactions.
+pub async fn submit(
+    config: &Config,
+    sender: &BundleSender,
+    batches: Vec<Vec<Instruction>>,
+    outcome: &mut UpdateOutcome,
+) -> Result<()> {
+    let mut batches = sender.with_tip(&config.fee_payer.pubkey(), batches)?;
+    let Some(last) = batches.pop() else {
+        return Ok(());
+    };
+    let mut transactions = Vec::with_capacity(batches.len() + 1);
+    let mut fees = Vec::with_capacity(batches.len() + 1);
+    for batch in batches {
+        let (transaction, fee) =
+            checked_transaction_with_signers(config, &batch, &[config.fee_payer.as_ref()]).await?;
+        transactions.push(transaction);
+        fees.push(fee);
+    }
+    let (transaction, fee) = checked_transaction_with_signers_and_additional_fee(
+        config,
+        &last,
+        &[config.fee_payer.as_ref()],
+        sender.tip_lamports,
+    )
+    .await?;
+    transactions.push(transaction);
+    fees.push(fee.saturating_add(sender.tip_lamports));
+
+    if config.dry_run {
+        for transaction in transactions {
+            send_transaction(config, transaction).await?;
+        }
+        return Ok(());
+    }
+
+    ensure_leader(config)?;
+    let bundle_id = sender.send_bundle(&transactions).await?;
+    tracing::info!(
+        "Sent bundle {} with {} transactions",
+        bundle_id,
+        transactions.len()
+    );
+    // bundles land atomically, so the last transaction stands for all of them
+    let last_signature = transactions
+        .last()
+        .and_then(|transaction| transaction.signatures.first())
+        .copied()
+        .context("Bundle transaction is not signed")?;
+    let started = Instant::now();
+    loop {
+        let status = config
+            .rpc_client
+            .get_signature_status_with_commitment(&last_signature, CommitmentConfig::confirmed())
+            .await?;
+        match status {
+            Some(Ok(())) => break,
+            Some(Err(err)) => anyhow::bail!("Bundle {} failed: {}", bundle_id, err),
+            None if started.elapsed() > CONFIRM_TIMEOUT => {
+                anyhow::bail!(
+                    "Bundle {} did not land within {:?}",
+                    bundle_id,
+                    CONFIRM_TIMEOUT
+                )
+            }
+            None => sleep(Duration::from_secs(2)).await,
+        }
+    }
+    for (transaction, fee) in transactions.iter().zip(fees) {
+        outcome.record(
+            transaction
+                .signatures
+                .first()
+                .map(|signature| signature.to_string()),
+            fee,
+        );
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct JsonRpcRequest {
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Stand-in for a block engine: `sendBundle` forwards the transactions one by
+/// one to the RPC, without the atomicity of a real bundle.
+#[post("/api/v1/bundles")]
+async fn mock_bundles(
+    rpc_client: web::Data<RpcClient>,
+    request: web::Json<JsonRpcRequest>,
+) -> HttpResponse {
+    let JsonRpcRequest { id, method, params } = request.into_inner();
+    if method != "sendBundle" {
+        return HttpResponse::Ok().json(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": -32601, "message": format!("Method not found: {method}")},
+        }));
+    }
+    match forward_bundle(&rpc_client, &params).await {
+        Ok(bundle_id) => HttpResponse::Ok().json(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": bundle_id,
+        })),
+        Err(err) => HttpResponse::Ok().json(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": -32000, "message": format!("{err:#}")},
+        })),
+    }
+}
+
+async fn forward_bundle(rpc_client: &RpcClient, params: &Value) -> Result<String> {
+    let encoded = params[0]
+        .as_array()
+        .context("sendBundle expects a list of transactions")?;
+    if encoded.is_empty() || encoded.len() > MAX_BUNDLE_TRANSACTIONS {
+        anyhow::bail!(
+            "A bundle holds 1 to {} transactions, got {}",
+            MAX_BUNDLE_TRANSACTIONS,
+            encoded.len()
+        );
+    }
+    let mut signatures = Vec::with_capacity(encoded.len());
+    for transaction in encoded {
+        let bytes = STANDARD.decode(
+            transaction
+                .as_str()
+                .context("Bundle transactions must be base64 strings")?,
+        )?;
+        let transaction: VersionedTransaction = bincode::deserialize(&bytes)?;
+        let signature = rpc_client
+            .send_and_confirm_transaction(&transaction)
+            .await?;
+        tracing::info!("Mock block engine landed {}", signature);
+        signatures.push(signature);
+    }
+    // real block engines return a hash of the signatures
+    Ok(signatures[0].to_string())
+}
+
+/// Serve the mock block engine on `port` until the process is stopped.
+pub async fn run_mock_server(rpc_url: &str, port: u16) -> Result<()> {
+    let rpc_client = web::Data::new(RpcClient::new_with_commitment(
+        rpc_url.to_string(),
+        CommitmentConfig::confirmed(),
+    ));
+    tracing::info!(
+        "Mock block engine listening on http://127.0.0.1:{}{}",
+        port,
+        BUNDLES_PATH
+    );
+    HttpServer::new(move || {
+        App::new()
+            .app_data(rpc_client.clone())
+            .service(mock_bundles)
+    })
+    .bind(("127.0.0.1", port))?
+    .run()
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        actix_web::test::{TestRequest, call_and_read_body_json, init_service},
+        solana_program::system_program,
+    };
+
+    fn sender(url: String, tip_account: Option<Pubkey>) -> BundleSender {
+        BundleSender {
+            client: reqwest::Client::new(),
+            url,
+            tip_lamports: 10_000,
+            tip_account,
+            pools: HashSet::new(),
+        }
+    }
+
+    fn batch(len: usize) -> Vec<Instruction> {
+        let payer = Pubkey::new_unique();
+        (0..len)
+            .map(|_| system_instruction::transfer(&payer, &Pubkey::new_unique(), 1))
+            .collect()
+    }
+
+    fn transaction() -> Transaction {
+        let payer = Pubkey::new_unique();
+        Transaction::new_with_payer(&batch(1), Some(&payer))
+    }
+
+    #[test]
+    fn tip_is_paid_by_the_last_transaction() {
+        let payer = Pubkey::new_unique();
+        let tip_account = Pubkey::new_unique();
+        let batches = vec![batch(2), Vec::new(), batch(1), batch(3)];
+        let expected = batches.clone();
+        let bundled = sender(String::new(), Some(tip_account))
+            .with_tip(&payer, batches)
+            .unwrap();
+
+        // the empty group is dropped, the others keep their order
+        assert_eq!(bundled.len(), 3);
+        assert_eq!(bundled[0], expected[0]);
+        assert_eq!(bundled[1], expected[2]);
+        assert_eq!(bundled[2][..3], expected[3][..]);
+        assert_eq!(
+            bundled[2][3],
+            system_instruction::transfer(&payer, &tip_account, 10_000)
+        );
+    }
+
+    #[test]
+    fn tip_goes_to_a_block_engine_account_by_default() {
+        let payer = Pubkey::new_unique();
+        let bundled = sender(String::new(), None)
+            .with_tip(&payer, vec![batch(1)])
+            .unwrap();
+        let tip = bundled[0].last().unwrap();
+        assert_eq!(tip.program_id, system_program::id());
+        assert!(TIP_ACCOUNTS.contains(&tip.accounts[1].pubkey.to_string().as_str()));
+    }
+
+    #[test]
+    fn rejects_more_transactions_than_a_bundle_holds() {
+        let payer = Pubkey::new_unique();
+        let sender = sender(String::new(), None);
+        let full: Vec<_> = (0..MAX_BUNDLE_TRANSACTIONS).map(|_| batch(1)).collect();
+        assert_eq!(sender.with_tip(&payer, full.clone()).unwrap().len(), 5);
+
+        let mut over = full;
+        over.push(batch(1));
+        let err = sender.with_tip(&payer, over).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("at most 5 transactions, 6 requested")
+        );
+        assert!(sender.with_tip(&payer, Vec::new()).unwrap().is_empty());
+    }
+
+    /// Accepts bundles of a single transaction and rejects the rest.
+    #[post("/api/v1/bundles")]
+    async fn block_engine(request: web::Json<Value>) -> HttpResponse {
+        match request["params"][0].as_array().map(Vec::len) {
+            Some(1) => HttpResponse::Ok().json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": "bundle-id",
+            })),
+            _ => HttpResponse::Ok().json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": {"code": -32000, "message": "bundle rejected"},
+            })),
+        }
+    }
+
+    #[actix_web::test]
+    async fn send_bundle_reports_json_rpc_errors() {
+        let server = HttpServer::new(|| App::new().service(block_engine))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        let sender = sender(format!("http://{address}{BUNDLES_PATH}"), None);
+
+        assert_eq!(
+            sender.send_bundle(&[transaction()]).await.unwrap(),
+            "bundle-id"
+        );
+        let err = sender
+            .send_bundle(&[transaction(), transaction()])
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Block engine rejected the bundle"), "{err}");
+        assert!(err.contains("bundle rejected"), "{err}");
+
+        handle.stop(false).await;
+    }
+
+    async fn call_mock(body: Value) -> Value {
+        // nothing listens here, the requests fail before a transaction is sent
+        let rpc_client = web::Data::new(RpcClient::new("http://127.0.0.1:1".to_string()));
+        let app = init_service(App::new().app_data(rpc_client).service(mock_bundles)).await;
+        let request = TestRequest::post()
+            .uri(BUNDLES_PATH)
+            .set_json(body)
+            .to_request();
+        call_and_read_body_json(&app, request).await
+    }
+
+    fn send_bundle_request(params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": 7, "method": "sendBundle", "params": params})
+    }
+
+    #[actix_web::test]
+    async fn mock_rejects_unknown_methods() {
+        let response =
+            call_mock(json!({"jsonrpc": "2.0", "id": 7, "method": "getBundleStatuses"})).await;
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["error"]["code"], -32601);
+    }
+
+    #[actix_web::test]
+    async fn mock_validates_the_bundle() {
+        let encoded = STANDARD.encode(bincode::serialize(&transaction()).unwrap());
+        let cases = [
+            (json!(["not a list"]), "expects a list of transactions"),
+            (json!([[]]), "1 to 5 transactions, got 0"),
+            (json!([vec![encoded; 6]]), "1 to 5 transactions, got 6"),
+            (json!([[42]]), "must be base64 strings"),
+            (json!([["not base64!"]]), "Invalid"),
+            (json!([[STANDARD.encode([1, 2, 3])]]), ""),
+        ];
+        for (params, expected) in cases {
+            let response = call_mock(send_bundle_request(params)).await;
+            assert_eq!(response["id"], 7);
+            assert_eq!(response["error"]["code"], -32000);
+            assert!(response.get("result").is_none());
+            let message = response["error"]["message"].as_str().unwrap();
+            assert!(message.contains(expected), "{message}");
+        }
+    }
+}
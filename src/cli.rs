@@ -0,0 +1,202 @@
+use {
+    crate::{
+        audit, build_config, bundle, config::StakePoolConfig, lease, manager::fee_schedule,
+        monitor::transient, offline, staker::validator_set,
+    },
+    anyhow::Result,
+    solana_pubkey::Pubkey,
+    std::str::FromStr,
+};
+
+const USAGE: &str = "\
+Usage: stake-pool-cranker [COMMAND]
+
+Without a command the cranker runs the worker and HTTP API.
+
+Commands:
+  validators plan              Show the changes needed to match VALIDATOR_ALLOWLIST_FILE
+  validators apply [--dry-run] Add and remove validators to match VALIDATOR_ALLOWLIST_FILE
+  audit                        Cross-check pool totals and stake accounts with the validator list
+  transient check              Find transient stake accounts that do not match the validator list
+  transient cleanup [--dry-run]
+                               Re-run the validator list update for the mismatched validators
+  fees show                    Show the current and scheduled fees of every pool
+  fees set <pool> <fee> <value> [--dry-run]
+                               Change a fee with the manager key. <fee> is one of epoch,
+                               stake-withdrawal, sol-withdrawal, stake-deposit, sol-deposit,
+                               stake-referral or sol-referral. <value> is <numerator>/<denominator>,
+                               or a percentage for referral fees
+  update sign-only <pool>      Sign the next update transaction of <pool> on NONCE_ACCOUNT
+                               and print it base64 encoded instead of sending it
+  submit <transaction>         Send a transaction printed by update sign-only and wait for
+                               it to confirm
+  bundle mock-server [<port>]  Serve a local block engine on <port> (default 8001) that
+                               forwards bundled transactions to RPC_URL one by one
+  lease mock-server [<port>]   Serve an in-memory lease server on <port> (default 8002)
+                               for LEASE=http";
+
+/// Run a one-off command given on the command line.
+pub async fn run(config: &StakePoolConfig, args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["validators", "plan"] => reconcile_validators(config, false, true).await,
+        ["validators", "apply"] => reconcile_validators(config, true, config.staker_dry_run).await,
+        ["validators", "apply", "--dry-run"] => reconcile_validators(config, true, true).await,
+        ["audit"] => audit_pools(config).await,
+        ["transient", "check"] => check_transient(config, false, false).await,
+        ["transient", "cleanup"] => check_transient(config, true, false).await,
+        ["transient", "cleanup", "--dry-run"] => check_transient(config, true, true).await,
+        ["fees", "show"] => show_fees(config).await,
+        ["fees", "set", pool, fee, value] => set_fee(config, pool, fee, value, false).await,
+        ["fees", "set", pool, fee, value, "--dry-run"] => {
+            set_fee(config, pool, fee, value, true).await
+        }
+        ["update", "sign-only", pool] => sign_update(config, pool).await,
+        ["submit", transaction] => submit(config, transaction).await,
+        ["bundle", "mock-server"] => bundle::run_mock_server(&config.rpc_url, 8001).await,
+        ["bundle", "mock-server", port] => {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| anyhow::anyhow!("Invalid port: {}", port))?;
+            bundle::run_mock_server(&config.rpc_url, port).await
+        }
+        ["lease", "mock-server"] => lease::run_mock_server(8002).await,
+        ["lease", "mock-server", port] => {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| anyhow::anyhow!("Invalid port: {}", port))?;
+            lease::run_mock_server(port).await
+        }
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => anyhow::bail!("Unknown command: {}\n\n{}", args.join(" "), USAGE),
+    }
+}
+
+async fn reconcile_validators(config: &StakePoolConfig, apply: bool, dry_run: bool) -> Result<()> {
+    if config.validator_allowlist.is_empty() {
+        anyhow::bail!("VALIDATOR_ALLOWLIST_FILE is not set or lists no pools");
+    }
+    let pool_config = build_config(config)?;
+    for stake_pool_address in &config.stake_pool_address {
+        let Some(allowlist) = config.validator_allowlist.get(stake_pool_address) else {
+            println!("Stake pool {stake_pool_address} has no allowlist, skipping");
+            continue;
+        };
+        let stake_pool_pubkey = Pubkey::from_str(stake_pool_address)?;
+        let plan = validator_set::plan(&pool_config, &stake_pool_pubkey, allowlist).await?;
+        print!("{plan}");
+        if !apply || plan.is_empty() {
+            continue;
+        }
+        let signatures = validator_set::apply(&pool_config, &plan, dry_run).await?;
+        if dry_run {
+            println!("  dry run, all transactions simulated successfully");
+        }
+        for signature in signatures {
+            println!("  sent {signature}");
+        }
+    }
+    Ok(())
+}
+
+async fn audit_pools(config: &StakePoolConfig) -> Result<()> {
+    let pool_config = build_config(config)?;
+    let mut clean = true;
+    for stake_pool_address in &config.stake_pool_address {
+        let stake_pool_pubkey = Pubkey::from_str(stake_pool_address)?;
+        let pool_name = config
+            .pool_names
+            .get(stake_pool_address)
+            .map_or(stake_pool_address.as_str(), String::as_str);
+        let report = audit::audit_pool(&pool_config, &stake_pool_pubkey, pool_name).await?;
+        println!("{}", report.render());
+        clean &= report.is_clean();
+    }
+    if !clean {
+        anyhow::bail!("Audit found discrepancies");
+    }
+    Ok(())
+}
+
+async fn check_transient(config: &StakePoolConfig, clean_up: bool, dry_run: bool) -> Result<()> {
+    let pool_config = build_config(config)?;
+    for stake_pool_address in &config.stake_pool_address {
+        let stake_pool_pubkey = Pubkey::from_str(stake_pool_address)?;
+        let orphans = transient::find_orphans(&pool_config, &stake_pool_pubkey).await?;
+        println!("Stake pool {stake_pool_address}");
+        if orphans.is_empty() {
+            println!("  transient stake accounts match the validator list");
+            continue;
+        }
+        for orphan in &orphans {
+            println!("  {orphan}");
+        }
+        if !clean_up {
+            continue;
+        }
+        let signatures =
+            transient::clean_up(&pool_config, &stake_pool_pubkey, &orphans, dry_run).await?;
+        if dry_run {
+            println!("  dry run, all transactions simulated successfully");
+        }
+        for signature in signatures {
+            println!("  sent {signature}");
+        }
+    }
+    Ok(())
+}
+
+async fn show_fees(config: &StakePoolConfig) -> Result<()> {
+    let pool_config = build_config(config)?;
+    for stake_pool_address in &config.stake_pool_address {
+        let stake_pool_pubkey = Pubkey::from_str(stake_pool_address)?;
+        print!(
+            "{}",
+            fee_schedule::fee_schedule(&pool_config, &stake_pool_pubkey).await?
+        );
+    }
+    Ok(())
+}
+
+async fn set_fee(
+    config: &StakePoolConfig,
+    stake_pool_address: &str,
+    fee: &str,
+    value: &str,
+    dry_run: bool,
+) -> Result<()> {
+    let stake_pool_pubkey = Pubkey::from_str(stake_pool_address)?;
+    let fee_type = fee_schedule::parse_fee(fee, value)?;
+    let pool_config = build_config(config)?;
+    let signature =
+        fee_schedule::set_fee(&pool_config, &stake_pool_pubkey, fee_type, dry_run).await?;
+    match signature {
+        Some(signature) => println!("Sent {signature}"),
+        None => println!("Dry run, transaction simulated successfully"),
+    }
+    print!(
+        "{}",
+        fee_schedule::fee_schedule(&pool_config, &stake_pool_pubkey).await?
+    );
+    Ok(())
+}
+
+async fn sign_update(config: &StakePoolConfig, stake_pool_address: &str) -> Result<()> {
+    let stake_pool_pubkey = Pubkey::from_str(stake_pool_address)?;
+    let pool_config = build_config(config)?;
+    match offline::sign_next_update(&pool_config, &stake_pool_pubkey).await? {
+        Some(transaction) => println!("{transaction}"),
+        None => eprintln!("Stake pool {stake_pool_address} is already updated for this epoch"),
+    }
+    Ok(())
+}
+
+async fn submit(config: &StakePoolConfig, transaction: &str) -> Result<()> {
+    let pool_config = build_config(config)?;
+    let signature = offline::submit(&pool_config, transaction).await?;
+    println!("Sent {signature}");
+    Ok(())
+}
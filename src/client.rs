@@ -4,7 +4,10 @@ use {
     solana_hash::Hash,
     solana_instruction::Instruction,
     solana_message::Message,
-    solana_program::borsh1::try_from_slice_unchecked,
+    solana_program::{
+        borsh1::try_from_slice_unchecked,
+        nonce::state::{State, Versions},
+    },
     solana_pubkey::Pubkey,
     solana_rpc_client::nonblocking::rpc_client::RpcClient,
     solana_transaction::Transaction,
@@ -43,6 +46,36 @@ pub async fn get_validator_list(
     Ok(validator_list)
 }
 
+/// The blockhash stored in a durable nonce account, checked against the
+/// authority that will advance it.
+pub async fn get_nonce_blockhash(
+    rpc_client: &RpcClient,
+    nonce_account: &Pubkey,
+    authority: &Pubkey,
+) -> Result<Hash> {
+    let account_data = rpc_client
+        .get_account_data(nonce_account)
+        .await
+        .context(format!(
+            "Failed to get account data for nonce account: {nonce_account}"
+        ))?;
+    let versions = bincode::deserialize::<Versions>(&account_data)
+        .map_err(|err| anyhow::anyhow!("Invalid nonce account {}: {}", nonce_account, err))?;
+    match versions.state() {
+        State::Initialized(data) if data.authority == *authority => Ok(data.blockhash()),
+        State::Initialized(data) => Err(anyhow::anyhow!(
+            "Nonce account {} has authority {}, expected {}",
+            nonce_account,
+            data.authority,
+            authority
+        )),
+        State::Uninitialized => Err(anyhow::anyhow!(
+            "Nonce account {} is not initialized",
+            nonce_account
+        )),
+    }
+}
+
 /// Helper function to add a compute unit limit instruction to a given set
 /// of instructions by simulating, and then propagating the result via context.
 pub(crate) async fn add_compute_unit_limit_from_simulation(
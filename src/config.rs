@@ -1,6 +1,157 @@
-use std::env;
+use std::{collections::HashMap, env, str::FromStr};
 
 use anyhow::{Context, Result};
+use solana_pubkey::Pubkey;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotifierKind {
+    #[default]
+    Slack,
+    Discord,
+    Telegram,
+    Webhook,
+    Stdout,
+}
+
+impl FromStr for NotifierKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "slack" => Ok(Self::Slack),
+            "discord" => Ok(Self::Discord),
+            "telegram" => Ok(Self::Telegram),
+            "webhook" => Ok(Self::Webhook),
+            "stdout" => Ok(Self::Stdout),
+            other => Err(anyhow::anyhow!("Unknown notifier: {}", other)),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebalanceStrategy {
+    /// Leave stake where it is
+    #[default]
+    None,
+    /// Same target for every active validator
+    Equal,
+    /// Targets proportional to `REBALANCE_WEIGHTS`
+    Weighted,
+    /// Targets proportional to vote credits earned net of commission
+    Performance,
+}
+
+impl FromStr for RebalanceStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "equal" => Ok(Self::Equal),
+            "weighted" => Ok(Self::Weighted),
+            "performance" => Ok(Self::Performance),
+            other => Err(anyhow::anyhow!("Unknown rebalance strategy: {}", other)),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelinquencyAction {
+    /// Only send alerts
+    #[default]
+    Alert,
+    /// Move the validator's stake back to the reserve
+    Decrease,
+    /// Remove the validator from the pool
+    Remove,
+}
+
+impl FromStr for DelinquencyAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "alert" => Ok(Self::Alert),
+            "decrease" => Ok(Self::Decrease),
+            "remove" => Ok(Self::Remove),
+            other => Err(anyhow::anyhow!("Unknown delinquency action: {}", other)),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeCollectionAction {
+    /// Leave the fees in the manager fee account
+    #[default]
+    None,
+    /// Redeem the pool tokens for SOL from the reserve
+    WithdrawSol,
+    /// Redeem the pool tokens for a stake account split off a validator
+    WithdrawStake,
+    /// Move the pool tokens to a treasury token account
+    Transfer,
+}
+
+impl FromStr for FeeCollectionAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "withdraw_sol" => Ok(Self::WithdrawSol),
+            "withdraw_stake" => Ok(Self::WithdrawStake),
+            "transfer" => Ok(Self::Transfer),
+            other => Err(anyhow::anyhow!("Unknown fee collection action: {}", other)),
+        }
+    }
+}
+
+/// How the final update transactions of a pool are submitted.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Submission {
+    /// `sendTransaction` to `RPC_URL`
+    #[default]
+    Rpc,
+    /// A tipped bundle sent to `BLOCK_ENGINE_URL`
+    Bundle,
+}
+
+impl FromStr for Submission {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rpc" => Ok(Self::Rpc),
+            "bundle" => Ok(Self::Bundle),
+            other => Err(anyhow::anyhow!("Unknown submission: {}", other)),
+        }
+    }
+}
+
+/// How replicas agree on the one that submits transactions.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseKind {
+    /// A single instance, always the leader
+    #[default]
+    None,
+    /// An exclusive lock on `LEASE_FILE`, for replicas on one host
+    File,
+    /// A TTL lease kept by the server at `LEASE_URL`
+    Http,
+}
+
+impl FromStr for LeaseKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "file" => Ok(Self::File),
+            "http" => Ok(Self::Http),
+            other => Err(anyhow::anyhow!("Unknown lease: {}", other)),
+        }
+    }
+}
 
 #[allow(dead_code)]
 #[derive(Default, Debug, Clone)]
@@ -8,9 +159,86 @@ pub struct StakePoolConfig {
     pub port: u16,
     pub rpc_url: String,
     pub fee_payer_private_key: String,
+    pub staker_private_key: Option<String>,
+    pub staker_dry_run: bool,
+    pub manager_private_key: Option<String>,
     pub stake_pool_address: Vec<String>,
+    pub pool_names: HashMap<String, String>,
     pub slack_token: String,
     pub slack_channel_id: String,
+    pub notifier: NotifierKind,
+    pub pool_notifiers: HashMap<String, NotifierKind>,
+    pub discord_webhook_url: String,
+    pub telegram_bot_token: String,
+    pub telegram_chat_id: String,
+    pub webhook_url: String,
+    pub template_epoch_changed: Option<String>,
+    pub template_update_succeeded: Option<String>,
+    pub template_update_failed: Option<String>,
+    pub template_rpc_failed: Option<String>,
+    pub rebalance_strategy: RebalanceStrategy,
+    pub rebalance_weights: HashMap<String, f64>,
+    pub rebalance_min_lamports: u64,
+    pub delinquency_epochs: u64,
+    pub delinquency_action: DelinquencyAction,
+    /// Re-run the validator list update for validators whose transient stake
+    /// account does not match the list
+    pub transient_cleanup: bool,
+    /// Highest commission percentage a pool validator may charge before alerting
+    pub max_commission: u8,
+    /// Give validators above `max_commission` a zero rebalance target
+    pub rebalance_avoid_high_commission: bool,
+    /// Point the preferred deposit and withdraw validators at the most
+    /// under-target and over-target validators each epoch
+    pub rotate_preferred_validators: bool,
+    /// Share of the pool's lamports to keep in the reserve for instant withdrawals
+    pub reserve_target_pct: Option<f64>,
+    /// Alert when the reserve holds less than this share of the pool
+    pub reserve_alert_pct: Option<f64>,
+    /// Move stake to or from the reserve to keep it at `reserve_target_pct`
+    pub manage_reserve: bool,
+    pub fee_collection_action: FeeCollectionAction,
+    /// Collect once the manager fee account holds at least this many pool
+    /// tokens, in the mint's smallest unit
+    pub fee_collection_min_tokens: u64,
+    /// SOL recipient, stake authority or treasury token account, depending on
+    /// the action. Withdrawals default to the manager.
+    pub fee_collection_destination: Option<Pubkey>,
+    pub fee_collection_dry_run: bool,
+    /// Bearer token for the API endpoints that send transactions, which are
+    /// disabled without it
+    pub admin_api_token: Option<String>,
+    /// Desired vote accounts per pool address, from `VALIDATOR_ALLOWLIST_FILE`
+    pub validator_allowlist: HashMap<String, Vec<String>>,
+    /// Pack update chunks into v0 transactions with a per pool lookup table
+    pub use_lookup_table: bool,
+    pub submission: Submission,
+    pub pool_submission: HashMap<String, Submission>,
+    /// Block engine base URL, required when a pool submits bundles
+    pub block_engine_url: String,
+    pub bundle_tip_lamports: u64,
+    /// Tip recipient, a random block engine tip account when unset
+    pub bundle_tip_account: Option<Pubkey>,
+    /// Durable nonce account used instead of a recent blockhash
+    pub nonce_account: Option<Pubkey>,
+    /// Nonce authority, the fee payer when unset
+    pub nonce_authority_private_key: Option<String>,
+    pub lease: LeaseKind,
+    pub lease_file: String,
+    pub lease_url: String,
+    pub lease_name: String,
+    /// Identifies this replica to the lease, hostname and process id by default
+    pub lease_holder: String,
+    pub lease_ttl_secs: u64,
+    /// How long shutdown waits for the crank in progress to finish
+    pub shutdown_timeout_secs: u64,
+    /// How often the validator metadata served by the API is refreshed
+    pub validator_info_refresh_secs: u64,
+    pub alert_dedup_window_secs: u64,
+    pub alert_escalate_after_cycles: u32,
+    pub state_file: String,
+    pub balance_warning_epochs: f64,
+    pub balance_critical_epochs: f64,
 }
 
 impl StakePoolConfig {
@@ -25,6 +253,10 @@ impl StakePoolConfig {
         let fee_payer_private_key =
             env::var("FEE_PAYER_PRIVATE_KEY").context("FEE_PAYER_PRIVATE_KEY is not set")?;
 
+        let staker_private_key = env::var("STAKER_PRIVATE_KEY").ok();
+        let staker_dry_run = env_flag("STAKER_DRY_RUN")?;
+        let manager_private_key = env::var("MANAGER_PRIVATE_KEY").ok();
+
         let stake_pool_address_str =
             env::var("STAKE_POOL_ADDRESS").context("STAKE_POOL_ADDRESS is not set")?;
         let stake_pool_address: Vec<String> = stake_pool_address_str
@@ -33,18 +265,412 @@ impl StakePoolConfig {
             .filter(|s| !s.is_empty())
             .collect();
 
-        let slack_token = env::var("SLACK_TOKEN").context("SLACK_TOKEN is not set")?;
+        // POOL_NAMES="<pool address>=<display name>,..."
+        let pool_names = match env::var("POOL_NAMES") {
+            Ok(pool_names) => parse_map(&pool_names, "POOL_NAMES")?,
+            Err(_) => HashMap::new(),
+        };
+
+        // Notifier credentials are checked when the notifiers are built, so
+        // only the backends that are actually selected need to be configured.
+        let slack_token = env::var("SLACK_TOKEN").unwrap_or_default();
+        let slack_channel_id = env::var("SLACK_CHANNEL_ID").unwrap_or_default();
+        let discord_webhook_url = env::var("DISCORD_WEBHOOK_URL").unwrap_or_default();
+        let telegram_bot_token = env::var("TELEGRAM_BOT_TOKEN").unwrap_or_default();
+        let telegram_chat_id = env::var("TELEGRAM_CHAT_ID").unwrap_or_default();
+        let webhook_url = env::var("WEBHOOK_URL").unwrap_or_default();
 
-        let slack_channel_id =
-            env::var("SLACK_CHANNEL_ID").context("SLACK_CHANNEL_ID is not set")?;
+        let notifier = match env::var("NOTIFIER") {
+            Ok(notifier) => notifier.parse::<NotifierKind>()?,
+            Err(_) => NotifierKind::Slack,
+        };
+
+        // POOL_NOTIFIERS="<pool address>=<notifier>,..."
+        let pool_notifiers = match env::var("POOL_NOTIFIERS") {
+            Ok(pool_notifiers) => parse_map(&pool_notifiers, "POOL_NOTIFIERS")?,
+            Err(_) => HashMap::new(),
+        };
+
+        // Message templates are validated when they are parsed at startup
+        let template_epoch_changed = env::var("TEMPLATE_EPOCH_CHANGED").ok();
+        let template_update_succeeded = env::var("TEMPLATE_UPDATE_SUCCEEDED").ok();
+        let template_update_failed = env::var("TEMPLATE_UPDATE_FAILED").ok();
+        let template_rpc_failed = env::var("TEMPLATE_RPC_FAILED").ok();
+
+        let rebalance_strategy = match env::var("REBALANCE_STRATEGY") {
+            Ok(strategy) => strategy.parse::<RebalanceStrategy>()?,
+            Err(_) => RebalanceStrategy::None,
+        };
+
+        // REBALANCE_WEIGHTS="<vote account>=<weight>,..."
+        let rebalance_weights = match env::var("REBALANCE_WEIGHTS") {
+            Ok(weights) => parse_map(&weights, "REBALANCE_WEIGHTS")?,
+            Err(_) => HashMap::new(),
+        };
+        if rebalance_strategy == RebalanceStrategy::Weighted && rebalance_weights.is_empty() {
+            anyhow::bail!("REBALANCE_WEIGHTS is required for the weighted rebalance strategy");
+        }
+        if rebalance_strategy != RebalanceStrategy::None && staker_private_key.is_none() {
+            anyhow::bail!("STAKER_PRIVATE_KEY is required to rebalance validator stake");
+        }
+
+        let rebalance_min_lamports = match env::var("REBALANCE_MIN_LAMPORTS") {
+            Ok(lamports) => lamports
+                .parse::<u64>()
+                .context("REBALANCE_MIN_LAMPORTS must be a number of lamports")?,
+            Err(_) => 1_000_000_000,
+        };
+
+        let delinquency_epochs = match env::var("DELINQUENCY_EPOCHS") {
+            Ok(epochs) => epochs
+                .parse::<u64>()
+                .context("DELINQUENCY_EPOCHS must be a number of epochs")?,
+            Err(_) => 3,
+        };
+
+        let delinquency_action = match env::var("DELINQUENCY_ACTION") {
+            Ok(action) => action.parse::<DelinquencyAction>()?,
+            Err(_) => DelinquencyAction::Alert,
+        };
+        if delinquency_action != DelinquencyAction::Alert && staker_private_key.is_none() {
+            anyhow::bail!(
+                "STAKER_PRIVATE_KEY is required for DELINQUENCY_ACTION={delinquency_action:?}"
+            );
+        }
+
+        let transient_cleanup = env_flag("TRANSIENT_CLEANUP")?;
+
+        let max_commission = match env::var("MAX_COMMISSION") {
+            Ok(commission) => commission
+                .parse::<u8>()
+                .ok()
+                .filter(|commission| *commission <= 100)
+                .context("MAX_COMMISSION must be a percentage between 0 and 100")?,
+            Err(_) => 10,
+        };
+        let rebalance_avoid_high_commission = env_flag("REBALANCE_AVOID_HIGH_COMMISSION")?;
+
+        let rotate_preferred_validators = env_flag("ROTATE_PREFERRED_VALIDATORS")?;
+        if rotate_preferred_validators && staker_private_key.is_none() {
+            anyhow::bail!("STAKER_PRIVATE_KEY is required to rotate preferred validators");
+        }
+
+        let reserve_target_pct = match env::var("RESERVE_TARGET_PCT") {
+            Ok(pct) => Some(parse_pct(&pct, "RESERVE_TARGET_PCT")?),
+            Err(_) => None,
+        };
+        // alert once the reserve drops to half of its target by default
+        let reserve_alert_pct = match env::var("RESERVE_ALERT_PCT") {
+            Ok(pct) => Some(parse_pct(&pct, "RESERVE_ALERT_PCT")?),
+            Err(_) => reserve_target_pct.map(|pct| pct / 2.0),
+        };
+        let manage_reserve = env_flag("MANAGE_RESERVE")?;
+        if manage_reserve {
+            if reserve_target_pct.is_none() {
+                anyhow::bail!("RESERVE_TARGET_PCT is required to manage the reserve");
+            }
+            if staker_private_key.is_none() {
+                anyhow::bail!("STAKER_PRIVATE_KEY is required to manage the reserve");
+            }
+        }
+
+        let fee_collection_action = match env::var("FEE_COLLECTION_ACTION") {
+            Ok(action) => action.parse::<FeeCollectionAction>()?,
+            Err(_) => FeeCollectionAction::None,
+        };
+        let fee_collection_min_tokens = match env::var("FEE_COLLECTION_MIN_TOKENS") {
+            Ok(tokens) => tokens
+                .parse::<u64>()
+                .context("FEE_COLLECTION_MIN_TOKENS must be an amount of pool tokens")?,
+            Err(_) => 1_000_000_000,
+        };
+        let fee_collection_destination = env::var("FEE_COLLECTION_DESTINATION")
+            .ok()
+            .filter(|address| !address.is_empty())
+            .map(|address| Pubkey::from_str(&address))
+            .transpose()
+            .context("FEE_COLLECTION_DESTINATION must be a valid address")?;
+        let fee_collection_dry_run = env_flag("FEE_COLLECTION_DRY_RUN")?;
+        if fee_collection_action != FeeCollectionAction::None && manager_private_key.is_none() {
+            anyhow::bail!("MANAGER_PRIVATE_KEY is required to collect manager fees");
+        }
+        if fee_collection_action == FeeCollectionAction::Transfer
+            && fee_collection_destination.is_none()
+        {
+            anyhow::bail!("FEE_COLLECTION_DESTINATION is required to transfer manager fees");
+        }
+
+        let admin_api_token = env::var("ADMIN_API_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+
+        // VALIDATOR_ALLOWLIST_FILE points at {"<pool address>": ["<vote account>", ...]}
+        let validator_allowlist = match env::var("VALIDATOR_ALLOWLIST_FILE") {
+            Ok(path) => {
+                let contents = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read VALIDATOR_ALLOWLIST_FILE {path}"))?;
+                serde_json::from_str(&contents)
+                    .with_context(|| format!("Invalid VALIDATOR_ALLOWLIST_FILE {path}"))?
+            }
+            Err(_) => HashMap::new(),
+        };
+
+        let use_lookup_table = env_flag("USE_LOOKUP_TABLE")?;
+
+        let submission = match env::var("SUBMISSION") {
+            Ok(submission) => submission.parse::<Submission>()?,
+            Err(_) => Submission::Rpc,
+        };
+        // POOL_SUBMISSION="<pool address>=<submission>,..."
+        let pool_submission = match env::var("POOL_SUBMISSION") {
+            Ok(pool_submission) => parse_map(&pool_submission, "POOL_SUBMISSION")?,
+            Err(_) => HashMap::new(),
+        };
+        let block_engine_url = env::var("BLOCK_ENGINE_URL").unwrap_or_default();
+        let uses_bundles = stake_pool_address.iter().any(|address| {
+            pool_submission.get(address).copied().unwrap_or(submission) == Submission::Bundle
+        });
+        if uses_bundles && block_engine_url.is_empty() {
+            anyhow::bail!("BLOCK_ENGINE_URL is required to submit bundles");
+        }
+        let bundle_tip_lamports = match env::var("BUNDLE_TIP_LAMPORTS") {
+            Ok(lamports) => lamports
+                .parse::<u64>()
+                .context("BUNDLE_TIP_LAMPORTS must be a number of lamports")?,
+            Err(_) => 10_000,
+        };
+        let bundle_tip_account = env::var("BUNDLE_TIP_ACCOUNT")
+            .ok()
+            .filter(|account| !account.is_empty())
+            .map(|account| Pubkey::from_str(&account))
+            .transpose()
+            .context("BUNDLE_TIP_ACCOUNT must be a valid address")?;
+
+        let nonce_account = env::var("NONCE_ACCOUNT")
+            .ok()
+            .filter(|account| !account.is_empty())
+            .map(|account| Pubkey::from_str(&account))
+            .transpose()
+            .context("NONCE_ACCOUNT must be a valid address")?;
+        let nonce_authority_private_key = env::var("NONCE_AUTHORITY_PRIVATE_KEY")
+            .ok()
+            .filter(|key| !key.is_empty());
+        // every transaction in a bundle would need the same nonce
+        if nonce_account.is_some() && uses_bundles {
+            anyhow::bail!("NONCE_ACCOUNT cannot be combined with bundle submission");
+        }
+        // v0 update chunks are compiled without the nonce
+        if nonce_account.is_some() && use_lookup_table {
+            anyhow::bail!("NONCE_ACCOUNT cannot be combined with USE_LOOKUP_TABLE");
+        }
+
+        let lease = match env::var("LEASE") {
+            Ok(lease) => lease.parse::<LeaseKind>()?,
+            Err(_) => LeaseKind::None,
+        };
+        let lease_file = env::var("LEASE_FILE").unwrap_or_else(|_| "cranker.lock".to_string());
+        let lease_url = env::var("LEASE_URL").unwrap_or_default();
+        if lease == LeaseKind::Http && lease_url.is_empty() {
+            anyhow::bail!("LEASE_URL is required for the http lease");
+        }
+        let lease_name =
+            env::var("LEASE_NAME").unwrap_or_else(|_| "stake-pool-cranker".to_string());
+        let lease_holder = env::var("LEASE_HOLDER").unwrap_or_else(|_| {
+            format!(
+                "{}-{}",
+                env::var("HOSTNAME").unwrap_or_else(|_| "cranker".to_string()),
+                std::process::id()
+            )
+        });
+        let lease_ttl_secs = match env::var("LEASE_TTL_SECS") {
+            Ok(secs) => secs
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs >= 3)
+                .context("LEASE_TTL_SECS must be at least 3 seconds")?,
+            Err(_) => 60,
+        };
+
+        let shutdown_timeout_secs = match env::var("SHUTDOWN_TIMEOUT_SECS") {
+            Ok(secs) => secs
+                .parse::<u64>()
+                .context("SHUTDOWN_TIMEOUT_SECS must be a number of seconds")?,
+            Err(_) => 5 * 60,
+        };
+
+        let validator_info_refresh_secs = match env::var("VALIDATOR_INFO_REFRESH_SECS") {
+            Ok(secs) => secs
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .context("VALIDATOR_INFO_REFRESH_SECS must be a positive number of seconds")?,
+            Err(_) => 10 * 60,
+        };
+
+        let alert_dedup_window_secs = match env::var("ALERT_DEDUP_WINDOW_SECS") {
+            Ok(secs) => secs
+                .parse::<u64>()
+                .context("ALERT_DEDUP_WINDOW_SECS must be a number of seconds")?,
+            Err(_) => 6 * 60 * 60,
+        };
+
+        let alert_escalate_after_cycles = match env::var("ALERT_ESCALATE_AFTER_CYCLES") {
+            Ok(cycles) => cycles
+                .parse::<u32>()
+                .context("ALERT_ESCALATE_AFTER_CYCLES must be a number")?,
+            Err(_) => 3,
+        };
+
+        let state_file =
+            env::var("STATE_FILE").unwrap_or_else(|_| "cranker-state.json".to_string());
+
+        let balance_warning_epochs = match env::var("BALANCE_WARNING_EPOCHS") {
+            Ok(epochs) => parse_epochs(&epochs, "BALANCE_WARNING_EPOCHS")?,
+            Err(_) => 10.0,
+        };
+
+        let balance_critical_epochs = match env::var("BALANCE_CRITICAL_EPOCHS") {
+            Ok(epochs) => parse_epochs(&epochs, "BALANCE_CRITICAL_EPOCHS")?,
+            Err(_) => 3.0,
+        };
+
+        if balance_critical_epochs > balance_warning_epochs {
+            anyhow::bail!(
+                "BALANCE_CRITICAL_EPOCHS ({}) must not exceed BALANCE_WARNING_EPOCHS ({})",
+                balance_critical_epochs,
+                balance_warning_epochs
+            );
+        }
 
         Ok(Self {
             port,
             rpc_url,
             fee_payer_private_key,
+            staker_private_key,
+            staker_dry_run,
+            manager_private_key,
             stake_pool_address,
+            pool_names,
             slack_token,
             slack_channel_id,
+            notifier,
+            pool_notifiers,
+            discord_webhook_url,
+            telegram_bot_token,
+            telegram_chat_id,
+            webhook_url,
+            template_epoch_changed,
+            template_update_succeeded,
+            template_update_failed,
+            template_rpc_failed,
+            rebalance_strategy,
+            rebalance_weights,
+            rebalance_min_lamports,
+            delinquency_epochs,
+            delinquency_action,
+            transient_cleanup,
+            max_commission,
+            rebalance_avoid_high_commission,
+            rotate_preferred_validators,
+            reserve_target_pct,
+            reserve_alert_pct,
+            manage_reserve,
+            fee_collection_action,
+            fee_collection_min_tokens,
+            fee_collection_destination,
+            fee_collection_dry_run,
+            admin_api_token,
+            validator_allowlist,
+            use_lookup_table,
+            submission,
+            pool_submission,
+            block_engine_url,
+            bundle_tip_lamports,
+            bundle_tip_account,
+            nonce_account,
+            nonce_authority_private_key,
+            lease,
+            lease_file,
+            lease_url,
+            lease_name,
+            lease_holder,
+            lease_ttl_secs,
+            shutdown_timeout_secs,
+            validator_info_refresh_secs,
+            alert_dedup_window_secs,
+            alert_escalate_after_cycles,
+            state_file,
+            balance_warning_epochs,
+            balance_critical_epochs,
         })
     }
 }
+
+/// Read a `true`/`false` flag, defaulting to `false` when unset.
+fn env_flag(name: &str) -> Result<bool> {
+    match env::var(name) {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(true),
+            "false" | "0" | "no" | "" => Ok(false),
+            other => Err(anyhow::anyhow!(
+                "{} must be true or false, got {}",
+                name,
+                other
+            )),
+        },
+        Err(_) => Ok(false),
+    }
+}
+
+/// Parse a `<key>=<value>,...` list, such as pool address overrides, into a map.
+pub(crate) fn parse_map<T>(value: &str, name: &str) -> Result<HashMap<String, T>>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (key, value) = entry
+                .split_once('=')
+                .with_context(|| format!("{name} entry {entry:?} must be <key>=<value>"))?;
+            let value = value
+                .trim()
+                .parse::<T>()
+                .map_err(|err| anyhow::anyhow!("Invalid {} entry {:?}: {}", name, entry, err))?;
+            Ok((key.trim().to_string(), value))
+        })
+        .collect()
+}
+
+fn parse_pct(value: &str, name: &str) -> Result<f64> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|pct| (0.0..=100.0).contains(pct))
+        .with_context(|| format!("{name} must be a percentage between 0 and 100"))
+}
+
+/// Parse a runway threshold in epochs, which the forecast divides against.
+fn parse_epochs(value: &str, name: &str) -> Result<f64> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|epochs| epochs.is_finite() && *epochs > 0.0)
+        .with_context(|| format!("{name} must be a positive number of epochs"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epochs_must_be_finite_and_positive() {
+        assert_eq!(parse_epochs("2.5", "EPOCHS").unwrap(), 2.5);
+        for value in ["NaN", "inf", "-1", "0", "-0", "ten", ""] {
+            assert!(parse_epochs(value, "EPOCHS").is_err(), "{value}");
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use {
+    crate::{
+        config::{LeaseKind, StakePoolConfig},
+        notifier::{BoxFuture, Notifier, notify},
+    },
+    actix_web::{App, HttpResponse, HttpServer, delete, post, web},
+    anyhow::{Context, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        fs::{File, OpenOptions, TryLockError},
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    tokio::{
+        sync::Notify,
+        time::{Duration, Instant, interval},
+    },
+};
+
+/// Decides which replica submits transactions.
+pub trait Lease: Send + Sync {
+    /// Acquire the lease or renew it, returning whether this instance holds it.
+    fn acquire(&self) -> BoxFuture<'_, Result<bool>>;
+    /// Give the lease up so another replica can take over without waiting
+    /// for it to expire.
+    fn release(&self) -> BoxFuture<'_, Result<()>>;
+}
+
+/// Used without `LEASE`, a single instance is always the leader.
+pub struct NoLease;
+
+impl Lease for NoLease {
+    fn acquire(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async { Ok(true) })
+    }
+
+    fn release(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// An exclusive OS lock on `LEASE_FILE`, for replicas on one host. The lock
+/// is held until it is released on shutdown, or by the OS when the process
+/// exits.
+pub struct FileLease {
+    path: String,
+    file: Mutex<Option<File>>,
+}
+
+impl Lease for FileLease {
+    fn acquire(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move {
+            let mut held = self.file.lock().expect("lease file lock poisoned");
+            if held.is_some() {
+                return Ok(true);
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&self.path)
+                .with_context(|| format!("Failed to open lease file {}", self.path))?;
+            match file.try_lock() {
+                Ok(()) => {
+                    *held = Some(file);
+                    Ok(true)
+                }
+                Err(TryLockError::WouldBlock) => Ok(false),
+                Err(TryLockError::Error(err)) => {
+                    Err(err).with_context(|| format!("Failed to lock lease file {}", self.path))
+                }
+            }
+        })
+    }
+
+    fn release(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let held = self.file.lock().expect("lease file lock poisoned").take();
+            if let Some(file) = held {
+                file.unlock()
+                    .with_context(|| format!("Failed to unlock lease file {}", self.path))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// The lease as stored by a lease server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LeaseRecord {
+    pub holder: String,
+    /// Unix timestamp after which another holder may take the lease
+    pub expires_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LeaseRequest {
+    holder: String,
+    #[serde(default)]
+    ttl_secs: u64,
+}
+
+/// A TTL lease kept by a server at `LEASE_URL`, shared by replicas on any
+/// host. The server grants the lease when it is free, expired or already
+/// held by the caller, like `SET NX PX` in redis.
+pub struct HttpLease {
+    client: reqwest::Client,
+    url: String,
+    holder: String,
+    ttl_secs: u64,
+}
+
+impl Lease for HttpLease {
+    fn acquire(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move {
+            let record: LeaseRecord = self
+                .client
+                .post(&self.url)
+                .json(&LeaseRequest {
+                    holder: self.holder.clone(),
+                    ttl_secs: self.ttl_secs,
+                })
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach lease server {}", self.url))?
+                .error_for_status()?
+                .json()
+                .await
+                .context("Invalid lease server response")?;
+            Ok(record.holder == self.holder)
+        })
+    }
+
+    fn release(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            self.client
+                .delete(&self.url)
+                .json(&LeaseRequest {
+                    holder: self.holder.clone(),
+                    ttl_secs: 0,
+                })
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach lease server {}", self.url))?
+                .error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+/// Longest a lease server request may take. Renewals run every third of the
+/// TTL, so a hanging request fails before the next one is due.
+fn request_timeout(ttl_secs: u64) -> Duration {
+    Duration::from_secs(ttl_secs) / 4
+}
+
+pub fn from_config(config: &StakePoolConfig) -> Result<Arc<dyn Lease>> {
+    Ok(match config.lease {
+        LeaseKind::None => Arc::new(NoLease),
+        LeaseKind::File => Arc::new(FileLease {
+            path: config.lease_file.clone(),
+            file: Mutex::new(None),
+        }),
+        LeaseKind::Http => Arc::new(HttpLease {
+            client: reqwest::Client::builder()
+                .timeout(request_timeout(config.lease_ttl_secs))
+                .build()
+                .context("Failed to build the lease server client")?,
+            url: format!(
+                "{}/leases/{}",
+                config.lease_url.trim_end_matches('/'),
+                config.lease_name
+            ),
+            holder: config.lease_holder.clone(),
+            ttl_secs: config.lease_ttl_secs,
+        }),
+    })
+}
+
+/// Whether this instance currently holds the lease, shared by the lease
+/// task, the worker and the HTTP server.
+#[derive(Clone)]
+pub struct Leadership {
+    leader: Arc<AtomicBool>,
+    /// When the last successful acquire was sent
+    acquired_at: Arc<Mutex<Option<Instant>>>,
+    /// How long an acquire holds a lease that expires, `None` otherwise
+    ttl: Option<Duration>,
+    changed: Arc<Notify>,
+}
+
+impl Leadership {
+    pub fn new(leader: bool, ttl: Option<Duration>) -> Self {
+        Self {
+            leader: Arc::new(AtomicBool::new(leader)),
+            acquired_at: Arc::new(Mutex::new(None)),
+            ttl,
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Whether this instance holds the lease. An expiring lease that was not
+    /// renewed within its TTL may already belong to another replica, even if
+    /// the renewal has not failed yet.
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::SeqCst)
+            && self.ttl.is_none_or(|ttl| {
+                self.acquired_at
+                    .lock()
+                    .expect("leadership lock poisoned")
+                    .is_some_and(|acquired_at| acquired_at.elapsed() < ttl)
+            })
+    }
+
+    /// Record a successful acquire sent at `at`.
+    fn renewed(&self, at: Instant) {
+        *self.acquired_at.lock().expect("leadership lock poisoned") = Some(at);
+    }
+
+    /// Resolves at the next change of leader, so the worker takes over
+    /// without waiting for its next tick.
+    pub async fn changed(&self) {
+        self.changed.notified().await
+    }
+
+    /// Record the new state and return whether it changed.
+    fn set(&self, leader: bool) -> bool {
+        let changed = self.leader.swap(leader, Ordering::SeqCst) != leader;
+        if changed {
+            self.changed.notify_waiters();
+        }
+        changed
+    }
+}
+
+/// Acquire or renew the lease once and announce a change of leader. An
+/// instance that cannot reach the lease steps down, since the lease may
+/// expire meanwhile.
+pub async fn renew(
+    lease: &dyn Lease,
+    leadership: &Leadership,
+    notifier: &dyn Notifier,
+    holder: &str,
+) {
+    // the server starts the TTL when it handles the request, at the earliest
+    // when it was sent
+    let sent_at = Instant::now();
+    let leader = match lease.acquire().await {
+        Ok(leader) => leader,
+        Err(err) => {
+            tracing::error!("Failed to renew the lease: {:#?}", err);
+            false
+        }
+    };
+    if leader {
+        leadership.renewed(sent_at);
+    }
+    if leadership.set(leader) {
+        let message = if leader {
+            format!("Cranker {holder} is now the leader and submits transactions")
+        } else {
+            format!("Cranker {holder} is on standby, another replica holds the lease")
+        };
+        tracing::info!("{}", message);
+        notify(notifier, &message).await;
+    }
+}
+
+/// Renew the lease every third of its TTL.
+pub async fn run(
+    lease: Arc<dyn Lease>,
+    leadership: Leadership,
+    notifier: &dyn Notifier,
+    holder: &str,
+    ttl_secs: u64,
+) {
+    let mut ticker = interval(Duration::from_secs((ttl_secs / 3).max(1)));
+    loop {
+        ticker.tick().await;
+        renew(lease.as_ref(), &leadership, notifier, holder).await;
+    }
+}
+
+/// Step down and give the lease up on shutdown.
+pub async fn release(lease: &dyn Lease, leadership: &Leadership, holder: &str) {
+    leadership.set(false);
+    match lease.release().await {
+        Ok(()) => tracing::info!("Cranker {} released the lease", holder),
+        Err(err) => tracing::error!("Failed to release the lease: {:#?}", err),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+type Leases = Mutex<HashMap<String, LeaseRecord>>;
+
+#[post("/leases/{name}")]
+async fn mock_acquire(
+    leases: web::Data<Leases>,
+    name: web::Path<String>,
+    request: web::Json<LeaseRequest>,
+) -> HttpResponse {
+    let now = now();
+    let mut leases = leases.lock().expect("lease map lock poisoned");
+    let record = leases
+        .entry(name.into_inner())
+        .or_insert_with(|| LeaseRecord {
+            holder: request.holder.clone(),
+            expires_at: 0,
+        });
+    if record.holder == request.holder || record.expires_at <= now {
+        record.holder = request.holder.clone();
+        record.expires_at = now + request.ttl_secs;
+    }
+    HttpResponse::Ok().json(record.clone())
+}
+
+#[delete("/leases/{name}")]
+async fn mock_release(
+    leases: web::Data<Leases>,
+    name: web::Path<String>,
+    request: web::Json<LeaseRequest>,
+) -> HttpResponse {
+    let mut leases = leases.lock().expect("lease map lock poisoned");
+    if leases
+        .get(name.as_str())
+        .is_some_and(|record| record.holder == request.holder)
+    {
+        leases.remove(name.as_str());
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// Serve an in-memory lease server on `port`, a local stand-in for a shared
+/// store when trying out `LEASE=http`.
+pub async fn run_mock_server(port: u16) -> Result<()> {
+    let leases = web::Data::new(Leases::default());
+    tracing::info!("Mock lease server listening on http://127.0.0.1:{}", port);
+    HttpServer::new(move || {
+        App::new()
+            .app_data(leases.clone())
+            .service(mock_acquire)
+            .service(mock_release)
+    })
+    .bind(("127.0.0.1", port))?
+    .run()
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        actix_web::{
+            http::StatusCode,
+            test::{TestRequest, call_and_read_body_json, call_service, init_service},
+        },
+    };
+
+    fn lease_file() -> String {
+        let path = std::env::temp_dir().join(format!(
+            "cranker-lease-{}-{:?}.lock",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        path.to_string_lossy().into_owned()
+    }
+
+    fn file_lease(path: &str) -> FileLease {
+        FileLease {
+            path: path.to_string(),
+            file: Mutex::new(None),
+        }
+    }
+
+    #[tokio::test]
+    async fn file_lease_is_exclusive_until_released() {
+        let path = lease_file();
+        let (first, second) = (file_lease(&path), file_lease(&path));
+        assert!(first.acquire().await.unwrap());
+        assert!(first.acquire().await.unwrap());
+        assert!(!second.acquire().await.unwrap());
+
+        first.release().await.unwrap();
+        assert!(second.acquire().await.unwrap());
+        assert!(!first.acquire().await.unwrap());
+        second.release().await.unwrap();
+        second.release().await.unwrap();
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn requests_time_out_before_the_next_renewal() {
+        for ttl_secs in [3, 60, 600] {
+            assert!(request_timeout(ttl_secs) < Duration::from_secs(ttl_secs) / 3);
+        }
+    }
+
+    #[test]
+    fn leadership_expires_without_a_renewal() {
+        let ttl = Duration::from_secs(60);
+        let leadership = Leadership::new(false, Some(ttl));
+        leadership.set(true);
+        assert!(!leadership.is_leader());
+
+        leadership.renewed(Instant::now());
+        assert!(leadership.is_leader());
+        leadership.renewed(Instant::now() - ttl);
+        assert!(!leadership.is_leader());
+
+        leadership.renewed(Instant::now());
+        leadership.set(false);
+        assert!(!leadership.is_leader());
+    }
+
+    #[test]
+    fn leadership_without_a_ttl_follows_the_flag() {
+        let leadership = Leadership::new(true, None);
+        assert!(leadership.is_leader());
+        leadership.set(false);
+        assert!(!leadership.is_leader());
+    }
+
+    fn acquire_request(holder: &str, ttl_secs: u64) -> TestRequest {
+        TestRequest::post()
+            .uri("/leases/cranker")
+            .set_json(LeaseRequest {
+                holder: holder.to_string(),
+                ttl_secs,
+            })
+    }
+
+    fn release_request(holder: &str) -> TestRequest {
+        TestRequest::delete()
+            .uri("/leases/cranker")
+            .set_json(LeaseRequest {
+                holder: holder.to_string(),
+                ttl_secs: 0,
+            })
+    }
+
+    #[actix_web::test]
+    async fn mock_server_grants_the_lease_to_one_holder() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(Leases::default()))
+                .service(mock_acquire)
+                .service(mock_release),
+        )
+        .await;
+
+        let record: LeaseRecord =
+            call_and_read_body_json(&app, acquire_request("a", 60).to_request()).await;
+        assert_eq!(record.holder, "a");
+        let renewed: LeaseRecord =
+            call_and_read_body_json(&app, acquire_request("a", 60).to_request()).await;
+        assert!(renewed.expires_at >= record.expires_at);
+        let record: LeaseRecord =
+            call_and_read_body_json(&app, acquire_request("b", 60).to_request()).await;
+        assert_eq!(record.holder, "a");
+
+        // only the holder can release the lease
+        let response = call_service(&app, release_request("b").to_request()).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let record: LeaseRecord =
+            call_and_read_body_json(&app, acquire_request("b", 60).to_request()).await;
+        assert_eq!(record.holder, "a");
+
+        call_service(&app, release_request("a").to_request()).await;
+        let record: LeaseRecord =
+            call_and_read_body_json(&app, acquire_request("b", 60).to_request()).await;
+        assert_eq!(record.holder, "b");
+    }
+
+    #[actix_web::test]
+    async fn mock_server_hands_over_an_expired_lease() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(Leases::default()))
+                .service(mock_acquire)
+                .service(mock_release),
+        )
+        .await;
+
+        let record: LeaseRecord =
+            call_and_read_body_json(&app, acquire_request("a", 0).to_request()).await;
+        assert_eq!(record.holder, "a");
+        let record: LeaseRecord =
+            call_and_read_body_json(&app, acquire_request("b", 60).to_request()).await;
+        assert_eq!(record.holder, "b");
+        assert!(record.expires_at > now());
+    }
+}
@@ -0,0 +1,479 @@
+use {
+    crate::{
+        AppState, ComputeUnitLimit, Config, UpdateOutcome,
+        alerts::Severity,
+        check_fee_payer_balance, checked_transaction_with_signers,
+        client::{get_stake_pool, get_validator_list},
+        current_chunks, get_latest_blockhash,
+        notifier::Notifier,
+        send_transaction, send_transaction_no_wait,
+        utils::compute_budget::ComputeBudgetInstruction,
+        wait_between_chunks,
+    },
+    anyhow::{Context, Result},
+    solana_commitment_config::CommitmentConfig,
+    solana_hash::Hash,
+    solana_instruction::Instruction,
+    solana_message::{AddressLookupTableAccount, VersionedMessage, v0},
+    solana_program::address_lookup_table::{
+        instruction::{create_lookup_table, extend_lookup_table},
+        state::AddressLookupTable,
+    },
+    solana_pubkey::Pubkey,
+    solana_signer::Signer,
+    solana_transaction::versioned::VersionedTransaction,
+    std::{collections::HashSet, ops::Range, str::FromStr},
+    tokio::time::{Duration, sleep},
+};
+
+/// Most addresses a lookup table can hold
+const MAX_ADDRESSES: usize = 256;
+
+/// Addresses added per extend transaction, keeping it under the packet size
+const EXTEND_BATCH: usize = 20;
+
+/// Largest serialized transaction the cluster accepts
+const MAX_TRANSACTION_SIZE: usize = 1232;
+
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Chunks per transaction when the compute unit limit is not static
+const DEFAULT_CHUNKS_PER_TRANSACTION: usize = 4;
+
+/// Return the pool's lookup table, creating it on the first crank and
+/// extending it with every account the update instructions reference that
+/// it does not hold yet. The address is kept in the state file. A full
+/// table raises an alert, the accounts it cannot hold are sent in full.
+pub async fn prepare(
+    config: &Config,
+    state: &AppState,
+    notifier: &dyn Notifier,
+    stake_pool_address: &Pubkey,
+    pool_name: &str,
+) -> Result<AddressLookupTableAccount> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let (update_list_instructions, final_instructions) =
+        spl_stake_pool::instruction::update_stake_pool(
+            &config.stake_pool_program_id,
+            &stake_pool,
+            &validator_list,
+            stake_pool_address,
+            false,
+        );
+    let fee_payer = config.fee_payer.pubkey();
+    let mut seen = HashSet::new();
+    let wanted: Vec<Pubkey> = update_list_instructions
+        .iter()
+        .chain(&final_instructions)
+        .flat_map(|instruction| {
+            std::iter::once(instruction.program_id)
+                .chain(instruction.accounts.iter().map(|account| account.pubkey))
+        })
+        .filter(|address| *address != fee_payer && seen.insert(*address))
+        .collect();
+
+    let stored = state
+        .store
+        .read(|data| {
+            data.lookup_tables
+                .get(&stake_pool_address.to_string())
+                .cloned()
+        })
+        .await
+        .map(|address| Pubkey::from_str(&address))
+        .transpose()
+        .context("Invalid lookup table address in the state file")?;
+    let exists = match stored {
+        Some(address) => config
+            .rpc_client
+            .get_account_with_commitment(&address, config.rpc_client.commitment())
+            .await?
+            .value
+            .is_some(),
+        None => false,
+    };
+    // only a table that is gone is replaced, an rpc error fails the crank
+    let table_address = match stored {
+        Some(address) if exists => address,
+        _ => create(config, state, stake_pool_address).await?,
+    };
+
+    let mut table = fetch(config, &table_address).await?;
+    let held: HashSet<Pubkey> = table.addresses.iter().copied().collect();
+    let room = MAX_ADDRESSES.saturating_sub(table.addresses.len());
+    let mut missing: Vec<Pubkey> = wanted
+        .into_iter()
+        .filter(|address| !held.contains(address))
+        .collect();
+    let full_alert_key = format!("lookup-table-full:{stake_pool_address}");
+    if missing.len() > room {
+        let left_out = missing.len() - room;
+        missing.truncate(room);
+        state
+            .alerts
+            .raise(
+                notifier,
+                &full_alert_key,
+                Severity::Warn,
+                &format!(
+                    "Lookup table {table_address} of stake pool {pool_name} is full, \
+                     {left_out} accounts of the update are sent without it. Deactivate and \
+                     close the table and remove it from the state file to start a new one"
+                ),
+            )
+            .await;
+    } else {
+        state
+            .alerts
+            .resolve(
+                notifier,
+                &full_alert_key,
+                &format!("Lookup table of stake pool {pool_name} holds every update account"),
+            )
+            .await;
+    }
+    if !missing.is_empty() {
+        for batch in missing.chunks(EXTEND_BATCH) {
+            let instruction =
+                extend_lookup_table(table_address, fee_payer, Some(fee_payer), batch.to_vec());
+            let (transaction, _) = checked_transaction_with_signers(
+                config,
+                &[instruction],
+                &[config.fee_payer.as_ref()],
+            )
+            .await?;
+            send_transaction(config, transaction).await?;
+        }
+        tracing::info!(
+            "Extended lookup table {} with {} addresses",
+            table_address,
+            missing.len()
+        );
+        // new addresses can only be looked up from the next slot on
+        sleep(Duration::from_secs(1)).await;
+        table = fetch(config, &table_address).await?;
+    }
+    Ok(table)
+}
+
+async fn create(config: &Config, state: &AppState, stake_pool_address: &Pubkey) -> Result<Pubkey> {
+    let fee_payer = config.fee_payer.pubkey();
+    let recent_slot = config
+        .rpc_client
+        .get_slot_with_commitment(CommitmentConfig::finalized())
+        .await?;
+    let (instruction, table_address) = create_lookup_table(fee_payer, fee_payer, recent_slot);
+    let (transaction, _) =
+        checked_transaction_with_signers(config, &[instruction], &[config.fee_payer.as_ref()])
+            .await?;
+    send_transaction(config, transaction).await?;
+    tracing::info!(
+        "Created lookup table {} for stake pool {}",
+        table_address,
+        stake_pool_address
+    );
+    state
+        .store
+        .update(|data| {
+            data.lookup_tables
+                .insert(stake_pool_address.to_string(), table_address.to_string());
+        })
+        .await?;
+    Ok(table_address)
+}
+
+async fn fetch(config: &Config, table_address: &Pubkey) -> Result<AddressLookupTableAccount> {
+    let account = config.rpc_client.get_account(table_address).await?;
+    let table = AddressLookupTable::deserialize(&account.data)
+        .map_err(|err| anyhow::anyhow!("Invalid lookup table {}: {}", table_address, err))?;
+    if table.meta.authority != Some(config.fee_payer.pubkey()) {
+        anyhow::bail!(
+            "Lookup table {} is not owned by the fee payer {}",
+            table_address,
+            config.fee_payer.pubkey()
+        );
+    }
+    Ok(AddressLookupTableAccount {
+        key: *table_address,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+/// The parts of the config that shape a packed transaction.
+struct Budget<'a> {
+    fee_payer: Pubkey,
+    compute_unit_price: Option<u64>,
+    compute_unit_limit: &'a ComputeUnitLimit,
+}
+
+impl<'a> Budget<'a> {
+    fn new(config: &'a Config) -> Self {
+        Self {
+            fee_payer: config.fee_payer.pubkey(),
+            compute_unit_price: config.compute_unit_price,
+            compute_unit_limit: &config.compute_unit_limit,
+        }
+    }
+}
+
+/// Compute budget instructions for a transaction carrying `chunks` update
+/// chunks. A static limit is sized for a single chunk.
+fn compute_budget_instructions(budget: &Budget, chunks: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    if let Some(compute_unit_price) = budget.compute_unit_price {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+            compute_unit_price,
+        ));
+    }
+    let limit = match *budget.compute_unit_limit {
+        ComputeUnitLimit::Default => None,
+        ComputeUnitLimit::Static(limit) => Some(
+            limit
+                .saturating_mul(chunks as u32)
+                .min(MAX_COMPUTE_UNIT_LIMIT),
+        ),
+        ComputeUnitLimit::Simulated => Some(MAX_COMPUTE_UNIT_LIMIT),
+    };
+    if let Some(limit) = limit {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(limit));
+    }
+    instructions
+}
+
+fn compile(
+    budget: &Budget,
+    chunks: &[Instruction],
+    table: &AddressLookupTableAccount,
+    blockhash: Hash,
+) -> Result<v0::Message> {
+    let mut instructions = chunks.to_vec();
+    instructions.extend(compute_budget_instructions(budget, chunks.len()));
+    Ok(v0::Message::try_compile(
+        &budget.fee_payer,
+        &instructions,
+        std::slice::from_ref(table),
+        blockhash,
+    )?)
+}
+
+fn fits(message: &v0::Message) -> Result<bool> {
+    // one signature and its compact length prefix
+    let size = bincode::serialized_size(&VersionedMessage::V0(message.clone()))? as usize + 1 + 64;
+    Ok(size <= MAX_TRANSACTION_SIZE)
+}
+
+/// Group `instructions` into as few v0 transactions as the transaction size
+/// and compute budget allow. The blockhash only stands in for sizing.
+fn pack(
+    budget: &Budget,
+    instructions: Vec<Instruction>,
+    table: &AddressLookupTableAccount,
+) -> Result<Vec<Vec<Instruction>>> {
+    let max_chunks = match *budget.compute_unit_limit {
+        ComputeUnitLimit::Static(limit) => (MAX_COMPUTE_UNIT_LIMIT / limit.max(1)).max(1) as usize,
+        _ => DEFAULT_CHUNKS_PER_TRANSACTION,
+    };
+    let mut batches = Vec::new();
+    let mut batch: Vec<Instruction> = Vec::new();
+    for instruction in instructions {
+        batch.push(instruction);
+        if batch.len() <= max_chunks && fits(&compile(budget, &batch, table, Hash::default())?)? {
+            continue;
+        }
+        let instruction = batch.pop().expect("batch has the instruction just pushed");
+        if !batch.is_empty() {
+            batches.push(std::mem::take(&mut batch));
+        }
+        if !fits(&compile(
+            budget,
+            std::slice::from_ref(&instruction),
+            table,
+            Hash::default(),
+        )?)? {
+            anyhow::bail!("Update instruction does not fit in a transaction");
+        }
+        batch.push(instruction);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    Ok(batches)
+}
+
+/// Send the `update_validator_list_balance` chunks packed into v0
+/// transactions that resolve the shared accounts through `table`. Like the
+/// legacy path, the validator list is read again after every wait and chunks
+/// another cranker ran meanwhile are skipped. `chunks` pairs each instruction
+/// with the validators it covers, see `current_chunks`.
+pub async fn send_update_chunks(
+    config: &Config,
+    validator_list_address: &Pubkey,
+    epoch: u64,
+    chunks: Vec<(Instruction, Option<Range<usize>>)>,
+    table: &AddressLookupTableAccount,
+    outcome: &mut UpdateOutcome,
+) -> Result<()> {
+    let budget = Budget::new(config);
+    let mut remaining = chunks;
+    loop {
+        let ranges: Vec<Option<Range<usize>>> =
+            remaining.iter().map(|(_, range)| range.clone()).collect();
+        let current = current_chunks(config, validator_list_address, &ranges, epoch).await?;
+        let mut pending = Vec::with_capacity(remaining.len());
+        for ((instruction, range), current) in remaining.into_iter().zip(current) {
+            if current {
+                outcome.chunks_skipped += 1;
+            } else {
+                pending.push((instruction, range));
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let batches = pack(
+            &budget,
+            pending
+                .iter()
+                .map(|(instruction, _)| instruction.clone())
+                .collect(),
+            table,
+        )?;
+        tracing::info!(
+            "Packed {} update chunks into {} transactions with lookup table {}",
+            pending.len(),
+            batches.len(),
+            table.key
+        );
+        let last = batches.len() == 1;
+        let Some(batch) = batches.into_iter().next() else {
+            return Ok(());
+        };
+        let chunk_count = batch.len();
+        let blockhash = get_latest_blockhash(&config.rpc_client)
+            .await
+            .context("Failed to get latest blockhash")?;
+        let message = compile(&budget, &batch, table, blockhash)?;
+        let fee = config
+            .rpc_client
+            .get_fee_for_message(&message)
+            .await
+            .context("Failed to fetch fee for transaction message")?;
+        check_fee_payer_balance(config, fee).await?;
+        let transaction = VersionedTransaction::try_new(
+            VersionedMessage::V0(message),
+            &[config.fee_payer.as_ref()],
+        )?;
+        if last {
+            let signature = send_transaction(config, transaction).await?;
+            outcome.record_chunks(signature, fee, chunk_count);
+            return Ok(());
+        }
+        // send the first ones without waiting
+        let signature = send_transaction_no_wait(config, transaction).await?;
+        outcome.record_chunks(signature, fee, chunk_count);
+        wait_between_chunks(config).await?;
+        remaining = pending.split_off(chunk_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_instruction::AccountMeta};
+
+    fn budget(compute_unit_limit: &ComputeUnitLimit) -> Budget<'_> {
+        Budget {
+            fee_payer: Pubkey::new_unique(),
+            compute_unit_price: None,
+            compute_unit_limit,
+        }
+    }
+
+    fn table(addresses: Vec<Pubkey>) -> AddressLookupTableAccount {
+        AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses,
+        }
+    }
+
+    fn instruction(program_id: Pubkey, accounts: &[Pubkey], data_len: usize) -> Instruction {
+        Instruction::new_with_bytes(
+            program_id,
+            &vec![1; data_len],
+            accounts
+                .iter()
+                .map(|account| AccountMeta::new(*account, false))
+                .collect(),
+        )
+    }
+
+    fn batch_sizes(batches: &[Vec<Instruction>]) -> Vec<usize> {
+        batches.iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn static_limit_caps_chunks_per_transaction() {
+        // 1_400_000 / 250_000 leaves room for five chunks
+        let limit = ComputeUnitLimit::Static(250_000);
+        let program_id = Pubkey::new_unique();
+        let instructions = (0..12).map(|_| instruction(program_id, &[], 8)).collect();
+        let batches = pack(&budget(&limit), instructions, &table(Vec::new())).unwrap();
+        assert_eq!(batch_sizes(&batches), [5, 5, 2]);
+    }
+
+    #[test]
+    fn transaction_size_caps_chunks_per_transaction() {
+        let limit = ComputeUnitLimit::Default;
+        let program_id = Pubkey::new_unique();
+        let instructions = (0..5).map(|_| instruction(program_id, &[], 500)).collect();
+        let batches = pack(&budget(&limit), instructions, &table(Vec::new())).unwrap();
+        assert_eq!(batch_sizes(&batches), [2, 2, 1]);
+        for batch in &batches {
+            assert!(
+                fits(
+                    &compile(&budget(&limit), batch, &table(Vec::new()), Hash::default()).unwrap()
+                )
+                .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn table_lets_more_chunks_share_a_transaction() {
+        let limit = ComputeUnitLimit::Default;
+        let program_id = Pubkey::new_unique();
+        let accounts: Vec<Pubkey> = (0..30).map(|_| Pubkey::new_unique()).collect();
+        let instructions: Vec<Instruction> = (0..4)
+            .map(|_| instruction(program_id, &accounts, 8))
+            .collect();
+
+        let without = pack(&budget(&limit), instructions.clone(), &table(Vec::new())).unwrap();
+        assert!(without.len() > 1);
+        let with = pack(&budget(&limit), instructions, &table(accounts)).unwrap();
+        assert_eq!(batch_sizes(&with), [4]);
+    }
+
+    #[test]
+    fn keeps_instruction_order() {
+        let limit = ComputeUnitLimit::Static(700_000);
+        let program_id = Pubkey::new_unique();
+        let instructions: Vec<Instruction> = (1..=5)
+            .map(|len| instruction(program_id, &[], len))
+            .collect();
+        let batches = pack(&budget(&limit), instructions, &table(Vec::new())).unwrap();
+        let lengths: Vec<usize> = batches.iter().flatten().map(|ix| ix.data.len()).collect();
+        assert_eq!(lengths, [1, 2, 3, 4, 5]);
+        assert_eq!(batch_sizes(&batches), [2, 2, 1]);
+    }
+
+    #[test]
+    fn rejects_an_instruction_too_large_for_a_transaction() {
+        let limit = ComputeUnitLimit::Default;
+        let program_id = Pubkey::new_unique();
+        let instructions = vec![
+            instruction(program_id, &[], 8),
+            instruction(program_id, &[], 1_200),
+        ];
+        assert!(pack(&budget(&limit), instructions, &table(Vec::new())).is_err());
+    }
+}
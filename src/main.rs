@@ -1,7 +1,28 @@
 #![allow(clippy::arithmetic_side_effects)]
+mod alerts;
+mod api;
+mod audit;
+mod balance;
+mod bundle;
+mod cli;
 mod client;
 mod config;
+mod lease;
+mod lookup_table;
+mod manager;
+mod monitor;
+mod notifier;
+mod offline;
+mod rates;
+mod report;
+mod rewards;
+mod shutdown;
+mod staker;
+mod store;
+mod supervisor;
+mod templates;
 mod utils;
+mod validator_info;
 
 use {
     crate::{
@@ -13,25 +34,35 @@ use {
     },
     actix_cors::Cors,
     actix_web::{App, HttpResponse, HttpServer, get, web},
+    alerts::{Alerts, Severity},
     anyhow::{Context, Result},
-    config::StakePoolConfig,
+    config::{FeeCollectionAction, LeaseKind, RebalanceStrategy, StakePoolConfig},
     dotenv::dotenv,
+    lease::Leadership,
+    notifier::Notifiers,
+    report::EpochSummary,
+    serde::Serialize,
+    shutdown::Shutdown,
     solana_commitment_config::CommitmentConfig,
     solana_epoch_info::EpochInfo,
     solana_hash::Hash,
     solana_instruction::Instruction,
     solana_keypair::Keypair,
-    solana_message::Message,
+    solana_message::{AddressLookupTableAccount, Message},
     solana_native_token::{self, Sol},
+    solana_program::system_instruction,
     solana_pubkey::Pubkey,
-    solana_rpc_client::nonblocking::rpc_client::RpcClient,
+    solana_rpc_client::{nonblocking::rpc_client::RpcClient, rpc_client::SerializableTransaction},
     solana_signer::{Signer, signers::Signers},
     solana_transaction::Transaction,
     spl_stake_pool::state::AccountType as SplAccountType,
-    std::{str::FromStr, sync::Arc},
-    tokio::time::{Duration, interval, sleep},
+    std::{collections::HashMap, ops::Range, str::FromStr, sync::Arc},
+    store::Store,
+    templates::{MessageContext, MessageTemplates},
+    tokio::time::{Duration, Instant, interval, sleep},
     tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt},
     utils::compute_budget::ComputeBudgetInstruction,
+    validator_info::{ValidatorInfoCache, ValidatorMetadata},
 };
 
 #[allow(dead_code)]
@@ -45,12 +76,101 @@ pub(crate) struct Config {
     stake_pool_program_id: Pubkey,
     rpc_client: RpcClient,
     fee_payer: Box<dyn Signer + Send + Sync + 'static>,
+    staker: Option<Box<dyn Signer + Send + Sync + 'static>>,
+    manager: Option<Box<dyn Signer + Send + Sync + 'static>>,
+    /// Block engine sender, when a pool submits bundles
+    bundles: Option<bundle::BundleSender>,
+    /// Durable nonce used instead of a recent blockhash, when configured
+    nonce: Option<DurableNonce>,
+    /// Checked before every send when running as a replica, `None` on the
+    /// command line
+    leadership: Option<Leadership>,
+    /// Cuts the wait between update chunks short, `None` on the command line
+    shutdown: Option<Shutdown>,
     dry_run: bool,
     no_update: bool,
     compute_unit_price: Option<u64>,
     compute_unit_limit: ComputeUnitLimit,
 }
 
+/// A nonce account whose stored blockhash replaces the recent blockhash.
+struct DurableNonce {
+    account: Pubkey,
+    /// Signs the `AdvanceNonceAccount` instruction of every transaction
+    authority: Box<dyn Signer + Send + Sync + 'static>,
+}
+
+/// Long-lived handles shared by every worker cycle and the HTTP server.
+#[derive(Clone)]
+pub(crate) struct AppState {
+    store: Store,
+    notifiers: Notifiers,
+    alerts: Alerts,
+    templates: MessageTemplates,
+    validator_info: ValidatorInfoCache,
+    /// Only the leader submits transactions
+    leadership: Leadership,
+    /// Set on SIGINT or SIGTERM, no new crank starts afterwards
+    shutdown: Shutdown,
+}
+
+impl AppState {
+    /// A state on an empty temp store that sends every message through
+    /// `notifier`.
+    #[cfg(any(test, feature = "dev-context-only-utils"))]
+    pub(crate) fn temp(notifier: Arc<dyn notifier::Notifier>) -> Self {
+        let store = Store::temp();
+        Self {
+            alerts: Alerts::new(store.clone(), Duration::from_secs(3600), 0),
+            store,
+            notifiers: Notifiers::single(notifier),
+            templates: MessageTemplates::defaults(),
+            validator_info: ValidatorInfoCache::default(),
+            leadership: Leadership::new(true, None),
+            shutdown: Shutdown::default(),
+        }
+    }
+}
+
+/// What a single `command_update` run did, for notifications.
+#[derive(Default, Debug)]
+pub(crate) struct UpdateOutcome {
+    validator_count: usize,
+    /// Validators that were already updated for this epoch before the crank
+    validators_already_current: usize,
+    /// `update_validator_list_balance` chunks built for the update
+    chunks_total: usize,
+    /// Chunks not sent because another cranker had already run them
+    chunks_skipped: usize,
+    /// Signatures of the transactions that carried update chunks and the
+    /// number of chunks in each, empty on a dry run
+    chunk_transactions: Vec<(String, usize)>,
+    /// Signatures of the transactions sent, empty on a dry run
+    signatures: Vec<String>,
+    /// Transactions sent that failed or were not confirmed in time
+    failed_transactions: usize,
+    /// Total fees in lamports across all transactions
+    fees: u64,
+    duration: Duration,
+    /// Validator balances read before the update, for reward attribution
+    balances_before: HashMap<String, rewards::ValidatorBalance>,
+}
+
+impl UpdateOutcome {
+    fn record(&mut self, signature: Option<String>, fee: u64) {
+        self.signatures.extend(signature);
+        self.fees = self.fees.saturating_add(fee);
+    }
+
+    /// Record a transaction carrying `chunks` update chunks.
+    fn record_chunks(&mut self, signature: Option<String>, fee: u64, chunks: usize) {
+        if let Some(signature) = &signature {
+            self.chunk_transactions.push((signature.clone(), chunks));
+        }
+        self.record(signature, fee);
+    }
+}
+
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
@@ -68,37 +188,228 @@ async fn main() -> std::io::Result<()> {
         StakePoolConfig::get_config()
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?,
     );
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&config, &args)
+            .await
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+    }
+
+    let store = Store::load(&config.state_file)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let notifiers = Notifiers::from_config(&config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let alerts = Alerts::new(
+        store.clone(),
+        Duration::from_secs(config.alert_dedup_window_secs),
+        config.alert_escalate_after_cycles,
+    );
+    let templates = MessageTemplates::from_config(&config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let state = AppState {
+        store,
+        notifiers,
+        alerts,
+        templates,
+        validator_info: ValidatorInfoCache::default(),
+        leadership: Leadership::new(
+            config.lease == LeaseKind::None,
+            (config.lease == LeaseKind::Http).then(|| Duration::from_secs(config.lease_ttl_secs)),
+        ),
+        shutdown: Shutdown::default(),
+    };
     let worker_config = config.clone();
+    let worker_state = state.clone();
     let port = config.port;
     tracing::info!("Stake pool starting on port: {}", port);
 
+    let lease = lease::from_config(&config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    // settle leadership before the first crank
+    lease::renew(
+        lease.as_ref(),
+        &state.leadership,
+        state.notifiers.global(),
+        &config.lease_holder,
+    )
+    .await;
+    let lease_config = config.clone();
+    let lease_state = state.clone();
+    let renewed_lease = lease.clone();
+    let lease_task = tokio::spawn(async move {
+        lease::run(
+            renewed_lease,
+            lease_state.leadership.clone(),
+            lease_state.notifiers.global(),
+            &lease_config.lease_holder,
+            lease_config.lease_ttl_secs,
+        )
+        .await
+    });
+
+    let info_config = config.clone();
+    let info_cache = state.validator_info.clone();
     tokio::spawn(async move {
-        let mut ticker = interval(tokio::time::Duration::from_secs(30 * 60));
+        let mut ticker = interval(Duration::from_secs(info_config.validator_info_refresh_secs));
         loop {
             ticker.tick().await;
-            if let Err(err) = set_config_and_update((*worker_config).clone()).await {
-                tracing::error!("ConfigUpdate Worker:- Error: {:#?}", err);
+            if let Err(err) = info_cache.refresh(&info_config).await {
+                tracing::error!("Failed to refresh validator metadata: {:#?}", err);
             }
         }
     });
 
-    HttpServer::new(move || {
+    let mut worker = tokio::spawn(supervisor::supervise(state.clone(), move || {
+        run_worker(worker_config.clone(), worker_state.clone())
+    }));
+
+    let server_config = config.clone();
+    let server_state = state.clone();
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(
                 Cors::default()
                     .allow_any_origin()
                     .allowed_methods(vec!["GET"]),
             )
-            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::from(server_config.clone()))
+            .app_data(web::Data::new(server_state.clone()))
             .service(get_validators)
+            .service(api::get_validator_metadata)
+            .service(api::get_summaries)
+            .service(api::get_latest_summary)
+            .service(api::get_commission_history)
+            .service(api::get_fees)
+            .service(api::get_audit)
+            .service(api::get_apy)
+            .service(api::get_rate_history)
+            .service(api::get_validator_rewards)
+            .service(api::set_fee)
+            .service(api::get_leader)
+            .service(api::get_metrics)
+            .service(api::get_worker_restarts)
     })
+    // shutdown is coordinated below instead of by actix
+    .disable_signals()
     .bind(("0.0.0.0", port))?
-    .run()
-    .await
+    .run();
+    let server_handle = server.handle();
+    let mut server = tokio::spawn(server);
+
+    tokio::select! {
+        signal = shutdown::signal() => {
+            tracing::info!("Received {}, shutting down", signal?);
+        }
+        result = &mut server => {
+            // the server only stops on its own when it fails
+            return result.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+    }
+    state.shutdown.request();
+
+    // a pool left half-updated is picked up by the next crank, so waiting is
+    // bounded to stay within the deployment's grace period
+    let timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    match tokio::time::timeout(timeout, &mut worker).await {
+        Ok(Ok(())) => tracing::info!("Worker stopped"),
+        Ok(Err(err)) => tracing::error!("Worker task failed: {:#?}", err),
+        Err(_) => {
+            tracing::warn!(
+                "The crank in progress did not finish within {:?}, stopping it",
+                timeout
+            );
+            // the lease is only released once nothing can send anymore
+            worker.abort();
+            if let Err(err) = worker.await.err().filter(|err| !err.is_cancelled()) {
+                tracing::error!("Worker task failed: {:#?}", err);
+            }
+        }
+    }
+
+    if let Err(err) = state.store.flush().await {
+        tracing::error!("Failed to persist state on shutdown: {:#?}", err);
+    }
+    lease_task.abort();
+    lease::release(lease.as_ref(), &state.leadership, &config.lease_holder).await;
+    server_handle.stop(true).await;
+    if let Err(err) = server.await {
+        tracing::error!("HTTP server task failed: {:#?}", err);
+    }
+    tracing::info!("Shutdown complete");
+    Ok(())
+}
+
+/// Crank every 30 minutes until shutdown. Runs under the supervisor, which
+/// restarts it if it panics.
+async fn run_worker(config: Arc<StakePoolConfig>, state: AppState) {
+    let mut ticker = interval(tokio::time::Duration::from_secs(30 * 60));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            // a new leader cranks right away instead of at its next tick
+            () = state.leadership.changed() => {}
+            () = state.shutdown.requested() => break,
+        }
+        if !state.leadership.is_leader() {
+            tracing::info!("Standing by, another replica holds the lease");
+            continue;
+        }
+        match set_config_and_update((*config).clone(), &state).await {
+            Ok(()) => {
+                state
+                    .alerts
+                    .resolve(
+                        state.notifiers.global(),
+                        supervisor::WORKER_ALERT_KEY,
+                        "Cranker worker completed a cycle after restarting",
+                    )
+                    .await
+            }
+            Err(err) => tracing::error!("ConfigUpdate Worker:- Error: {:#?}", err),
+        }
+    }
+}
+
+/// A validator list entry with the cached cluster metadata of its vote
+/// account as additional fields, all `null` until the metadata cache has
+/// seen the vote account.
+#[derive(Serialize)]
+struct ValidatorEntry {
+    #[serde(flatten)]
+    stake_info: ValidatorStakeInfo,
+    identity: Option<String>,
+    name: Option<String>,
+    website: Option<String>,
+    commission: Option<u8>,
+    activated_stake: Option<u64>,
+    last_vote: Option<u64>,
+}
+
+impl ValidatorEntry {
+    fn new(stake_info: ValidatorStakeInfo, metadata: Option<&ValidatorMetadata>) -> Self {
+        Self {
+            stake_info,
+            identity: metadata.map(|metadata| metadata.identity.clone()),
+            name: metadata.and_then(|metadata| metadata.name.clone()),
+            website: metadata.and_then(|metadata| metadata.website.clone()),
+            commission: metadata.map(|metadata| metadata.commission),
+            activated_stake: metadata.map(|metadata| metadata.activated_stake),
+            last_vote: metadata.map(|metadata| metadata.last_vote),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ValidatorsResponse {
+    header: ValidatorListHeader,
+    validators: Vec<ValidatorEntry>,
 }
 
 #[get("/validators")]
-async fn get_validators(config: web::Data<StakePoolConfig>) -> HttpResponse {
+async fn get_validators(
+    config: web::Data<StakePoolConfig>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
     let result = tokio::task::spawn(async move {
         if config.stake_pool_address.is_empty() {
             return Err("No stake pool addresses configured");
@@ -157,7 +468,21 @@ async fn get_validators(config: web::Data<StakePoolConfig>) -> HttpResponse {
     .await;
 
     match result {
-        Ok(Ok(validators)) => HttpResponse::Ok().json(validators),
+        Ok(Ok(validator_list)) => {
+            let (metadata, _) = state.validator_info.all().await;
+            let validators = validator_list
+                .validators
+                .into_iter()
+                .map(|stake_info| {
+                    let vote_account = stake_info.vote_account_address.to_string();
+                    ValidatorEntry::new(stake_info, metadata.get(&vote_account))
+                })
+                .collect();
+            HttpResponse::Ok().json(ValidatorsResponse {
+                header: validator_list.header,
+                validators,
+            })
+        }
         Ok(Err(msg)) => HttpResponse::InternalServerError().body(msg),
         Err(_) => HttpResponse::InternalServerError().body("Internal panic occurred"),
     }
@@ -172,49 +497,173 @@ async fn get_epoch_info(client: &RpcClient) -> Result<EpochInfo> {
     Ok(epoch_info)
 }
 
-async fn set_config_and_update(config: StakePoolConfig) -> Result<()> {
+const RPC_ALERT_KEY: &str = "rpc-epoch-info";
+
+/// Build the transaction sending [`Config`] from the environment config.
+fn build_config(config: &StakePoolConfig) -> Result<Config> {
     let fee_payer = Keypair::from_base58_string(&config.fee_payer_private_key);
-    let channel_id = config.slack_channel_id;
-    let stake_pool_addresses = config.stake_pool_address.clone();
-    let rpc_client = RpcClient::new_with_commitment(config.rpc_url, CommitmentConfig::confirmed());
+    let rpc_client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
 
     let fee_payer_box: Box<dyn Signer + Send + Sync + 'static> = Box::new(fee_payer);
+    let staker_box = config
+        .staker_private_key
+        .as_deref()
+        .map(|staker_private_key| {
+            Box::new(Keypair::from_base58_string(staker_private_key))
+                as Box<dyn Signer + Send + Sync + 'static>
+        });
+    let nonce = config.nonce_account.map(|account| {
+        let authority = match config.nonce_authority_private_key.as_deref() {
+            Some(authority_private_key) => Keypair::from_base58_string(authority_private_key),
+            None => Keypair::from_base58_string(&config.fee_payer_private_key),
+        };
+        DurableNonce {
+            account,
+            authority: Box::new(authority),
+        }
+    });
+    let manager_box = config
+        .manager_private_key
+        .as_deref()
+        .map(|manager_private_key| {
+            Box::new(Keypair::from_base58_string(manager_private_key))
+                as Box<dyn Signer + Send + Sync + 'static>
+        });
 
-    let config = Config {
+    Ok(Config {
         rpc_client: rpc_client,
         stake_pool_program_id: Pubkey::from_str("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy")?,
         fee_payer: fee_payer_box,
+        staker: staker_box,
+        manager: manager_box,
+        bundles: bundle::BundleSender::from_config(config)?,
+        nonce,
+        leadership: None,
+        shutdown: None,
         dry_run: false,
         no_update: false,
         compute_unit_limit: ComputeUnitLimit::Static(250_000),
         compute_unit_price: None,
-    };
+    })
+}
+
+async fn set_config_and_update(config: StakePoolConfig, state: &AppState) -> Result<()> {
+    let balance_warning_epochs = config.balance_warning_epochs;
+    let balance_critical_epochs = config.balance_critical_epochs;
+    let stake_pool_addresses = config.stake_pool_address.clone();
+    let pool_names = config.pool_names.clone();
+    let rebalance_strategy = config.rebalance_strategy;
+    let rebalance_weights = config.rebalance_weights.clone();
+    let rebalance_min_lamports = config.rebalance_min_lamports;
+    let staker_dry_run = config.staker_dry_run;
+    let delinquency_epochs = config.delinquency_epochs;
+    let delinquency_action = config.delinquency_action;
+    let max_commission = config.max_commission;
+    let transient_cleanup = config.transient_cleanup;
+    let rebalance_max_commission = config
+        .rebalance_avoid_high_commission
+        .then_some(config.max_commission);
+    let rotate_preferred_validators = config.rotate_preferred_validators;
+    let reserve_target_pct = config.reserve_target_pct;
+    let reserve_alert_pct = config.reserve_alert_pct;
+    let manage_reserve = config.manage_reserve;
+    let fee_collection_action = config.fee_collection_action;
+    let fee_collection_min_tokens = config.fee_collection_min_tokens;
+    let fee_collection_destination = config.fee_collection_destination;
+    let fee_collection_dry_run = config.fee_collection_dry_run;
+    let use_lookup_table = config.use_lookup_table;
+
+    let mut config = build_config(&config)?;
+    config.leadership = Some(state.leadership.clone());
+    config.shutdown = Some(state.shutdown.clone());
 
     tracing::info!("Thread is awake, checking if epoch changed...");
 
+    let cycle_started = Instant::now();
+    let mut summary_epoch = None;
+    // the epoch last read from the rpc, reused by the checks after the pools
+    let mut latest_epoch = None;
+    let mut rpc_failed = false;
+    let mut pool_summaries = Vec::new();
     for stake_pool_address_str in &stake_pool_addresses {
+        if !state.leadership.is_leader() {
+            tracing::warn!("Lost the lease, leaving the remaining pools to the new leader");
+            break;
+        }
+        if state.shutdown.is_requested() {
+            tracing::info!("Shutting down, leaving the remaining pools to the next crank");
+            break;
+        }
         let stake_pool_pubkey = Pubkey::from_str(stake_pool_address_str)?;
 
+        let notifier = state.notifiers.for_pool(stake_pool_address_str);
+        let pool_name = pool_names
+            .get(stake_pool_address_str)
+            .map_or(stake_pool_address_str.as_str(), String::as_str);
+
         let stake_pool = get_stake_pool(&config.rpc_client, &stake_pool_pubkey).await?;
         let epoch_info = match get_epoch_info(&config.rpc_client).await {
-            Ok(info) => info,
+            Ok(info) => {
+                state
+                    .alerts
+                    .resolve(
+                        notifier,
+                        RPC_ALERT_KEY,
+                        "Rpc is returning the latest epoch info again",
+                    )
+                    .await;
+                info
+            }
             Err(err) => {
                 tracing::error!("Failed with error: {:#?}", err);
-                slack_notification::send::send_message(
-                    &channel_id,
-                    "Rpc is failing to get the latest epoch info. Retrying again in 5 minutes",
-                )
-                .await
-                .context("Failed to send message on slack about rpc failure")?;
-                return Ok(());
+                let message = state.templates.rpc_failed.render(&MessageContext {
+                    pool: stake_pool_address_str,
+                    pool_name,
+                    epoch: stake_pool.last_update_epoch,
+                    outcome: None,
+                    error: Some(format!("{err:#}")),
+                });
+                state
+                    .alerts
+                    .raise(notifier, RPC_ALERT_KEY, Severity::Warn, &message)
+                    .await;
+                rpc_failed = true;
+                break;
             }
         };
+        latest_epoch = Some(epoch_info.epoch);
 
         if stake_pool.last_update_epoch == epoch_info.epoch {
             tracing::info!(
                 "Epoch has not changed for stake pool {}, skipping the update...",
                 stake_pool_address_str
             );
+            // the pool may have been updated by another cranker
+            if let Err(err) = report::record_pool_already_updated(
+                &config,
+                state,
+                &stake_pool_pubkey,
+                &stake_pool.validator_list,
+                epoch_info.epoch,
+            )
+            .await
+            {
+                tracing::error!(
+                    "Failed to record the update chunks of stake pool {}: {:#?}",
+                    stake_pool_address_str,
+                    err
+                );
+            }
+            if let Err(err) =
+                rates::record_rate(&config, state, &stake_pool_pubkey, epoch_info.epoch).await
+            {
+                tracing::error!(
+                    "Failed to record the exchange rate of stake pool {}: {:#?}",
+                    stake_pool_address_str,
+                    err
+                );
+            }
             continue;
         }
 
@@ -222,39 +671,665 @@ async fn set_config_and_update(config: StakePoolConfig) -> Result<()> {
             "Epoch changed, executing the update for stake pool {}...",
             stake_pool_address_str
         );
+        if let Err(err) =
+            report::record_pool_stale(&state.store, &stake_pool_pubkey, epoch_info.epoch).await
+        {
+            tracing::error!(
+                "Failed to record the update chunks of stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            );
+        }
+
+        let message = state.templates.epoch_changed.render(&MessageContext {
+            pool: stake_pool_address_str,
+            pool_name,
+            epoch: epoch_info.epoch,
+            outcome: None,
+            error: None,
+        });
+        state.alerts.info(notifier, &message).await;
+
+        let update_alert_key = format!("update-failed:{}", stake_pool_address_str);
+        let lookup_table_alert_key = format!("lookup-table-failed:{}", stake_pool_address_str);
+        let lookup_table = if use_lookup_table {
+            match lookup_table::prepare(&config, state, notifier, &stake_pool_pubkey, pool_name)
+                .await
+            {
+                Ok(table) => {
+                    state
+                        .alerts
+                        .resolve(
+                            notifier,
+                            &lookup_table_alert_key,
+                            &format!("Lookup table of stake pool {pool_name} is ready again"),
+                        )
+                        .await;
+                    Some(table)
+                }
+                Err(err) => {
+                    // fall back to one legacy transaction per chunk
+                    tracing::error!(
+                        "Failed to prepare the lookup table of stake pool {}: {:#?}",
+                        stake_pool_address_str,
+                        err
+                    );
+                    state
+                        .alerts
+                        .raise(
+                            notifier,
+                            &lookup_table_alert_key,
+                            Severity::Warn,
+                            &format!(
+                                "Failed to prepare the lookup table of stake pool {pool_name}, sending legacy transactions: {err:#}"
+                            ),
+                        )
+                        .await;
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut outcome = UpdateOutcome::default();
+        let error = match command_update(
+            &config,
+            &stake_pool_pubkey,
+            true,
+            false,
+            false,
+            lookup_table.as_ref(),
+            &mut outcome,
+        )
+        .await
+        {
+            Ok(()) => {
+                state
+                    .alerts
+                    .resolve(
+                        notifier,
+                        &update_alert_key,
+                        &format!("Stake pool {} updated successfully", pool_name),
+                    )
+                    .await;
+                let message = state.templates.update_succeeded.render(&MessageContext {
+                    pool: stake_pool_address_str,
+                    pool_name,
+                    epoch: epoch_info.epoch,
+                    outcome: Some(&outcome),
+                    error: None,
+                });
+                state.alerts.info(notifier, &message).await;
+                None
+            }
+            Err(err) => {
+                tracing::error!(
+                    "Failed to update stake pool {}. Failed with error: {:#?}",
+                    stake_pool_address_str,
+                    err
+                );
+                let message = state.templates.update_failed.render(&MessageContext {
+                    pool: stake_pool_address_str,
+                    pool_name,
+                    epoch: epoch_info.epoch,
+                    outcome: None,
+                    error: Some(format!("{err:#}")),
+                });
+                state
+                    .alerts
+                    .raise(notifier, &update_alert_key, Severity::Critical, &message)
+                    .await;
+                Some(format!("{err:#}"))
+            }
+        };
+
+        // stake can only be moved once every validator is updated for the epoch
+        if error.is_none() && rebalance_strategy != RebalanceStrategy::None {
+            let rebalance_alert_key = format!("rebalance-failed:{}", stake_pool_address_str);
+            match staker::rebalance::rebalance_pool(
+                &config,
+                &stake_pool_pubkey,
+                rebalance_strategy,
+                &rebalance_weights,
+                rebalance_max_commission,
+                reserve_target_pct,
+                rebalance_min_lamports,
+                staker_dry_run,
+            )
+            .await
+            {
+                Ok(actions) => {
+                    state
+                        .alerts
+                        .resolve(
+                            notifier,
+                            &rebalance_alert_key,
+                            &format!("Stake pool {pool_name} rebalanced successfully"),
+                        )
+                        .await;
+                    if !actions.is_empty() {
+                        let mut message = format!(
+                            "Rebalanced stake pool {} with the {:?} strategy{}:",
+                            pool_name,
+                            rebalance_strategy,
+                            if staker_dry_run { " (dry run)" } else { "" }
+                        );
+                        for action in &actions {
+                            message.push_str(&format!("\n- {action}"));
+                        }
+                        state.alerts.info(notifier, &message).await;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "Failed to rebalance stake pool {}: {:#?}",
+                        stake_pool_address_str,
+                        err
+                    );
+                    state
+                        .alerts
+                        .raise(
+                            notifier,
+                            &rebalance_alert_key,
+                            Severity::Warn,
+                            &format!("Failed to rebalance stake pool {pool_name}: {err:#}"),
+                        )
+                        .await;
+                }
+            }
+        }
 
-        slack_notification::send::send_message(
-            &channel_id,
-            &format!(
-                "Epoch changed, executing update for stake pool {} for epoch {}",
-                stake_pool_address_str, epoch_info.epoch
+        // the low reserve alert runs every cycle with the other monitors
+        if let Some(target_pct) = reserve_target_pct.filter(|_| error.is_none() && manage_reserve) {
+            let reserve_failed_key = format!("reserve-failed:{}", stake_pool_address_str);
+            match staker::reserve::manage_reserve(
+                &config,
+                &stake_pool_pubkey,
+                target_pct,
+                true,
+                rebalance_min_lamports,
+                staker_dry_run,
+            )
+            .await
+            {
+                Ok(status) => {
+                    state
+                        .alerts
+                        .resolve(
+                            notifier,
+                            &reserve_failed_key,
+                            &format!("Reserve of stake pool {pool_name} managed successfully"),
+                        )
+                        .await;
+                    if !status.actions.is_empty() {
+                        let mut message = format!(
+                            "Moved stake of stake pool {} towards its {}% reserve target{}:",
+                            pool_name,
+                            target_pct,
+                            if staker_dry_run { " (dry run)" } else { "" }
+                        );
+                        for action in &status.actions {
+                            message.push_str(&format!("\n- {action}"));
+                        }
+                        state.alerts.info(notifier, &message).await;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "Failed to manage the reserve of stake pool {}: {:#?}",
+                        stake_pool_address_str,
+                        err
+                    );
+                    state
+                        .alerts
+                        .raise(
+                            notifier,
+                            &reserve_failed_key,
+                            Severity::Warn,
+                            &format!(
+                                "Failed to manage the reserve of stake pool {pool_name}: {err:#}"
+                            ),
+                        )
+                        .await;
+                }
+            }
+        }
+
+        // runs after the rebalance so its transient stake counts towards the targets
+        if error.is_none() && rotate_preferred_validators {
+            let preferred_alert_key =
+                format!("preferred-validators-failed:{}", stake_pool_address_str);
+            match staker::preferred::rotate_preferred_validators(
+                &config,
+                &stake_pool_pubkey,
+                rebalance_strategy,
+                &rebalance_weights,
+                rebalance_max_commission,
+                staker_dry_run,
+            )
+            .await
+            {
+                Ok(changes) => {
+                    state
+                        .alerts
+                        .resolve(
+                            notifier,
+                            &preferred_alert_key,
+                            &format!("Preferred validators of stake pool {pool_name} updated"),
+                        )
+                        .await;
+                    if !changes.is_empty() {
+                        let mut message = format!(
+                            "Rotated the preferred validators of stake pool {}{}:",
+                            pool_name,
+                            if staker_dry_run { " (dry run)" } else { "" }
+                        );
+                        for change in &changes {
+                            message.push_str(&format!("\n- {change}"));
+                        }
+                        state.alerts.info(notifier, &message).await;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "Failed to rotate preferred validators of stake pool {}: {:#?}",
+                        stake_pool_address_str,
+                        err
+                    );
+                    state
+                        .alerts
+                        .raise(
+                            notifier,
+                            &preferred_alert_key,
+                            Severity::Warn,
+                            &format!(
+                                "Failed to rotate preferred validators of stake pool {pool_name}: {err:#}"
+                            ),
+                        )
+                        .await;
+                }
+            }
+        }
+
+        // fees for the epoch are minted by the pool balance update
+        if error.is_none() && fee_collection_action != FeeCollectionAction::None {
+            let fee_collection_alert_key =
+                format!("fee-collection-failed:{}", stake_pool_address_str);
+            match manager::fee_collection::collect_fees(
+                &config,
+                &stake_pool_pubkey,
+                fee_collection_action,
+                fee_collection_min_tokens,
+                fee_collection_destination.as_ref(),
+                fee_collection_dry_run,
+            )
+            .await
+            {
+                Ok(collection) => {
+                    state
+                        .alerts
+                        .resolve(
+                            notifier,
+                            &fee_collection_alert_key,
+                            &format!(
+                                "Manager fees of stake pool {pool_name} collected successfully"
+                            ),
+                        )
+                        .await;
+                    if let Some(collection) = collection {
+                        state
+                            .alerts
+                            .info(
+                                notifier,
+                                &format!(
+                                    "Collected manager fees of stake pool {}{}: {}",
+                                    pool_name,
+                                    if fee_collection_dry_run {
+                                        " (dry run)"
+                                    } else {
+                                        ""
+                                    },
+                                    collection
+                                ),
+                            )
+                            .await;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "Failed to collect manager fees of stake pool {}: {:#?}",
+                        stake_pool_address_str,
+                        err
+                    );
+                    state
+                        .alerts
+                        .raise(
+                            notifier,
+                            &fee_collection_alert_key,
+                            Severity::Warn,
+                            &format!(
+                                "Failed to collect manager fees of stake pool {pool_name}: {err:#}"
+                            ),
+                        )
+                        .await;
+                }
+            }
+        }
+
+        summary_epoch = Some(epoch_info.epoch);
+        match report::summarize_pool(
+            &config,
+            &stake_pool_pubkey,
+            pool_name,
+            epoch_info.epoch,
+            &outcome,
+            error,
+        )
+        .await
+        {
+            Ok(summary) => pool_summaries.push(summary),
+            Err(err) => tracing::error!(
+                "Failed to summarize stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
             ),
+        }
+
+        if let Err(err) = report::record_chunks(
+            &config,
+            state,
+            &stake_pool_pubkey,
+            epoch_info.epoch,
+            &outcome,
         )
         .await
-        .context("Failed to send slack message about triggering rewards")?;
+        {
+            tracing::error!(
+                "Failed to record the update chunks of stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            );
+        }
 
-        if let Err(err) = command_update(&config, &stake_pool_pubkey, true, false, false).await {
+        // also when the update failed, another cranker may have finished it
+        if let Err(err) =
+            rates::record_rate(&config, state, &stake_pool_pubkey, epoch_info.epoch).await
+        {
             tracing::error!(
-                "Failed to update stake pool {}. Failed with error: {:#?}",
+                "Failed to record the exchange rate of stake pool {}: {:#?}",
                 stake_pool_address_str,
                 err
             );
-            if let Err(err) = slack_notification::send::send_message(
-                &channel_id,
-                &format!(
-                    "Failed to run command to update stake pool {}",
-                    stake_pool_address_str
-                ),
-            )
-            .await
-            {
-                tracing::error!(
-                    "Failed to send slack message about command update.\nError {}:-",
-                    err
+        }
+
+        if let Err(err) = rewards::record_rewards(
+            &config,
+            state,
+            &stake_pool_pubkey,
+            epoch_info.epoch,
+            Some(&outcome.balances_before),
+        )
+        .await
+        {
+            tracing::error!(
+                "Failed to attribute validator rewards of stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            );
+        }
+
+        let audit = match audit::audit_pool(&config, &stake_pool_pubkey, pool_name).await {
+            Ok(report) => audit::publish(state, notifier, report).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = audit {
+            tracing::error!(
+                "Failed to audit stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            );
+        }
+    }
+
+    for stake_pool_address_str in &stake_pool_addresses {
+        let stake_pool_pubkey = Pubkey::from_str(stake_pool_address_str)?;
+        let pool_name = pool_names
+            .get(stake_pool_address_str)
+            .map_or(stake_pool_address_str.as_str(), String::as_str);
+        match monitor::delinquency::check_pool(
+            &config,
+            state,
+            state.notifiers.for_pool(stake_pool_address_str),
+            &stake_pool_pubkey,
+            pool_name,
+            delinquency_epochs,
+            delinquency_action,
+            staker_dry_run,
+        )
+        .await
+        {
+            Ok(delinquent) => {
+                for validator in delinquent {
+                    tracing::warn!(
+                        "Validator {} in stake pool {} has been delinquent for {} epochs",
+                        validator.vote_account,
+                        stake_pool_address_str,
+                        validator.epochs
+                    );
+                }
+            }
+            Err(err) => tracing::error!(
+                "Failed to check delinquent validators for stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            ),
+        }
+        match monitor::commission::check_pool(
+            &config,
+            state,
+            state.notifiers.for_pool(stake_pool_address_str),
+            &stake_pool_pubkey,
+            pool_name,
+            max_commission,
+        )
+        .await
+        {
+            Ok(high) => {
+                for validator in high {
+                    tracing::warn!(
+                        "Validator {} in stake pool {} charges {}% commission (previously {:?})",
+                        validator.vote_account,
+                        stake_pool_address_str,
+                        validator.commission,
+                        validator.previous
+                    );
+                }
+            }
+            Err(err) => tracing::error!(
+                "Failed to check validator commissions for stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            ),
+        }
+        if let Err(err) = manager::fee_schedule::announce_pending(
+            &config,
+            state,
+            state.notifiers.for_pool(stake_pool_address_str),
+            &stake_pool_pubkey,
+            pool_name,
+        )
+        .await
+        {
+            tracing::error!(
+                "Failed to check scheduled fee changes for stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            );
+        }
+        let reserve = match reserve_alert_pct {
+            Some(alert_pct) => {
+                staker::reserve::check_liquidity(
+                    &config,
+                    state,
+                    state.notifiers.for_pool(stake_pool_address_str),
+                    &stake_pool_pubkey,
+                    pool_name,
+                    alert_pct,
+                )
+                .await
+            }
+            None => Ok(()),
+        };
+        if let Err(err) = reserve {
+            tracing::error!(
+                "Failed to check the reserve of stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            );
+        }
+        let transient_alert_key = format!("transient-orphans:{}", stake_pool_address_str);
+        let cleanup_alert_key = format!("transient-cleanup:{}", stake_pool_address_str);
+        match monitor::transient::find_orphans(&config, &stake_pool_pubkey).await {
+            Ok(orphans) if orphans.is_empty() => {
+                let message = format!(
+                    "Transient stake accounts of stake pool {pool_name} match the validator list"
                 );
+                for key in [&transient_alert_key, &cleanup_alert_key] {
+                    state
+                        .alerts
+                        .resolve(
+                            state.notifiers.for_pool(stake_pool_address_str),
+                            key,
+                            &message,
+                        )
+                        .await;
+                }
+            }
+            Ok(orphans) => {
+                let mut message = format!(
+                    "Stake pool {} has {} transient stake accounts that do not match the validator list:",
+                    pool_name,
+                    orphans.len()
+                );
+                for orphan in &orphans {
+                    message.push_str(&format!("\n- {orphan}"));
+                }
+                state
+                    .alerts
+                    .raise(
+                        state.notifiers.for_pool(stake_pool_address_str),
+                        &transient_alert_key,
+                        Severity::Warn,
+                        &message,
+                    )
+                    .await;
+                // at most one attempt per epoch, a cleanup that fails or
+                // leaves the accounts mismatched is alerted rather than retried
+                let attempt = match latest_epoch.filter(|_| transient_cleanup) {
+                    Some(epoch) => {
+                        monitor::transient::claim_attempt(&state.store, &stake_pool_pubkey, epoch)
+                            .await
+                    }
+                    None => Ok(false),
+                };
+                let cleanup = match attempt {
+                    Ok(true) => {
+                        monitor::transient::clean_up(&config, &stake_pool_pubkey, &orphans, false)
+                            .await
+                    }
+                    Ok(false) => Ok(Vec::new()),
+                    Err(err) => Err(err),
+                };
+                match cleanup {
+                    Ok(signatures) if !signatures.is_empty() => tracing::info!(
+                        "Cleaned up transient stake of stake pool {}: {:?}",
+                        stake_pool_address_str,
+                        signatures
+                    ),
+                    Ok(_) => {}
+                    Err(err) => {
+                        tracing::error!(
+                            "Failed to clean up transient stake of stake pool {}: {:#?}",
+                            stake_pool_address_str,
+                            err
+                        );
+                        state
+                            .alerts
+                            .raise(
+                                state.notifiers.for_pool(stake_pool_address_str),
+                                &cleanup_alert_key,
+                                Severity::Warn,
+                                &format!(
+                                    "Failed to clean up transient stake of stake pool {pool_name}, \
+                                     it is not tried again before the next epoch: {err:#}"
+                                ),
+                            )
+                            .await;
+                    }
+                }
             }
+            Err(err) => tracing::error!(
+                "Failed to check transient stake accounts of stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            ),
+        }
+
+        // every cycle, so an update sent by another cranker is attributed
+        // and the next one is measured from the balances after this cycle's
+        // stake moves
+        let recorded_rewards = match latest_epoch {
+            Some(epoch) => {
+                rewards::record_rewards(&config, state, &stake_pool_pubkey, epoch, None).await
+            }
+            None => Ok(()),
+        };
+        if let Err(err) = recorded_rewards {
+            tracing::error!(
+                "Failed to attribute validator rewards of stake pool {}: {:#?}",
+                stake_pool_address_str,
+                err
+            );
         }
     }
+
+    if let Some(epoch) = summary_epoch {
+        let summary = EpochSummary::new(epoch, cycle_started.elapsed(), pool_summaries);
+        if let Err(err) = report::publish(state, summary).await {
+            tracing::error!("Failed to publish crank summary: {:#?}", err);
+        }
+    }
+
+    // an rpc that just failed is not asked again, the failure is already alerted
+    let epoch = match latest_epoch {
+        Some(epoch) => Some(epoch),
+        None if rpc_failed => None,
+        None => match get_epoch_info(&config.rpc_client).await {
+            Ok(epoch_info) => Some(epoch_info.epoch),
+            Err(err) => {
+                tracing::error!("Failed to get epoch info: {:#?}", err);
+                None
+            }
+        },
+    };
+    let runway = match epoch {
+        Some(epoch) => balance::monitor_fee_payer_runway(
+            &config,
+            &state.store,
+            &state.alerts,
+            state.notifiers.global(),
+            epoch,
+            balance_warning_epochs,
+            balance_critical_epochs,
+        )
+        .await
+        .map(|_| ()),
+        None => Ok(()),
+    };
+    if let Err(err) = runway {
+        tracing::error!("Failed to check fee payer runway: {:#?}", err);
+    }
     Ok(())
 }
 
@@ -269,10 +1344,8 @@ async fn checked_transaction_with_signers<T: Signers>(
     config: &Config,
     instructions: &[Instruction],
     signers: &T,
-) -> Result<Transaction> {
-    let tx = checked_transaction_with_signers_and_additional_fee(config, instructions, signers, 0)
-        .await?;
-    Ok(tx)
+) -> Result<(Transaction, u64)> {
+    checked_transaction_with_signers_and_additional_fee(config, instructions, signers, 0).await
 }
 
 async fn check_fee_payer_balance(config: &Config, required_balance: u64) -> Result<()> {
@@ -298,11 +1371,22 @@ async fn checked_transaction_with_signers_and_additional_fee<T: Signers>(
     instructions: &[Instruction],
     signers: &T,
     additional_fee: u64,
-) -> Result<Transaction> {
-    let recent_blockhash = get_latest_blockhash(&config.rpc_client)
-        .await
-        .context("Failed to get latest blockhash")?;
+) -> Result<(Transaction, u64)> {
     let mut instructions = instructions.to_vec();
+    // a durable nonce transaction must advance the nonce first
+    let recent_blockhash = match &config.nonce {
+        Some(nonce) => {
+            let authority = nonce.authority.pubkey();
+            instructions.insert(
+                0,
+                system_instruction::advance_nonce_account(&nonce.account, &authority),
+            );
+            get_nonce_blockhash(&config.rpc_client, &nonce.account, &authority).await?
+        }
+        None => get_latest_blockhash(&config.rpc_client)
+            .await
+            .context("Failed to get latest blockhash")?,
+    };
 
     if let Some(compute_unit_price) = config.compute_unit_price {
         instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
@@ -341,12 +1425,55 @@ async fn checked_transaction_with_signers_and_additional_fee<T: Signers>(
 
     check_fee_payer_balance(config, additional_fee.saturating_add(required_fee)).await?;
 
-    let transaction = Transaction::new(signers, message, recent_blockhash);
+    let transaction = match &config.nonce {
+        Some(nonce) => {
+            let mut transaction = Transaction::new_unsigned(message);
+            transaction.try_partial_sign(signers, recent_blockhash)?;
+            transaction.try_partial_sign(&[nonce.authority.as_ref()], recent_blockhash)?;
+            if !transaction.is_signed() {
+                anyhow::bail!("Durable nonce transaction is missing a signature");
+            }
+            transaction
+        }
+        None => Transaction::new(signers, message, recent_blockhash),
+    };
+
+    Ok((transaction, required_fee))
+}
+
+/// Wait between update chunks to prevent rpc timeouts. On shutdown the
+/// remaining chunks are left to the next crank, the ones already sent are
+/// still confirmed by the caller.
+async fn wait_between_chunks(config: &Config) -> Result<()> {
+    let wait = Duration::from_secs(30);
+    let Some(shutdown) = &config.shutdown else {
+        sleep(wait).await;
+        return Ok(());
+    };
+    if !shutdown.sleep(wait).await {
+        anyhow::bail!("Shutting down, leaving the remaining update chunks to the next crank");
+    }
+    Ok(())
+}
 
-    Ok(transaction)
+/// Fence every send on the lease, so a replica that lost it mid-crank does
+/// not submit beside the new leader.
+fn ensure_leader(config: &Config) -> Result<()> {
+    if config
+        .leadership
+        .as_ref()
+        .is_some_and(|leadership| !leadership.is_leader())
+    {
+        anyhow::bail!("Lost the lease, not sending the transaction");
+    }
+    Ok(())
 }
 
-async fn send_transaction(config: &Config, transaction: Transaction) -> Result<()> {
+async fn send_transaction(
+    config: &Config,
+    transaction: impl SerializableTransaction,
+) -> Result<Option<String>> {
+    ensure_leader(config)?;
     if config.dry_run {
         let result = config
             .rpc_client
@@ -357,6 +1484,11 @@ async fn send_transaction(config: &Config, transaction: Transaction) -> Result<(
                 err
             })?;
         tracing::info!("Simulate result: {:?}", result);
+        Ok(None)
+    } else if config.nonce.is_some() {
+        let signature = send_and_confirm_durable_transaction(config, &transaction).await?;
+        tracing::info!("Signature: {}", signature);
+        Ok(Some(signature))
     } else {
         let signature = config
             .rpc_client
@@ -364,11 +1496,47 @@ async fn send_transaction(config: &Config, transaction: Transaction) -> Result<(
             .await
             .with_context(|| "Failed to send and confirm transaction with spinner")?;
         tracing::info!("Signature: {}", signature);
+        Ok(Some(signature.to_string()))
+    }
+}
+
+/// The spinner gives up once the transaction's blockhash leaves the recent
+/// blockhash queue, which a nonce blockhash never enters, so durable nonce
+/// transactions are polled by signature instead.
+async fn send_and_confirm_durable_transaction(
+    config: &Config,
+    transaction: &impl SerializableTransaction,
+) -> Result<String> {
+    const CONFIRM_TIMEOUT: Duration = Duration::from_secs(90);
+    let signature = config
+        .rpc_client
+        .send_transaction(transaction)
+        .await
+        .with_context(|| "Failed to send durable nonce transaction")?;
+    let started = Instant::now();
+    loop {
+        match config
+            .rpc_client
+            .get_signature_status_with_commitment(&signature, CommitmentConfig::confirmed())
+            .await?
+        {
+            Some(Ok(())) => return Ok(signature.to_string()),
+            Some(Err(err)) => anyhow::bail!("Transaction {} failed: {}", signature, err),
+            None if started.elapsed() > CONFIRM_TIMEOUT => anyhow::bail!(
+                "Transaction {} was not confirmed within {:?}",
+                signature,
+                CONFIRM_TIMEOUT
+            ),
+            None => sleep(Duration::from_secs(2)).await,
+        }
     }
-    Ok(())
 }
 
-async fn send_transaction_no_wait(config: &Config, transaction: Transaction) -> Result<()> {
+async fn send_transaction_no_wait(
+    config: &Config,
+    transaction: impl SerializableTransaction,
+) -> Result<Option<String>> {
+    ensure_leader(config)?;
     if config.dry_run {
         let result = config
             .rpc_client
@@ -379,6 +1547,10 @@ async fn send_transaction_no_wait(config: &Config, transaction: Transaction) ->
                 err
             })?;
         tracing::info!("Simulate result: {:?}", result);
+        Ok(None)
+    } else if config.nonce.is_some() {
+        // the next transaction can only be built once this one advanced the nonce
+        send_transaction(config, transaction).await
     } else {
         let signature = config
             .rpc_client
@@ -386,8 +1558,8 @@ async fn send_transaction_no_wait(config: &Config, transaction: Transaction) ->
             .await
             .with_context(|| "Failed to send transaction (no wait)")?;
         tracing::info!("Signature: {}", signature);
+        Ok(Some(signature.to_string()))
     }
-    Ok(())
 }
 
 async fn command_update(
@@ -396,6 +1568,107 @@ async fn command_update(
     force: bool,
     no_merge: bool,
     stale_only: bool,
+    lookup_table: Option<&AddressLookupTableAccount>,
+    outcome: &mut UpdateOutcome,
+) -> Result<()> {
+    let started = Instant::now();
+    let result = send_update_transactions(
+        config,
+        stake_pool_address,
+        force,
+        no_merge,
+        stale_only,
+        lookup_table,
+        outcome,
+    )
+    .await;
+    if let Err(err) = confirm_signatures(config, outcome).await {
+        tracing::error!("Failed to confirm the update transactions: {:#?}", err);
+    }
+    outcome.duration = started.elapsed();
+    result
+}
+
+/// Wait for the transactions recorded in `outcome` to confirm, counting those
+/// that failed or did not land in time. The chunks sent without waiting are
+/// otherwise never checked.
+async fn confirm_signatures(config: &Config, outcome: &mut UpdateOutcome) -> Result<()> {
+    const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+    // getSignatureStatuses accepts at most 256 signatures per request
+    const MAX_SIGNATURES: usize = 256;
+    let mut pending: Vec<_> = outcome
+        .signatures
+        .iter()
+        .filter_map(|signature| signature.parse().ok())
+        .collect();
+    let started = Instant::now();
+    while !pending.is_empty() {
+        let mut unconfirmed = Vec::new();
+        for batch in pending.chunks(MAX_SIGNATURES) {
+            let statuses = config.rpc_client.get_signature_statuses(batch).await?.value;
+            for (signature, status) in batch.iter().zip(statuses) {
+                match status {
+                    Some(status) if status.err.is_some() => {
+                        tracing::error!("Transaction {} failed: {:?}", signature, status.err);
+                        outcome.failed_transactions += 1;
+                    }
+                    Some(status) if status.satisfies_commitment(CommitmentConfig::confirmed()) => {}
+                    _ => unconfirmed.push(*signature),
+                }
+            }
+        }
+        pending = unconfirmed;
+        if !pending.is_empty() && started.elapsed() > CONFIRM_TIMEOUT {
+            tracing::error!(
+                "{} transactions were not confirmed within {:?}",
+                pending.len(),
+                CONFIRM_TIMEOUT
+            );
+            outcome.failed_transactions += pending.len();
+            break;
+        }
+        if !pending.is_empty() {
+            sleep(Duration::from_secs(2)).await;
+        }
+    }
+    Ok(())
+}
+
+/// For each validator index range, whether every validator in it was already
+/// updated for `epoch`. A `None` range is never current.
+async fn current_chunks(
+    config: &Config,
+    validator_list_address: &Pubkey,
+    ranges: &[Option<Range<usize>>],
+    epoch: u64,
+) -> Result<Vec<bool>> {
+    if ranges.iter().all(Option::is_none) {
+        return Ok(vec![false; ranges.len()]);
+    }
+    let validator_list = get_validator_list(&config.rpc_client, validator_list_address).await?;
+    Ok(ranges
+        .iter()
+        .map(|range| {
+            range
+                .clone()
+                .and_then(|range| validator_list.validators.get(range))
+                .is_some_and(|validators| {
+                    validators
+                        .iter()
+                        .all(|validator| u64::from_le_bytes(validator.last_update_epoch.0) >= epoch)
+                })
+        })
+        .collect())
+}
+
+async fn send_update_transactions(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    force: bool,
+    no_merge: bool,
+    stale_only: bool,
+    lookup_table: Option<&AddressLookupTableAccount>,
+    outcome: &mut UpdateOutcome,
 ) -> Result<()> {
     if config.no_update {
         tracing::info!("Update requested, but --no-update flag specified, so doing nothing");
@@ -414,8 +1687,15 @@ async fn command_update(
     }
 
     let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    outcome.validator_count = validator_list.validators.len();
+    outcome.balances_before = rewards::snapshot(&validator_list);
+    outcome.validators_already_current = validator_list
+        .validators
+        .iter()
+        .filter(|validator| u64::from_le_bytes(validator.last_update_epoch.0) >= epoch_info.epoch)
+        .count();
 
-    let (mut update_list_instructions, final_instructions) = if stale_only {
+    let (update_list_instructions, final_instructions) = if stale_only {
         spl_stake_pool::instruction::update_stale_stake_pool(
             &config.stake_pool_program_id,
             &stake_pool,
@@ -434,32 +1714,131 @@ async fn command_update(
         )
     };
 
-    let update_list_instructions_len = update_list_instructions.len();
-    if update_list_instructions_len > 0 {
-        let last_instruction = update_list_instructions.split_off(update_list_instructions_len - 1);
-        // send the first ones without waiting
-        for instruction in update_list_instructions {
-            let transaction = checked_transaction_with_signers(
-                config,
-                &[instruction],
-                &[config.fee_payer.as_ref()],
-            )
-            .await?;
-            send_transaction_no_wait(config, transaction).await?;
-            // to prevent rpc timeout
-            sleep(Duration::from_secs(30)).await;
-        }
+    // validators covered by each chunk, so chunks another replica or a public
+    // cranker already ran can be skipped. The stale-only chunks are built
+    // from the stale validators alone and are always sent.
+    let validator_count = validator_list.validators.len();
+    let chunk_ranges: Vec<Option<Range<usize>>> = (0..update_list_instructions.len())
+        .map(|chunk| {
+            let start = chunk * spl_stake_pool::MAX_VALIDATORS_TO_UPDATE;
+            (!stale_only).then(|| {
+                start..(start + spl_stake_pool::MAX_VALIDATORS_TO_UPDATE).min(validator_count)
+            })
+        })
+        .collect();
+    outcome.chunks_total = update_list_instructions.len();
+
+    // the last chunk and the final instructions go out together as a bundle
+    let bundle_sender = config
+        .bundles
+        .as_ref()
+        .filter(|sender| sender.enabled_for(stake_pool_address));
+    let mut bundled = Vec::new();
 
-        // wait on the last one
-        let transaction = checked_transaction_with_signers(
+    if let Some(lookup_table) = lookup_table {
+        let chunks = update_list_instructions
+            .into_iter()
+            .zip(chunk_ranges)
+            .collect();
+        lookup_table::send_update_chunks(
             config,
-            &last_instruction,
-            &[config.fee_payer.as_ref()],
+            &stake_pool.validator_list,
+            epoch_info.epoch,
+            chunks,
+            lookup_table,
+            outcome,
         )
         .await?;
-        send_transaction(config, transaction).await?;
+    } else {
+        let last_index = update_list_instructions.len().saturating_sub(1);
+        // read the list once, and again only before a chunk that was pending
+        // when it was last read and after a wait
+        let mut current = current_chunks(
+            config,
+            &stake_pool.validator_list,
+            &chunk_ranges,
+            epoch_info.epoch,
+        )
+        .await?;
+        let mut waited = false;
+        for (index, instruction) in update_list_instructions.into_iter().enumerate() {
+            if waited && !current[index] {
+                let tail = current_chunks(
+                    config,
+                    &stake_pool.validator_list,
+                    &chunk_ranges[index..],
+                    epoch_info.epoch,
+                )
+                .await?;
+                current[index..].copy_from_slice(&tail);
+                waited = false;
+            }
+            if current[index] {
+                tracing::info!(
+                    "Skipping update chunk {}, its validators are already current",
+                    index
+                );
+                outcome.chunks_skipped += 1;
+                continue;
+            }
+            if index < last_index {
+                // send the first ones without waiting
+                let (transaction, fee) = checked_transaction_with_signers(
+                    config,
+                    &[instruction],
+                    &[config.fee_payer.as_ref()],
+                )
+                .await?;
+                let signature = send_transaction_no_wait(config, transaction).await?;
+                outcome.record_chunks(signature, fee, 1);
+                wait_between_chunks(config).await?;
+                waited = true;
+            } else if bundle_sender.is_some() {
+                bundled.push(vec![instruction]);
+            } else {
+                // wait on the last one
+                let (transaction, fee) = checked_transaction_with_signers(
+                    config,
+                    &[instruction],
+                    &[config.fee_payer.as_ref()],
+                )
+                .await?;
+                let signature = send_transaction(config, transaction).await?;
+                outcome.record_chunks(signature, fee, 1);
+            }
+        }
+    }
+
+    // nothing left to do when another cranker also finished the pool update
+    if outcome.chunks_skipped == outcome.chunks_total
+        && get_stake_pool(&config.rpc_client, stake_pool_address)
+            .await?
+            .last_update_epoch
+            >= epoch_info.epoch
+    {
+        tracing::info!(
+            "Stake pool {} was already updated by another cranker",
+            stake_pool_address
+        );
+        return Ok(());
+    }
+    if let Some(bundle_sender) = bundle_sender {
+        let bundled_chunks = bundled.len();
+        bundled.push(final_instructions);
+        let recorded = outcome.signatures.len();
+        bundle::submit(config, bundle_sender, bundled, outcome).await?;
+        // the bundle's transactions are recorded in order once it landed
+        let chunk_signatures: Vec<String> = outcome.signatures[recorded..]
+            .iter()
+            .take(bundled_chunks)
+            .cloned()
+            .collect();
+        outcome
+            .chunk_transactions
+            .extend(chunk_signatures.into_iter().map(|signature| (signature, 1)));
+        return Ok(());
     }
-    let transaction = checked_transaction_with_signers(
+    let (transaction, fee) = checked_transaction_with_signers(
         config,
         &final_instructions,
         &[config.fee_payer.as_ref()],
@@ -468,7 +1847,8 @@ async fn command_update(
     .with_context(
         || "Failed to create checked transaction with signers for final stake pool instructions",
     )?;
-    send_transaction(config, transaction).await?;
+    let signature = send_transaction(config, transaction).await?;
+    outcome.record(signature, fee);
 
     Ok(())
 }
@@ -0,0 +1,182 @@
+use {
+    super::{manager, send_manager_transaction},
+    crate::{
+        Config,
+        client::{get_stake_pool, get_validator_list},
+        config::FeeCollectionAction,
+    },
+    anyhow::{Context, Result},
+    solana_keypair::Keypair,
+    solana_program::{stake::state::StakeStateV2, system_instruction},
+    solana_pubkey::Pubkey,
+    solana_signer::Signer,
+    spl_stake_pool::state::StakeStatus,
+    std::{fmt, num::NonZeroU32},
+};
+
+/// Manager fees collected from one pool.
+#[derive(Clone, Debug)]
+pub struct Collection {
+    pub action: FeeCollectionAction,
+    /// Pool tokens taken from the manager fee account, formatted with the
+    /// mint's decimals
+    pub ui_amount: String,
+    pub destination: Pubkey,
+    /// `None` on a dry run
+    pub signature: Option<String>,
+}
+
+impl fmt::Display for Collection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            FeeCollectionAction::WithdrawSol => "withdrew as SOL to",
+            FeeCollectionAction::WithdrawStake => "withdrew as stake for",
+            _ => "transferred to",
+        };
+        write!(
+            f,
+            "{} pool tokens {action} {}",
+            self.ui_amount, self.destination
+        )?;
+        if let Some(signature) = &self.signature {
+            write!(f, " ({signature})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the manager fee account of the pool and, once it holds at least
+/// `min_tokens`, redeem or transfer its whole balance.
+pub async fn collect_fees(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    action: FeeCollectionAction,
+    min_tokens: u64,
+    destination: Option<&Pubkey>,
+    dry_run: bool,
+) -> Result<Option<Collection>> {
+    if action == FeeCollectionAction::None {
+        return Ok(None);
+    }
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let manager = manager(config)?.pubkey();
+    let balance = config
+        .rpc_client
+        .get_token_account_balance(&stake_pool.manager_fee_account)
+        .await
+        .context("Failed to read the manager fee account")?;
+    let pool_tokens = balance.amount.parse::<u64>()?;
+    if pool_tokens == 0 || pool_tokens < min_tokens {
+        tracing::info!(
+            "Manager fee account of {} holds {} pool tokens, below the collection threshold",
+            stake_pool_address,
+            balance.ui_amount_string
+        );
+        return Ok(None);
+    }
+    let destination = destination.copied().unwrap_or(manager);
+    let withdraw_authority = spl_stake_pool::find_withdraw_authority_program_address(
+        &config.stake_pool_program_id,
+        stake_pool_address,
+    )
+    .0;
+
+    let signature = match action {
+        FeeCollectionAction::WithdrawSol => {
+            let instruction = spl_stake_pool::instruction::withdraw_sol(
+                &config.stake_pool_program_id,
+                stake_pool_address,
+                &withdraw_authority,
+                &manager,
+                &stake_pool.manager_fee_account,
+                &stake_pool.reserve_stake,
+                &destination,
+                &stake_pool.manager_fee_account,
+                &stake_pool.pool_mint,
+                &stake_pool.token_program_id,
+                pool_tokens,
+            );
+            send_manager_transaction(config, &[instruction], &[], dry_run).await?
+        }
+        FeeCollectionAction::WithdrawStake => {
+            // the program requires withdrawals from the preferred validator
+            // while it has stake, otherwise take from the largest one
+            let validator_list =
+                get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+            let active = validator_list.validators.iter().filter(|validator| {
+                matches!(
+                    StakeStatus::try_from(validator.status),
+                    Ok(StakeStatus::Active)
+                )
+            });
+            let validator = match stake_pool.preferred_withdraw_validator_vote_address {
+                Some(preferred) => active
+                    .clone()
+                    .find(|validator| validator.vote_account_address == preferred),
+                None => None,
+            }
+            .or_else(|| {
+                active.max_by_key(|validator| u64::from_le_bytes(validator.active_stake_lamports.0))
+            })
+            .context("Stake pool has no active validator to withdraw stake from")?;
+            let (stake_to_split, _) = spl_stake_pool::find_stake_program_address(
+                &config.stake_pool_program_id,
+                &validator.vote_account_address,
+                stake_pool_address,
+                NonZeroU32::new(u32::from_le_bytes(validator.validator_seed_suffix.0)),
+            );
+
+            let stake_receiver = Keypair::new();
+            let stake_rent = config
+                .rpc_client
+                .get_minimum_balance_for_rent_exemption(StakeStateV2::size_of())
+                .await?;
+            let instructions = [
+                system_instruction::create_account(
+                    &config.fee_payer.pubkey(),
+                    &stake_receiver.pubkey(),
+                    stake_rent,
+                    StakeStateV2::size_of() as u64,
+                    &solana_program::stake::program::id(),
+                ),
+                spl_stake_pool::instruction::withdraw_stake(
+                    &config.stake_pool_program_id,
+                    stake_pool_address,
+                    &stake_pool.validator_list,
+                    &withdraw_authority,
+                    &stake_to_split,
+                    &stake_receiver.pubkey(),
+                    &destination,
+                    &manager,
+                    &stake_pool.manager_fee_account,
+                    &stake_pool.manager_fee_account,
+                    &stake_pool.pool_mint,
+                    &stake_pool.token_program_id,
+                    pool_tokens,
+                ),
+            ];
+            send_manager_transaction(config, &instructions, &[&stake_receiver], dry_run).await?
+        }
+        FeeCollectionAction::Transfer => {
+            let instruction = spl_token_2022::instruction::transfer_checked(
+                &stake_pool.token_program_id,
+                &stake_pool.manager_fee_account,
+                &stake_pool.pool_mint,
+                &destination,
+                &manager,
+                &[],
+                pool_tokens,
+                balance.decimals,
+            )?;
+            send_manager_transaction(config, &[instruction], &[], dry_run).await?
+        }
+        FeeCollectionAction::None => return Ok(None),
+    };
+
+    Ok(Some(Collection {
+        action,
+        ui_amount: balance.ui_amount_string,
+        destination,
+        signature,
+    }))
+}
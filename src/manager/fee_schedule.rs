@@ -0,0 +1,409 @@
+use {
+    super::send_manager_transaction,
+    crate::{AppState, Config, client::get_stake_pool, notifier::Notifier},
+    anyhow::{Context, Result},
+    serde::Serialize,
+    solana_pubkey::Pubkey,
+    spl_stake_pool::{
+        instruction::FeeType,
+        state::{Fee, FutureEpoch, StakePool},
+    },
+    std::{collections::HashSet, fmt},
+};
+
+/// Fees that can be changed with `set_fee`, by their command line name.
+const FEE_KINDS: [&str; 7] = [
+    "epoch",
+    "stake-withdrawal",
+    "sol-withdrawal",
+    "stake-deposit",
+    "sol-deposit",
+    "stake-referral",
+    "sol-referral",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct FeeRate {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl From<&Fee> for FeeRate {
+    fn from(fee: &Fee) -> Self {
+        Self {
+            numerator: fee.numerator,
+            denominator: fee.denominator,
+        }
+    }
+}
+
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 0 {
+            return write!(f, "0%");
+        }
+        write!(
+            f,
+            "{}/{} ({:.2}%)",
+            self.numerator,
+            self.denominator,
+            self.numerator as f64 / self.denominator as f64 * 100.0
+        )
+    }
+}
+
+/// A fee change that the pool applies at a later epoch update.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct PendingFee {
+    pub fee: FeeRate,
+    /// First epoch the new fee is charged in
+    pub effective_epoch: u64,
+}
+
+fn pending(next: &FutureEpoch<Fee>, last_update_epoch: u64) -> Option<PendingFee> {
+    // each epoch update moves a change one step closer
+    let (fee, epochs) = match next {
+        FutureEpoch::None => return None,
+        FutureEpoch::One(fee) => (fee, 1),
+        FutureEpoch::Two(fee) => (fee, 2),
+    };
+    Some(PendingFee {
+        fee: fee.into(),
+        effective_epoch: last_update_epoch.saturating_add(epochs),
+    })
+}
+
+/// Current and scheduled fees of a stake pool.
+#[derive(Clone, Debug, Serialize)]
+pub struct FeeSchedule {
+    pub pool: String,
+    /// Epoch the pool was last updated in
+    pub last_update_epoch: u64,
+    pub epoch_fee: FeeRate,
+    pub next_epoch_fee: Option<PendingFee>,
+    pub stake_withdrawal_fee: FeeRate,
+    pub next_stake_withdrawal_fee: Option<PendingFee>,
+    pub sol_withdrawal_fee: FeeRate,
+    pub next_sol_withdrawal_fee: Option<PendingFee>,
+    pub stake_deposit_fee: FeeRate,
+    pub sol_deposit_fee: FeeRate,
+    /// Share of the stake deposit fee paid to referrers, in percent
+    pub stake_referral_fee: u8,
+    /// Share of the SOL deposit fee paid to referrers, in percent
+    pub sol_referral_fee: u8,
+}
+
+impl FeeSchedule {
+    pub fn new(stake_pool_address: &Pubkey, stake_pool: &StakePool) -> Self {
+        let epoch = stake_pool.last_update_epoch;
+        Self {
+            pool: stake_pool_address.to_string(),
+            last_update_epoch: epoch,
+            epoch_fee: (&stake_pool.epoch_fee).into(),
+            next_epoch_fee: pending(&stake_pool.next_epoch_fee, epoch),
+            stake_withdrawal_fee: (&stake_pool.stake_withdrawal_fee).into(),
+            next_stake_withdrawal_fee: pending(&stake_pool.next_stake_withdrawal_fee, epoch),
+            sol_withdrawal_fee: (&stake_pool.sol_withdrawal_fee).into(),
+            next_sol_withdrawal_fee: pending(&stake_pool.next_sol_withdrawal_fee, epoch),
+            stake_deposit_fee: (&stake_pool.stake_deposit_fee).into(),
+            sol_deposit_fee: (&stake_pool.sol_deposit_fee).into(),
+            stake_referral_fee: stake_pool.stake_referral_fee,
+            sol_referral_fee: stake_pool.sol_referral_fee,
+        }
+    }
+
+    /// Scheduled changes as `(fee name, current, pending)`.
+    pub fn pending_changes(&self) -> Vec<(&'static str, FeeRate, PendingFee)> {
+        [
+            ("epoch", self.epoch_fee, self.next_epoch_fee),
+            (
+                "stake-withdrawal",
+                self.stake_withdrawal_fee,
+                self.next_stake_withdrawal_fee,
+            ),
+            (
+                "sol-withdrawal",
+                self.sol_withdrawal_fee,
+                self.next_sol_withdrawal_fee,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, current, next)| next.map(|next| (name, current, next)))
+        .collect()
+    }
+}
+
+impl fmt::Display for FeeSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Stake pool {} (last updated in epoch {})",
+            self.pool, self.last_update_epoch
+        )?;
+        let scheduled = |next: &Option<PendingFee>| match next {
+            Some(next) => format!(", {} from epoch {}", next.fee, next.effective_epoch),
+            None => String::new(),
+        };
+        writeln!(
+            f,
+            "  epoch            {}{}",
+            self.epoch_fee,
+            scheduled(&self.next_epoch_fee)
+        )?;
+        writeln!(
+            f,
+            "  stake-withdrawal {}{}",
+            self.stake_withdrawal_fee,
+            scheduled(&self.next_stake_withdrawal_fee)
+        )?;
+        writeln!(
+            f,
+            "  sol-withdrawal   {}{}",
+            self.sol_withdrawal_fee,
+            scheduled(&self.next_sol_withdrawal_fee)
+        )?;
+        writeln!(f, "  stake-deposit    {}", self.stake_deposit_fee)?;
+        writeln!(f, "  sol-deposit      {}", self.sol_deposit_fee)?;
+        writeln!(f, "  stake-referral   {}%", self.stake_referral_fee)?;
+        writeln!(f, "  sol-referral     {}%", self.sol_referral_fee)
+    }
+}
+
+pub async fn fee_schedule(config: &Config, stake_pool_address: &Pubkey) -> Result<FeeSchedule> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    Ok(FeeSchedule::new(stake_pool_address, &stake_pool))
+}
+
+/// Parse a fee change such as `epoch 3/100` or `sol-referral 50`. Referral
+/// fees are a percentage, every other fee is a `numerator/denominator`.
+pub fn parse_fee(kind: &str, value: &str) -> Result<FeeType> {
+    let fee = || -> Result<Fee> {
+        let (numerator, denominator) = value
+            .split_once('/')
+            .with_context(|| format!("Fee {value} must be written as <numerator>/<denominator>"))?;
+        let fee = Fee {
+            numerator: numerator.trim().parse().context("Invalid fee numerator")?,
+            denominator: denominator
+                .trim()
+                .parse()
+                .context("Invalid fee denominator")?,
+        };
+        if fee.numerator > fee.denominator {
+            anyhow::bail!("Fee {} must not exceed 100%", value);
+        }
+        Ok(fee)
+    };
+    let referral = || -> Result<u8> {
+        value
+            .trim()
+            .trim_end_matches('%')
+            .parse::<u8>()
+            .ok()
+            .filter(|pct| *pct <= 100)
+            .context("Referral fee must be a percentage between 0 and 100")
+    };
+    Ok(match kind {
+        "epoch" => FeeType::Epoch(fee()?),
+        "stake-withdrawal" => FeeType::StakeWithdrawal(fee()?),
+        "sol-withdrawal" => FeeType::SolWithdrawal(fee()?),
+        "stake-deposit" => FeeType::StakeDeposit(fee()?),
+        "sol-deposit" => FeeType::SolDeposit(fee()?),
+        "stake-referral" => FeeType::StakeReferral(referral()?),
+        "sol-referral" => FeeType::SolReferral(referral()?),
+        other => anyhow::bail!(
+            "Unknown fee {}, expected one of: {}",
+            other,
+            FEE_KINDS.join(", ")
+        ),
+    })
+}
+
+/// Send `set_fee` as the manager. Epoch and withdrawal fees are only
+/// applied two epoch updates later, deposit and referral fees immediately.
+pub async fn set_fee(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    fee: FeeType,
+    dry_run: bool,
+) -> Result<Option<String>> {
+    let manager = super::manager(config)?.pubkey();
+    let instruction = spl_stake_pool::instruction::set_fee(
+        &config.stake_pool_program_id,
+        stake_pool_address,
+        &manager,
+        fee,
+    );
+    send_manager_transaction(config, &[instruction], &[], dry_run).await
+}
+
+/// Identifies a scheduled change by the fee, its new value and the epoch it
+/// takes effect in, so each change is announced exactly once.
+fn announcement_key(stake_pool_address: &Pubkey, name: &str, next: &PendingFee) -> String {
+    format!(
+        "{}:{}:{}/{}:{}",
+        stake_pool_address, name, next.fee.numerator, next.fee.denominator, next.effective_epoch
+    )
+}
+
+/// Announce each scheduled fee change of the pool once, when it is first
+/// seen.
+pub async fn announce_pending(
+    config: &Config,
+    state: &AppState,
+    notifier: &dyn Notifier,
+    stake_pool_address: &Pubkey,
+    pool_name: &str,
+) -> Result<()> {
+    let schedule = fee_schedule(config, stake_pool_address).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+    let changes = schedule.pending_changes();
+    let announcements: Vec<(String, &(&str, FeeRate, PendingFee))> = changes
+        .iter()
+        .map(|change| {
+            let (name, _, next) = change;
+            (announcement_key(stake_pool_address, name, next), change)
+        })
+        .collect();
+    let keys: HashSet<String> = announcements.iter().map(|(key, _)| key.clone()).collect();
+
+    let prefix = format!("{stake_pool_address}:");
+    let new_keys = state
+        .store
+        .update(|data| {
+            // forget changes that took effect or were replaced
+            data.announced_fee_changes
+                .retain(|key| !key.starts_with(&prefix) || keys.contains(key));
+            keys.iter()
+                .filter(|key| data.announced_fee_changes.insert((*key).clone()))
+                .cloned()
+                .collect::<HashSet<String>>()
+        })
+        .await?;
+
+    for (key, (name, current, next)) in &announcements {
+        if !new_keys.contains(key) {
+            continue;
+        }
+        let when = match next.effective_epoch.saturating_sub(epoch) {
+            0 | 1 => "at the next epoch update".to_string(),
+            epochs => format!("in {epochs} epochs"),
+        };
+        state
+            .alerts
+            .info(
+                notifier,
+                &format!(
+                    "The {} fee of stake pool {} changes from {} to {} {} (epoch {})",
+                    name, pool_name, current, next.fee, when, next.effective_epoch
+                ),
+            )
+            .await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee(numerator: u64, denominator: u64) -> Fee {
+        Fee {
+            numerator,
+            denominator,
+        }
+    }
+
+    #[test]
+    fn parses_fractional_fees() {
+        assert!(matches!(
+            parse_fee("epoch", "3/100").unwrap(),
+            FeeType::Epoch(Fee {
+                numerator: 3,
+                denominator: 100
+            })
+        ));
+        assert!(matches!(
+            parse_fee("sol-withdrawal", " 1 / 1000 ").unwrap(),
+            FeeType::SolWithdrawal(Fee {
+                numerator: 1,
+                denominator: 1000
+            })
+        ));
+        assert!(matches!(
+            parse_fee("stake-deposit", "10/10").unwrap(),
+            FeeType::StakeDeposit(_)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_fractional_fees() {
+        assert!(parse_fee("epoch", "101/100").is_err());
+        assert!(parse_fee("epoch", "3").is_err());
+        assert!(parse_fee("epoch", "3/x").is_err());
+        assert!(parse_fee("epoch", "-1/100").is_err());
+    }
+
+    #[test]
+    fn parses_referral_percentages() {
+        assert!(matches!(
+            parse_fee("sol-referral", "50").unwrap(),
+            FeeType::SolReferral(50)
+        ));
+        assert!(matches!(
+            parse_fee("stake-referral", "100%").unwrap(),
+            FeeType::StakeReferral(100)
+        ));
+        assert!(parse_fee("sol-referral", "101").is_err());
+        assert!(parse_fee("sol-referral", "1/2").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_fee_kinds() {
+        let err = parse_fee("deposit", "1/100").unwrap_err();
+        assert!(err.to_string().contains("expected one of: epoch"));
+    }
+
+    #[test]
+    fn pending_counts_epochs_from_last_update() {
+        assert!(pending(&FutureEpoch::None, 700).is_none());
+        let next = pending(&FutureEpoch::Two(fee(5, 100)), 700).unwrap();
+        assert_eq!(next.effective_epoch, 702);
+        assert_eq!(
+            next.fee,
+            FeeRate {
+                numerator: 5,
+                denominator: 100
+            }
+        );
+        assert_eq!(
+            pending(&FutureEpoch::One(fee(5, 100)), 701)
+                .unwrap()
+                .effective_epoch,
+            702
+        );
+    }
+
+    #[test]
+    fn announcement_key_ignores_epochs_left() {
+        // the same change seen two and one epochs ahead is announced once
+        let pool = Pubkey::new_unique();
+        let two_ahead = pending(&FutureEpoch::Two(fee(5, 100)), 700).unwrap();
+        let one_ahead = pending(&FutureEpoch::One(fee(5, 100)), 701).unwrap();
+        assert_eq!(
+            announcement_key(&pool, "epoch", &two_ahead),
+            announcement_key(&pool, "epoch", &one_ahead)
+        );
+        let replaced = pending(&FutureEpoch::One(fee(6, 100)), 701).unwrap();
+        assert_ne!(
+            announcement_key(&pool, "epoch", &one_ahead),
+            announcement_key(&pool, "epoch", &replaced)
+        );
+    }
+
+    #[test]
+    fn displays_fee_rates() {
+        let rate = FeeRate::from(&fee(3, 200));
+        assert_eq!(rate.to_string(), "3/200 (1.50%)");
+        assert_eq!(FeeRate::from(&fee(0, 0)).to_string(), "0%");
+    }
+}
@@ -0,0 +1,46 @@
+pub mod fee_collection;
+pub mod fee_schedule;
+
+use {
+    crate::{Config, checked_transaction_with_signers, send_transaction},
+    anyhow::Result,
+    solana_instruction::Instruction,
+    solana_signer::Signer,
+};
+
+/// The pool manager signer, required for every instruction in this module.
+pub fn manager(config: &Config) -> Result<&dyn Signer> {
+    config
+        .manager
+        .as_deref()
+        .map(|manager| manager as &dyn Signer)
+        .ok_or_else(|| anyhow::anyhow!("MANAGER_PRIVATE_KEY is not set"))
+}
+
+/// Send `instructions` in one transaction signed by the fee payer, the
+/// manager and `extra_signers`, or only simulate it when `dry_run` is set.
+pub async fn send_manager_transaction(
+    config: &Config,
+    instructions: &[Instruction],
+    extra_signers: &[&dyn Signer],
+    dry_run: bool,
+) -> Result<Option<String>> {
+    let manager = manager(config)?;
+    let mut signers: Vec<&dyn Signer> = vec![config.fee_payer.as_ref(), manager];
+    signers.extend_from_slice(extra_signers);
+    let (transaction, _) = checked_transaction_with_signers(config, instructions, &signers).await?;
+    if dry_run {
+        let result = config
+            .rpc_client
+            .simulate_transaction(&transaction)
+            .await?
+            .value;
+        tracing::info!("Simulated manager transaction: {:?}", result);
+        if let Some(err) = result.err {
+            anyhow::bail!("Manager transaction simulation failed: {}", err);
+        }
+        Ok(None)
+    } else {
+        send_transaction(config, transaction).await
+    }
+}
@@ -0,0 +1,295 @@
+use {
+    crate::{
+        AppState, Config,
+        alerts::Severity,
+        client::{get_stake_pool, get_validator_list},
+        notifier::Notifier,
+    },
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    solana_pubkey::Pubkey,
+    std::collections::HashMap,
+};
+
+/// Number of commission samples kept per vote account
+const MAX_SAMPLES: usize = 64;
+
+/// A validator that takes everything
+const RUG_PULL_COMMISSION: u8 = 100;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CommissionSample {
+    pub epoch: u64,
+    pub commission: u8,
+}
+
+/// A pool validator whose commission is above the configured limit.
+#[derive(Clone, Debug)]
+pub struct HighCommission {
+    pub vote_account: Pubkey,
+    pub commission: u8,
+    /// Commission before it was raised, if it went up this epoch
+    pub previous: Option<u8>,
+}
+
+/// The commission the validator had before `epoch`.
+fn previous_commission(history: &[CommissionSample], epoch: u64) -> Option<u8> {
+    history
+        .iter()
+        .rev()
+        .find(|sample| sample.epoch < epoch)
+        .map(|sample| sample.commission)
+}
+
+/// Whether the history already has `commission` as this epoch's sample.
+fn is_recorded(history: Option<&Vec<CommissionSample>>, epoch: u64, commission: u8) -> bool {
+    history
+        .and_then(|history| history.last())
+        .is_some_and(|last| last.epoch == epoch && last.commission == commission)
+}
+
+/// Add `commission` to the history, replacing this epoch's sample if the
+/// validator changed it again. Returns the commission it had before.
+fn record_sample(history: &mut Vec<CommissionSample>, epoch: u64, commission: u8) -> Option<u8> {
+    let previous = previous_commission(history, epoch);
+    match history.last_mut() {
+        Some(last) if last.epoch == epoch => last.commission = commission,
+        _ => history.push(CommissionSample { epoch, commission }),
+    }
+    if history.len() > MAX_SAMPLES {
+        let excess = history.len() - MAX_SAMPLES;
+        history.drain(..excess);
+    }
+    previous
+}
+
+/// Whether `key` is a commission alert of the validator `validator_key`,
+/// including the keys written before the commission was part of them.
+fn is_alert_for(key: &str, validator_key: &str) -> bool {
+    key.strip_prefix(validator_key)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+/// Record the commission of every validator in the pool and alert on the
+/// ones above `max_commission`, critically if they went to 100%.
+pub async fn check_pool(
+    config: &Config,
+    state: &AppState,
+    notifier: &dyn Notifier,
+    stake_pool_address: &Pubkey,
+    pool_name: &str,
+    max_commission: u8,
+) -> Result<Vec<HighCommission>> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+    let vote_accounts = config.rpc_client.get_vote_accounts().await?;
+    let commissions: HashMap<String, u8> = vote_accounts
+        .current
+        .into_iter()
+        .chain(vote_accounts.delinquent)
+        .map(|info| (info.vote_pubkey, info.commission))
+        .collect();
+
+    // unreported vote accounts are covered by the delinquency check
+    let reported: Vec<(Pubkey, u8)> = validator_list
+        .validators
+        .iter()
+        .filter_map(|validator| {
+            let vote_account = validator.vote_account_address;
+            commissions
+                .get(&vote_account.to_string())
+                .map(|commission| (vote_account, *commission))
+        })
+        .collect();
+    // the state file is only rewritten, once per pool, when a commission
+    // changed or the epoch has no sample yet
+    let changed = state
+        .store
+        .read(|data| {
+            reported.iter().any(|(vote_account, commission)| {
+                !is_recorded(
+                    data.commissions.get(&vote_account.to_string()),
+                    epoch,
+                    *commission,
+                )
+            })
+        })
+        .await;
+    let previous: HashMap<Pubkey, Option<u8>> = if changed {
+        state
+            .store
+            .update(|data| {
+                reported
+                    .iter()
+                    .map(|(vote_account, commission)| {
+                        let history = data
+                            .commissions
+                            .entry(vote_account.to_string())
+                            .or_default();
+                        (*vote_account, record_sample(history, epoch, *commission))
+                    })
+                    .collect()
+            })
+            .await?
+    } else {
+        state
+            .store
+            .read(|data| {
+                reported
+                    .iter()
+                    .map(|(vote_account, _)| {
+                        let previous = data
+                            .commissions
+                            .get(&vote_account.to_string())
+                            .and_then(|history| previous_commission(history, epoch));
+                        (*vote_account, previous)
+                    })
+                    .collect()
+            })
+            .await
+    };
+
+    let mut high = Vec::new();
+    for (vote_account, commission) in reported {
+        let previous = previous.get(&vote_account).copied().flatten();
+
+        // keyed on the commission so a further raise within the same
+        // severity is not suppressed as a repeat
+        let validator_key = format!("commission:{stake_pool_address}:{vote_account}");
+        let active = state
+            .alerts
+            .active_keys(|key| is_alert_for(key, &validator_key))
+            .await;
+        if commission <= max_commission {
+            for key in active {
+                state
+                    .alerts
+                    .resolve(
+                        notifier,
+                        &key,
+                        &format!(
+                            "Validator {vote_account} in stake pool {pool_name} lowered its commission to {commission}%"
+                        ),
+                    )
+                    .await;
+            }
+            continue;
+        }
+        let alert_key = format!("{validator_key}:{commission}");
+        let superseded: Vec<String> = active.into_iter().filter(|key| *key != alert_key).collect();
+        state.alerts.clear(&superseded).await;
+
+        let raised_from = previous.filter(|previous| *previous < commission);
+        let message = match raised_from {
+            Some(previous) => format!(
+                "Validator {vote_account} in stake pool {pool_name} raised its commission from {previous}% to {commission}%"
+            ),
+            None => format!(
+                "Validator {vote_account} in stake pool {pool_name} charges {commission}% commission, above the {max_commission}% limit"
+            ),
+        };
+        let severity = if commission >= RUG_PULL_COMMISSION {
+            Severity::Critical
+        } else {
+            Severity::Warn
+        };
+        state
+            .alerts
+            .raise(notifier, &alert_key, severity, &message)
+            .await;
+        high.push(HighCommission {
+            vote_account,
+            commission,
+            previous: raised_from,
+        });
+    }
+
+    Ok(high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(samples: &[(u64, u8)]) -> Vec<CommissionSample> {
+        samples
+            .iter()
+            .map(|&(epoch, commission)| CommissionSample { epoch, commission })
+            .collect()
+    }
+
+    fn commissions(history: &[CommissionSample]) -> Vec<(u64, u8)> {
+        history
+            .iter()
+            .map(|sample| (sample.epoch, sample.commission))
+            .collect()
+    }
+
+    #[test]
+    fn record_sample_returns_the_previous_epochs_commission() {
+        for (samples, epoch, commission, previous, recorded) in [
+            (vec![], 10, 5, None, vec![(10, 5)]),
+            (vec![(9, 5)], 10, 8, Some(5), vec![(9, 5), (10, 8)]),
+            // changed again within the epoch
+            (
+                vec![(9, 5), (10, 8)],
+                10,
+                10,
+                Some(5),
+                vec![(9, 5), (10, 10)],
+            ),
+            (
+                vec![(8, 5), (9, 7)],
+                10,
+                7,
+                Some(7),
+                vec![(8, 5), (9, 7), (10, 7)],
+            ),
+        ] {
+            let mut history = history(&samples);
+            assert_eq!(
+                record_sample(&mut history, epoch, commission),
+                previous,
+                "{samples:?}"
+            );
+            assert_eq!(commissions(&history), recorded, "{samples:?}");
+        }
+    }
+
+    #[test]
+    fn record_sample_keeps_the_latest_samples() {
+        let mut history = Vec::new();
+        for epoch in 0..MAX_SAMPLES as u64 + 3 {
+            record_sample(&mut history, epoch, 5);
+        }
+        assert_eq!(history.len(), MAX_SAMPLES);
+        assert_eq!(history[0].epoch, 3);
+    }
+
+    #[test]
+    fn is_recorded_compares_the_latest_sample() {
+        let samples = history(&[(9, 5), (10, 8)]);
+        for (epoch, commission, recorded) in [(10, 8, true), (10, 5, false), (11, 8, false)] {
+            assert_eq!(
+                is_recorded(Some(&samples), epoch, commission),
+                recorded,
+                "{epoch} {commission}"
+            );
+        }
+        assert!(!is_recorded(None, 10, 8));
+    }
+
+    #[test]
+    fn alert_keys_are_matched_per_validator() {
+        let validator_key = "commission:pool:vote";
+        for (key, matches) in [
+            ("commission:pool:vote", true),
+            ("commission:pool:vote:12", true),
+            ("commission:pool:voter:12", false),
+            ("commission:pool:other:12", false),
+        ] {
+            assert_eq!(is_alert_for(key, validator_key), matches, "{key}");
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use {
+    crate::{
+        AppState, Config,
+        alerts::Severity,
+        client::{get_stake_pool, get_validator_list},
+        config::DelinquencyAction,
+        notifier::Notifier,
+        staker::send_staker_instructions,
+    },
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    solana_client::rpc_response::RpcVoteAccountInfo,
+    solana_program::stake::state::StakeStateV2,
+    solana_pubkey::Pubkey,
+    spl_stake_pool::state::StakeStatus,
+    std::{
+        collections::{HashMap, HashSet},
+        num::NonZeroU32,
+    },
+};
+
+/// What the cranker remembers about a pool validator that stopped voting.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DelinquencyRecord {
+    /// First epoch the validator was seen delinquent
+    pub since_epoch: u64,
+    /// Epoch the configured action was last taken, so it runs once per epoch
+    pub action_epoch: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DelinquentValidator {
+    pub vote_account: Pubkey,
+    /// Full epochs since the validator last earned vote credits or was
+    /// first seen delinquent, `u64::MAX` if it has no recent credits at all
+    pub epochs: u64,
+}
+
+/// Number of epochs `vote_account` has gone without voting, or `None` if it
+/// is voting normally.
+fn epochs_without_voting(
+    vote_account: Option<&(RpcVoteAccountInfo, bool)>,
+    epoch: u64,
+) -> Option<u64> {
+    let Some((info, delinquent)) = vote_account else {
+        // closed or unstaked vote accounts are no longer reported at all
+        return Some(u64::MAX);
+    };
+    let last_credited_epoch = info
+        .epoch_credits
+        .iter()
+        .filter(|(_, credits, previous_credits)| credits > previous_credits)
+        .map(|(credit_epoch, _, _)| *credit_epoch)
+        .max();
+    // the current epoch is still being earned, so only count full epochs
+    // without credits
+    let epochs = match last_credited_epoch {
+        Some(credit_epoch) => epoch.saturating_sub(credit_epoch).saturating_sub(1),
+        None => u64::MAX,
+    };
+    (*delinquent || epochs > 0).then_some(epochs)
+}
+
+/// Find pool validators that are delinquent or have stopped voting, alert
+/// on them and apply the configured action once they have been out for
+/// `threshold_epochs` epochs.
+#[allow(clippy::too_many_arguments)]
+pub async fn check_pool(
+    config: &Config,
+    state: &AppState,
+    notifier: &dyn Notifier,
+    stake_pool_address: &Pubkey,
+    pool_name: &str,
+    threshold_epochs: u64,
+    action: DelinquencyAction,
+    dry_run: bool,
+) -> Result<Vec<DelinquentValidator>> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+    let vote_accounts = config.rpc_client.get_vote_accounts().await?;
+    let vote_accounts: HashMap<String, (RpcVoteAccountInfo, bool)> = vote_accounts
+        .current
+        .into_iter()
+        .map(|info| (info.vote_pubkey.clone(), (info, false)))
+        .chain(
+            vote_accounts
+                .delinquent
+                .into_iter()
+                .map(|info| (info.vote_pubkey.clone(), (info, true))),
+        )
+        .collect();
+
+    let stake_rent = config
+        .rpc_client
+        .get_minimum_balance_for_rent_exemption(StakeStateV2::size_of())
+        .await?;
+    let minimum_delegation =
+        spl_stake_pool::minimum_delegation(config.rpc_client.get_stake_minimum_delegation().await?);
+
+    // one read and at most one write per pool, the state file is rewritten
+    // on every update
+    let prefix = format!("{stake_pool_address}:");
+    let known: HashMap<String, DelinquencyRecord> = state
+        .store
+        .read(|data| {
+            data.delinquency
+                .iter()
+                .filter(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, record)| (key.clone(), record.clone()))
+                .collect()
+        })
+        .await;
+    let mut active = HashSet::new();
+    let mut records = HashMap::new();
+    let mut to_act = Vec::new();
+    let mut delinquent = Vec::new();
+    for validator in &validator_list.validators {
+        if !matches!(
+            StakeStatus::try_from(validator.status),
+            Ok(StakeStatus::Active)
+        ) {
+            continue;
+        }
+        let vote_account = validator.vote_account_address;
+        let key = format!("{prefix}{vote_account}");
+        active.insert(key.clone());
+        let vote_info = vote_accounts.get(&vote_account.to_string());
+        let Some(epochs_since_credits) = epochs_without_voting(vote_info, epoch) else {
+            continue;
+        };
+
+        let record = known.get(&key).cloned().unwrap_or(DelinquencyRecord {
+            since_epoch: epoch,
+            action_epoch: None,
+        });
+        let epochs = epochs_since_credits.max(epoch.saturating_sub(record.since_epoch));
+        let over_threshold = epochs >= threshold_epochs;
+        let description = if epochs == u64::MAX {
+            "has no recent vote credits".to_string()
+        } else {
+            format!("has not voted for {epochs} epochs")
+        };
+        state
+            .alerts
+            .raise(
+                notifier,
+                &format!("delinquent:{key}"),
+                if over_threshold {
+                    Severity::Critical
+                } else {
+                    Severity::Warn
+                },
+                &format!("Validator {vote_account} in stake pool {pool_name} {description}"),
+            )
+            .await;
+
+        if over_threshold && record.action_epoch != Some(epoch) {
+            to_act.push((validator, key.clone()));
+        }
+        records.insert(key, record);
+        delinquent.push(DelinquentValidator {
+            vote_account,
+            epochs,
+        });
+    }
+
+    // records of validators that vote again, are no longer active or have
+    // left the list are dropped
+    let cleared: Vec<String> = known
+        .keys()
+        .filter(|key| !records.contains_key(*key))
+        .cloned()
+        .collect();
+    let added = records.keys().any(|key| !known.contains_key(key));
+    if added || !cleared.is_empty() {
+        state
+            .store
+            .update(|data| {
+                for key in &cleared {
+                    data.delinquency.remove(key);
+                }
+                for (key, record) in &records {
+                    data.delinquency
+                        .entry(key.clone())
+                        .or_insert_with(|| record.clone());
+                }
+            })
+            .await?;
+    }
+    for key in cleared {
+        let vote_account = key.strip_prefix(&prefix).unwrap_or(&key);
+        let message = if active.contains(&key) {
+            format!("Validator {vote_account} in stake pool {pool_name} is voting again")
+        } else {
+            format!("Validator {vote_account} is no longer active in stake pool {pool_name}")
+        };
+        state
+            .alerts
+            .resolve(notifier, &format!("delinquent:{key}"), &message)
+            .await;
+    }
+
+    for (validator, key) in to_act {
+        let vote_account = validator.vote_account_address;
+        let validator_seed = NonZeroU32::new(u32::from_le_bytes(validator.validator_seed_suffix.0));
+        let transient_seed = u64::from_le_bytes(validator.transient_seed_suffix.0);
+        let active_lamports = u64::from_le_bytes(validator.active_stake_lamports.0);
+        let floor = stake_rent.saturating_add(minimum_delegation);
+        let instruction = match action {
+            DelinquencyAction::Alert => None,
+            // stake can only move once the validator is updated for the
+            // epoch and has no transient stake in flight
+            DelinquencyAction::Decrease
+                if u64::from_le_bytes(validator.last_update_epoch.0) == epoch
+                    && u64::from_le_bytes(validator.transient_stake_lamports.0) == 0
+                    && active_lamports.saturating_sub(floor) >= minimum_delegation =>
+            {
+                Some(
+                    spl_stake_pool::instruction::decrease_validator_stake_with_vote(
+                        &config.stake_pool_program_id,
+                        &stake_pool,
+                        stake_pool_address,
+                        &vote_account,
+                        active_lamports - floor,
+                        validator_seed,
+                        transient_seed,
+                    ),
+                )
+            }
+            DelinquencyAction::Decrease => None,
+            DelinquencyAction::Remove => Some(
+                spl_stake_pool::instruction::remove_validator_from_pool_with_vote(
+                    &config.stake_pool_program_id,
+                    &stake_pool,
+                    stake_pool_address,
+                    &vote_account,
+                    validator_seed,
+                    transient_seed,
+                ),
+            ),
+        };
+        let Some(instruction) = instruction else {
+            continue;
+        };
+        send_staker_instructions(config, vec![instruction], dry_run).await?;
+        state
+            .store
+            .update(|data| {
+                if let Some(record) = data.delinquency.get_mut(&key) {
+                    record.action_epoch = Some(epoch);
+                }
+            })
+            .await?;
+        state
+            .alerts
+            .info(
+                notifier,
+                &format!(
+                    "{} delinquent validator {} in stake pool {}{}",
+                    match action {
+                        DelinquencyAction::Decrease => "Decreased stake of",
+                        _ => "Removing",
+                    },
+                    vote_account,
+                    pool_name,
+                    if dry_run { " (dry run)" } else { "" }
+                ),
+            )
+            .await;
+    }
+
+    Ok(delinquent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH: u64 = 100;
+
+    /// A vote account that earned `credits` in each of `credited_epochs`.
+    fn vote_account(
+        credited_epochs: &[(u64, u64)],
+        delinquent: bool,
+    ) -> (RpcVoteAccountInfo, bool) {
+        let mut total = 0;
+        let epoch_credits = credited_epochs
+            .iter()
+            .map(|&(epoch, credits)| {
+                let previous = total;
+                total += credits;
+                (epoch, total, previous)
+            })
+            .collect();
+        let info = RpcVoteAccountInfo {
+            vote_pubkey: Pubkey::new_unique().to_string(),
+            node_pubkey: Pubkey::new_unique().to_string(),
+            activated_stake: 0,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits,
+            last_vote: 0,
+            root_slot: 0,
+        };
+        (info, delinquent)
+    }
+
+    #[test]
+    fn epochs_without_voting_counts_full_epochs() {
+        for (credited_epochs, delinquent, expected) in [
+            // voting in the current epoch
+            (vec![(99, 10), (100, 5)], false, None),
+            // the current epoch has not earned credits yet
+            (vec![(98, 10), (99, 10)], false, None),
+            (vec![(97, 10)], false, Some(2)),
+            // reported delinquent before a full epoch passed
+            (vec![(99, 10), (100, 5)], true, Some(0)),
+            // epochs without new credits do not count as voting
+            (vec![(95, 10), (96, 0), (97, 0)], false, Some(4)),
+            (vec![(96, 0)], false, Some(u64::MAX)),
+            (vec![], false, Some(u64::MAX)),
+        ] {
+            let vote_account = vote_account(&credited_epochs, delinquent);
+            assert_eq!(
+                epochs_without_voting(Some(&vote_account), EPOCH),
+                expected,
+                "{credited_epochs:?} delinquent: {delinquent}"
+            );
+        }
+    }
+
+    #[test]
+    fn unreported_vote_account_never_voted() {
+        assert_eq!(epochs_without_voting(None, EPOCH), Some(u64::MAX));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod commission;
+pub mod delinquency;
+pub mod transient;
@@ -0,0 +1,231 @@
+use {
+    crate::{
+        Config, checked_transaction_with_signers,
+        client::{get_stake_pool, get_validator_list},
+        send_transaction,
+        store::Store,
+    },
+    anyhow::Result,
+    solana_pubkey::Pubkey,
+    std::{collections::BTreeSet, fmt},
+};
+
+/// Number of seeds before the current one that are checked for leftovers
+const SEED_LOOKBACK: u64 = 4;
+
+/// Accounts per `getMultipleAccounts` request
+const MAX_ACCOUNTS_PER_REQUEST: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrphanKind {
+    /// The account at the current seed exists but the list records no
+    /// transient stake
+    Untracked,
+    /// The list records transient stake but the account does not exist
+    Missing,
+    /// The account holds a different amount than the list records
+    Mismatch { recorded: u64 },
+    /// An account at an earlier seed, which the pool no longer tracks
+    StaleSeed,
+}
+
+/// A transient stake account that does not match the validator list.
+#[derive(Clone, Debug)]
+pub struct OrphanedTransient {
+    pub vote_account: Pubkey,
+    /// Position of the validator in the validator list
+    pub validator_index: usize,
+    pub address: Pubkey,
+    pub seed: u64,
+    pub lamports: u64,
+    pub kind: OrphanKind,
+}
+
+impl fmt::Display for OrphanedTransient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (validator {}, seed {}): ",
+            self.address, self.vote_account, self.seed
+        )?;
+        match self.kind {
+            OrphanKind::Untracked => write!(f, "holds {} lamports, not recorded", self.lamports),
+            OrphanKind::Missing => write!(f, "recorded but does not exist"),
+            OrphanKind::Mismatch { recorded } => {
+                write!(f, "holds {} lamports, {} recorded", self.lamports, recorded)
+            }
+            OrphanKind::StaleSeed => {
+                write!(f, "holds {} lamports at an old seed", self.lamports)
+            }
+        }
+    }
+}
+
+/// Derive the transient stake account of every validator at its current
+/// seed and the few before it, and report the accounts that do not match
+/// `transient_stake_lamports`.
+pub async fn find_orphans(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+) -> Result<Vec<OrphanedTransient>> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+
+    // (validator index, seed, address)
+    let mut candidates = Vec::new();
+    for (index, validator) in validator_list.validators.iter().enumerate() {
+        let current_seed = u64::from_le_bytes(validator.transient_seed_suffix.0);
+        let seeds: BTreeSet<u64> = (0..=SEED_LOOKBACK)
+            .map(|back| current_seed.saturating_sub(back))
+            .collect();
+        for seed in seeds {
+            let (address, _) = spl_stake_pool::find_transient_stake_program_address(
+                &config.stake_pool_program_id,
+                &validator.vote_account_address,
+                stake_pool_address,
+                seed,
+            );
+            candidates.push((index, seed, address));
+        }
+    }
+
+    let addresses: Vec<Pubkey> = candidates.iter().map(|(_, _, address)| *address).collect();
+    let mut lamports = Vec::with_capacity(addresses.len());
+    for chunk in addresses.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+        let accounts = config.rpc_client.get_multiple_accounts(chunk).await?;
+        lamports.extend(
+            accounts
+                .into_iter()
+                .map(|account| account.map_or(0, |account| account.lamports)),
+        );
+    }
+
+    let mut orphans = Vec::new();
+    for ((index, seed, address), lamports) in candidates.into_iter().zip(lamports) {
+        let validator = &validator_list.validators[index];
+        let current_seed = u64::from_le_bytes(validator.transient_seed_suffix.0);
+        let recorded = u64::from_le_bytes(validator.transient_stake_lamports.0);
+        let kind = if seed != current_seed {
+            (lamports > 0).then_some(OrphanKind::StaleSeed)
+        } else if u64::from_le_bytes(validator.last_update_epoch.0) < epoch {
+            // the next update reconciles a stale entry anyway
+            None
+        } else {
+            match (lamports, recorded) {
+                (0, 0) => None,
+                (_, 0) => Some(OrphanKind::Untracked),
+                (0, _) => Some(OrphanKind::Missing),
+                (lamports, recorded) if lamports != recorded => {
+                    Some(OrphanKind::Mismatch { recorded })
+                }
+                _ => None,
+            }
+        };
+        if let Some(kind) = kind {
+            orphans.push(OrphanedTransient {
+                vote_account: validator.vote_account_address,
+                validator_index: index,
+                address,
+                seed,
+                lamports,
+                kind,
+            });
+        }
+    }
+    Ok(orphans)
+}
+
+/// Claim the cleanup attempt of the pool for `epoch`. Returns `false` when
+/// one was already made in that epoch, so a cleanup that does not fix the
+/// accounts is not repeated every cycle.
+pub async fn claim_attempt(store: &Store, stake_pool_address: &Pubkey, epoch: u64) -> Result<bool> {
+    let pool = stake_pool_address.to_string();
+    let attempted = store
+        .read(|data| data.transient_cleanups.get(&pool) == Some(&epoch))
+        .await;
+    if attempted {
+        return Ok(false);
+    }
+    store
+        .update(|data| {
+            data.transient_cleanups.insert(pool, epoch);
+        })
+        .await?;
+    Ok(true)
+}
+
+/// Re-run the validator list update for the validators with mismatched
+/// transient accounts at their current seed so the program merges or
+/// re-records them, then update the pool balance. Accounts at old seeds are
+/// outside the pool's bookkeeping and are only reported.
+pub async fn clean_up(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    orphans: &[OrphanedTransient],
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let indices: BTreeSet<usize> = orphans
+        .iter()
+        .filter(|orphan| orphan.kind != OrphanKind::StaleSeed)
+        .map(|orphan| orphan.validator_index)
+        .collect();
+    if indices.is_empty() {
+        return Ok(Vec::new());
+    }
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let withdraw_authority = spl_stake_pool::find_withdraw_authority_program_address(
+        &config.stake_pool_program_id,
+        stake_pool_address,
+    )
+    .0;
+
+    let mut instructions = Vec::new();
+    for index in indices {
+        instructions.push(
+            spl_stake_pool::instruction::update_validator_list_balance_chunk(
+                &config.stake_pool_program_id,
+                stake_pool_address,
+                &withdraw_authority,
+                &stake_pool.validator_list,
+                &stake_pool.reserve_stake,
+                &validator_list,
+                1,
+                index,
+                false,
+            )?,
+        );
+    }
+    instructions.push(spl_stake_pool::instruction::update_stake_pool_balance(
+        &config.stake_pool_program_id,
+        stake_pool_address,
+        &withdraw_authority,
+        &stake_pool.validator_list,
+        &stake_pool.reserve_stake,
+        &stake_pool.manager_fee_account,
+        &stake_pool.pool_mint,
+        &stake_pool.token_program_id,
+    ));
+
+    let mut signatures = Vec::new();
+    for instruction in instructions {
+        let (transaction, _) =
+            checked_transaction_with_signers(config, &[instruction], &[config.fee_payer.as_ref()])
+                .await?;
+        if dry_run {
+            let result = config
+                .rpc_client
+                .simulate_transaction(&transaction)
+                .await?
+                .value;
+            tracing::info!("Simulated transient stake cleanup: {:?}", result);
+            if let Some(err) = result.err {
+                anyhow::bail!("Transient stake cleanup simulation failed: {}", err);
+            }
+        } else {
+            signatures.extend(send_transaction(config, transaction).await?);
+        }
+    }
+    Ok(signatures)
+}
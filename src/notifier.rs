@@ -0,0 +1,332 @@
+use {
+    crate::config::{NotifierKind, StakePoolConfig},
+    anyhow::{Context, Result},
+    serde_json::json,
+    std::{collections::HashMap, future::Future, pin::Pin, sync::Arc},
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A destination for operator notifications.
+pub trait Notifier: Send + Sync {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Send `message` through `notifier`, logging instead of failing so a broken
+/// notification channel never aborts a crank.
+pub async fn notify(notifier: &dyn Notifier, message: &str) {
+    if let Err(err) = notifier.send(message).await {
+        tracing::error!(
+            "Failed to send notification {:?}.\nError {:#}:-",
+            message,
+            err
+        );
+    }
+}
+
+pub struct SlackNotifier {
+    channel_id: String,
+}
+
+impl Notifier for SlackNotifier {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            slack_notification::send::send_message(&self.channel_id, message)
+                .await
+                .map_err(|err| anyhow::anyhow!("Failed to send slack message: {}", err))?;
+            Ok(())
+        })
+    }
+}
+
+pub struct DiscordNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl Notifier for DiscordNotifier {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.client
+                .post(&self.webhook_url)
+                .json(&json!({ "content": message }))
+                .send()
+                .await
+                .context("Failed to call discord webhook")?
+                .error_for_status()
+                .context("Discord webhook rejected the message")?;
+            Ok(())
+        })
+    }
+}
+
+pub struct TelegramNotifier {
+    client: reqwest::Client,
+    bot_token: String,
+    chat_id: String,
+}
+
+impl Notifier for TelegramNotifier {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.client
+                .post(format!(
+                    "https://api.telegram.org/bot{}/sendMessage",
+                    self.bot_token
+                ))
+                .json(&json!({ "chat_id": self.chat_id, "text": message }))
+                .send()
+                .await
+                .context("Failed to call telegram sendMessage")?
+                .error_for_status()
+                .context("Telegram rejected the message")?;
+            Ok(())
+        })
+    }
+}
+
+/// Posts `{"text": message}` to an arbitrary endpoint.
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Notifier for WebhookNotifier {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.client
+                .post(&self.url)
+                .json(&json!({ "text": message }))
+                .send()
+                .await
+                .context("Failed to call notification webhook")?
+                .error_for_status()
+                .context("Notification webhook rejected the message")?;
+            Ok(())
+        })
+    }
+}
+
+pub struct StdoutNotifier;
+
+impl Notifier for StdoutNotifier {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            println!("{message}");
+            Ok(())
+        })
+    }
+}
+
+/// Records every message in memory instead of delivering it.
+#[cfg(any(test, feature = "dev-context-only-utils"))]
+#[derive(Default)]
+pub struct MockNotifier {
+    pub messages: std::sync::Mutex<Vec<String>>,
+    pub fail: bool,
+}
+
+#[cfg(any(test, feature = "dev-context-only-utils"))]
+impl Notifier for MockNotifier {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if self.fail {
+                anyhow::bail!("Mock notifier configured to fail");
+            }
+            self.messages.lock().unwrap().push(message.to_string());
+            Ok(())
+        })
+    }
+}
+
+/// The notifier for each configured pool, plus a default used for pools
+/// without an override and for messages not tied to a pool.
+#[derive(Clone)]
+pub struct Notifiers {
+    default: Arc<dyn Notifier>,
+    per_pool: HashMap<String, Arc<dyn Notifier>>,
+}
+
+impl Notifiers {
+    pub fn from_config(config: &StakePoolConfig) -> Result<Self> {
+        let client = reqwest::Client::new();
+        Self::route(config.notifier, &config.pool_notifiers, |kind| {
+            build_notifier(config, kind, &client)
+        })
+    }
+
+    /// Route each pool to the backend `build` returns for its kind, building
+    /// every kind once so pools sharing a kind share the backend.
+    fn route(
+        default: NotifierKind,
+        pool_notifiers: &HashMap<String, NotifierKind>,
+        mut build: impl FnMut(NotifierKind) -> Result<Arc<dyn Notifier>>,
+    ) -> Result<Self> {
+        let mut built = HashMap::new();
+        let mut backend = |kind: NotifierKind| -> Result<Arc<dyn Notifier>> {
+            if let Some(notifier) = built.get(&kind) {
+                return Ok(Arc::clone(notifier));
+            }
+            let notifier = build(kind)?;
+            built.insert(kind, Arc::clone(&notifier));
+            Ok(notifier)
+        };
+        let default = backend(default)?;
+        let per_pool = pool_notifiers
+            .iter()
+            .map(|(pool, kind)| Ok((pool.clone(), backend(*kind)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self { default, per_pool })
+    }
+
+    /// Send every message through `notifier`.
+    #[cfg(any(test, feature = "dev-context-only-utils"))]
+    pub fn single(notifier: Arc<dyn Notifier>) -> Self {
+        Self {
+            default: notifier,
+            per_pool: HashMap::new(),
+        }
+    }
+
+    pub fn global(&self) -> &dyn Notifier {
+        self.default.as_ref()
+    }
+
+    pub fn for_pool(&self, stake_pool_address: &str) -> &dyn Notifier {
+        self.per_pool
+            .get(stake_pool_address)
+            .unwrap_or(&self.default)
+            .as_ref()
+    }
+}
+
+fn build_notifier(
+    config: &StakePoolConfig,
+    kind: NotifierKind,
+    client: &reqwest::Client,
+) -> Result<Arc<dyn Notifier>> {
+    fn required(value: &str, name: &str, kind: NotifierKind) -> Result<String> {
+        if value.is_empty() {
+            anyhow::bail!("{} is required for the {:?} notifier", name, kind);
+        }
+        Ok(value.to_string())
+    }
+
+    Ok(match kind {
+        NotifierKind::Slack => {
+            required(&config.slack_token, "SLACK_TOKEN", kind)?;
+            Arc::new(SlackNotifier {
+                channel_id: required(&config.slack_channel_id, "SLACK_CHANNEL_ID", kind)?,
+            })
+        }
+        NotifierKind::Discord => Arc::new(DiscordNotifier {
+            client: client.clone(),
+            webhook_url: required(&config.discord_webhook_url, "DISCORD_WEBHOOK_URL", kind)?,
+        }),
+        NotifierKind::Telegram => Arc::new(TelegramNotifier {
+            client: client.clone(),
+            bot_token: required(&config.telegram_bot_token, "TELEGRAM_BOT_TOKEN", kind)?,
+            chat_id: required(&config.telegram_chat_id, "TELEGRAM_CHAT_ID", kind)?,
+        }),
+        NotifierKind::Webhook => Arc::new(WebhookNotifier {
+            client: client.clone(),
+            url: required(&config.webhook_url, "WEBHOOK_URL", kind)?,
+        }),
+        NotifierKind::Stdout => Arc::new(StdoutNotifier),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::config::parse_map};
+
+    const POOL_A: &str = "pool-a";
+    const POOL_B: &str = "pool-b";
+
+    type Mocks = HashMap<NotifierKind, Arc<MockNotifier>>;
+
+    /// Notifiers routed the way `from_config` routes a `POOL_NOTIFIERS`
+    /// value, with one mock standing in for each backend kind and stdout as
+    /// the default.
+    fn notifiers(pool_notifiers: &str) -> (Notifiers, Arc<MockNotifier>, Mocks) {
+        let pool_notifiers = parse_map::<NotifierKind>(pool_notifiers, "POOL_NOTIFIERS").unwrap();
+        let mut mocks = Mocks::new();
+        let notifiers = Notifiers::route(NotifierKind::Stdout, &pool_notifiers, |kind| {
+            let mock = Arc::new(MockNotifier::default());
+            mocks.insert(kind, mock.clone());
+            Ok(mock as Arc<dyn Notifier>)
+        })
+        .unwrap();
+        let default = mocks.remove(&NotifierKind::Stdout).unwrap();
+        (notifiers, default, mocks)
+    }
+
+    fn messages(mock: &MockNotifier) -> Vec<String> {
+        mock.messages.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn pool_override_receives_pool_messages() {
+        let (notifiers, default, mocks) = notifiers(&format!("{POOL_A}=discord"));
+        notify(notifiers.for_pool(POOL_A), "pool a").await;
+        notify(notifiers.global(), "global").await;
+
+        assert_eq!(messages(&mocks[&NotifierKind::Discord]), vec!["pool a"]);
+        assert_eq!(messages(&default), vec!["global"]);
+    }
+
+    #[tokio::test]
+    async fn pool_without_override_uses_default() {
+        let (notifiers, default, mocks) = notifiers(&format!("{POOL_A}=telegram"));
+        notify(notifiers.for_pool(POOL_B), "pool b").await;
+
+        assert_eq!(messages(&default), vec!["pool b"]);
+        assert!(messages(&mocks[&NotifierKind::Telegram]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn pools_sharing_a_kind_share_the_backend() {
+        let (notifiers, default, mocks) =
+            notifiers(&format!(" {POOL_A} = Webhook , {POOL_B}=webhook,"));
+        notify(notifiers.for_pool(POOL_A), "a").await;
+        notify(notifiers.for_pool(POOL_B), "b").await;
+
+        assert_eq!(mocks.len(), 1);
+        assert_eq!(messages(&mocks[&NotifierKind::Webhook]), vec!["a", "b"]);
+        assert!(messages(&default).is_empty());
+    }
+
+    #[test]
+    fn backend_errors_fail_the_routing() {
+        let pool_notifiers = parse_map::<NotifierKind>("pool-a=discord", "POOL_NOTIFIERS").unwrap();
+        let routed = Notifiers::route(NotifierKind::Stdout, &pool_notifiers, |kind| {
+            if kind == NotifierKind::Discord {
+                anyhow::bail!("DISCORD_WEBHOOK_URL is required");
+            }
+            Ok(Arc::new(MockNotifier::default()) as Arc<dyn Notifier>)
+        });
+        assert!(routed.is_err());
+    }
+
+    #[tokio::test]
+    async fn failing_notifier_does_not_propagate() {
+        let failing = MockNotifier {
+            fail: true,
+            ..MockNotifier::default()
+        };
+        assert!(failing.send("lost").await.is_err());
+        notify(&failing, "lost").await;
+        assert!(messages(&failing).is_empty());
+    }
+
+    #[test]
+    fn pool_notifiers_rejects_invalid_entries() {
+        assert!(parse_map::<NotifierKind>("pool-a=pager", "POOL_NOTIFIERS").is_err());
+        assert!(parse_map::<NotifierKind>("pool-a", "POOL_NOTIFIERS").is_err());
+        assert!(
+            parse_map::<NotifierKind>("", "POOL_NOTIFIERS")
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
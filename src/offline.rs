@@ -0,0 +1,150 @@
+use {
+    crate::{
+        Config, checked_transaction_with_signers,
+        client::{get_stake_pool, get_validator_list},
+        ensure_leader, send_and_confirm_durable_transaction,
+    },
+    anyhow::{Context, Result},
+    base64::{Engine, engine::general_purpose::STANDARD},
+    solana_instruction::Instruction,
+    solana_pubkey::Pubkey,
+    solana_transaction::Transaction,
+};
+
+/// The next update transaction of the pool: the first chunk with a validator
+/// not yet updated for the epoch, then the final pool balance update. `None`
+/// once the pool is updated.
+async fn next_update_instructions(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+) -> Result<Option<Vec<Instruction>>> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+    if stake_pool.last_update_epoch >= epoch {
+        return Ok(None);
+    }
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let (update_list_instructions, final_instructions) =
+        spl_stake_pool::instruction::update_stake_pool(
+            &config.stake_pool_program_id,
+            &stake_pool,
+            &validator_list,
+            stake_pool_address,
+            false,
+        );
+    let pending_chunk = update_list_instructions
+        .into_iter()
+        .zip(
+            validator_list
+                .validators
+                .chunks(spl_stake_pool::MAX_VALIDATORS_TO_UPDATE),
+        )
+        .find(|(_, validators)| {
+            validators
+                .iter()
+                .any(|validator| u64::from_le_bytes(validator.last_update_epoch.0) < epoch)
+        })
+        .map(|(instruction, _)| vec![instruction]);
+    Ok(Some(pending_chunk.unwrap_or(final_instructions)))
+}
+
+/// Build and sign the pool's next update transaction on the durable nonce
+/// without sending it, base64 encoded. It stays valid until the nonce is
+/// advanced, so it can be submitted later by anyone.
+pub async fn sign_next_update(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+) -> Result<Option<String>> {
+    if config.nonce.is_none() {
+        anyhow::bail!("NONCE_ACCOUNT is required to sign transactions for later submission");
+    }
+    let Some(instructions) = next_update_instructions(config, stake_pool_address).await? else {
+        return Ok(None);
+    };
+    let (transaction, _) =
+        checked_transaction_with_signers(config, &instructions, &[config.fee_payer.as_ref()])
+            .await?;
+    encode(&transaction).map(Some)
+}
+
+/// Send a transaction signed earlier and wait for it to confirm.
+pub async fn submit(config: &Config, encoded: &str) -> Result<String> {
+    let transaction = decode(encoded)?;
+    ensure_leader(config)?;
+    send_and_confirm_durable_transaction(config, &transaction).await
+}
+
+fn encode(transaction: &Transaction) -> Result<String> {
+    let bytes = bincode::serialize(transaction).context("Failed to serialize transaction")?;
+    Ok(STANDARD.encode(bytes))
+}
+
+/// Decode a base64 transaction and check that it carries every signature
+/// its message requires.
+fn decode(encoded: &str) -> Result<Transaction> {
+    let bytes = STANDARD
+        .decode(encoded.trim())
+        .context("Transaction must be base64 encoded")?;
+    let transaction: Transaction =
+        bincode::deserialize(&bytes).context("Invalid serialized transaction")?;
+    if !transaction.is_signed() {
+        anyhow::bail!("Transaction is missing signatures");
+    }
+    transaction
+        .verify()
+        .context("Transaction signatures do not match its message")?;
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, solana_hash::Hash, solana_keypair::Keypair, solana_message::Message,
+        solana_program::system_instruction, solana_signer::Signer,
+    };
+
+    fn transfer(payer: &Keypair) -> Message {
+        Message::new_with_blockhash(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&payer.pubkey()),
+            &Hash::new_unique(),
+        )
+    }
+
+    #[test]
+    fn signed_transaction_round_trips() {
+        let payer = Keypair::new();
+        let message = transfer(&payer);
+        let blockhash = message.recent_blockhash;
+        let transaction = Transaction::new(&[&payer], message, blockhash);
+        let decoded = decode(&encode(&transaction).unwrap()).unwrap();
+        assert_eq!(decoded, transaction);
+    }
+
+    #[test]
+    fn unsigned_transaction_is_rejected() {
+        let payer = Keypair::new();
+        let transaction = Transaction::new_unsigned(transfer(&payer));
+        assert!(decode(&encode(&transaction).unwrap()).is_err());
+    }
+
+    #[test]
+    fn tampered_transaction_is_rejected() {
+        let payer = Keypair::new();
+        let message = transfer(&payer);
+        let blockhash = message.recent_blockhash;
+        let mut transaction = Transaction::new(&[&payer], message, blockhash);
+        transaction.message.recent_blockhash = Hash::new_unique();
+        assert!(decode(&encode(&transaction).unwrap()).is_err());
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert!(decode("not base64!").is_err());
+        assert!(decode(&STANDARD.encode([1, 2, 3])).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use {
+    crate::{AppState, Config, client::get_stake_pool, report::exchange_rate},
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    solana_pubkey::Pubkey,
+    std::time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Number of rate samples kept per pool, a bit over two years of epochs
+const MAX_SAMPLES: usize = 400;
+
+const SECONDS_PER_YEAR: f64 = 365.25 * 24.0 * 60.0 * 60.0;
+
+/// Trailing windows, in epochs, reported by the APY endpoint
+pub const APY_WINDOWS: [u64; 3] = [1, 7, 30];
+
+/// The pool's exchange rate right after an epoch's update.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RateSample {
+    pub epoch: u64,
+    /// SOL per pool token
+    pub rate: f64,
+    pub total_lamports: u64,
+    pub pool_token_supply: u64,
+    /// Unix timestamp of the crank that recorded the sample
+    pub recorded_at: u64,
+}
+
+/// Add `sample` to the history, replacing a sample from the same epoch.
+fn record_sample(history: &mut Vec<RateSample>, sample: RateSample) {
+    history.retain(|existing| existing.epoch != sample.epoch);
+    history.push(sample);
+    history.sort_by_key(|sample| sample.epoch);
+    if history.len() > MAX_SAMPLES {
+        let excess = history.len() - MAX_SAMPLES;
+        history.drain(..excess);
+    }
+}
+
+/// Annualized yield over the last `epochs` epochs of `history`, compounded
+/// over the time that actually passed between the two samples. The rate is
+/// already net of the epoch fee, which the pool mints as new pool tokens.
+pub fn trailing_apy(history: &[RateSample], epochs: u64) -> Option<f64> {
+    let latest = history.last()?;
+    let start_epoch = latest.epoch.checked_sub(epochs)?;
+    let start = history
+        .iter()
+        .rev()
+        .find(|sample| sample.epoch <= start_epoch)?;
+    let elapsed = latest.recorded_at.checked_sub(start.recorded_at)?;
+    if elapsed == 0 || start.rate <= 0.0 {
+        return None;
+    }
+    let growth = latest.rate / start.rate;
+    Some((growth.powf(SECONDS_PER_YEAR / elapsed as f64) - 1.0) * 100.0)
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TrailingApy {
+    pub epochs: u64,
+    /// Annual percentage yield, `None` until enough history is recorded
+    pub apy_pct: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PoolApy {
+    pub pool: String,
+    pub epoch: u64,
+    pub rate: f64,
+    pub trailing: Vec<TrailingApy>,
+}
+
+impl PoolApy {
+    pub fn from_history(pool: &str, history: &[RateSample]) -> Option<Self> {
+        let latest = history.last()?;
+        Some(Self {
+            pool: pool.to_string(),
+            epoch: latest.epoch,
+            rate: latest.rate,
+            trailing: APY_WINDOWS
+                .into_iter()
+                .map(|epochs| TrailingApy {
+                    epochs,
+                    apy_pct: trailing_apy(history, epochs),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Record the pool's exchange rate once its balance has been updated for
+/// `epoch`, whoever sent the update. The first sample of an epoch is kept so
+/// later cycles do not move its timestamp.
+pub async fn record_rate(
+    config: &Config,
+    state: &AppState,
+    stake_pool_address: &Pubkey,
+    epoch: u64,
+) -> Result<()> {
+    let pool = stake_pool_address.to_string();
+    let recorded = state
+        .store
+        .read(|data| {
+            data.rates
+                .get(&pool)
+                .is_some_and(|history| history.iter().any(|sample| sample.epoch == epoch))
+        })
+        .await;
+    if recorded {
+        return Ok(());
+    }
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    if stake_pool.last_update_epoch != epoch {
+        return Ok(());
+    }
+    let Some(rate) = exchange_rate(stake_pool.total_lamports, stake_pool.pool_token_supply) else {
+        return Ok(());
+    };
+    let sample = RateSample {
+        epoch,
+        rate,
+        total_lamports: stake_pool.total_lamports,
+        pool_token_supply: stake_pool.pool_token_supply,
+        recorded_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default(),
+    };
+    state
+        .store
+        .update(|data| record_sample(data.rates.entry(pool).or_default(), sample))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECONDS_PER_EPOCH: u64 = 2 * 24 * 60 * 60;
+
+    fn sample(epoch: u64, rate: f64) -> RateSample {
+        RateSample {
+            epoch,
+            rate,
+            total_lamports: 0,
+            pool_token_supply: 0,
+            recorded_at: epoch * SECONDS_PER_EPOCH,
+        }
+    }
+
+    #[test]
+    fn no_apy_without_enough_history() {
+        assert_eq!(trailing_apy(&[], 1), None);
+        assert_eq!(trailing_apy(&[sample(10, 1.0)], 1), None);
+        let history = [sample(10, 1.0), sample(11, 1.001)];
+        assert_eq!(trailing_apy(&history, 7), None);
+    }
+
+    #[test]
+    fn compounds_over_elapsed_time() {
+        let history = [sample(10, 1.0), sample(11, 1.001)];
+        let expected = (1.001f64.powf(SECONDS_PER_YEAR / SECONDS_PER_EPOCH as f64) - 1.0) * 100.0;
+        let apy = trailing_apy(&history, 1).unwrap();
+        assert!((apy - expected).abs() < 1e-9);
+        assert!(apy > 0.0);
+    }
+
+    #[test]
+    fn uses_the_latest_sample_at_or_before_the_window() {
+        // epoch 12 is missing, so the 2 epoch window starts at epoch 11
+        let history = [sample(10, 1.0), sample(11, 1.001), sample(13, 1.003)];
+        let from_11 = trailing_apy(&history[1..], 2).unwrap();
+        assert_eq!(trailing_apy(&history, 2), Some(from_11));
+        assert_eq!(
+            trailing_apy(&history, 3),
+            trailing_apy(&[sample(10, 1.0), sample(13, 1.003)], 3)
+        );
+    }
+
+    #[test]
+    fn no_apy_for_degenerate_samples() {
+        let mut history = [sample(10, 1.0), sample(11, 1.001)];
+        history[1].recorded_at = history[0].recorded_at;
+        assert_eq!(trailing_apy(&history, 1), None);
+        assert_eq!(trailing_apy(&[sample(10, 0.0), sample(11, 1.0)], 1), None);
+    }
+
+    #[test]
+    fn record_sample_replaces_and_orders() {
+        let mut history = Vec::new();
+        record_sample(&mut history, sample(11, 1.001));
+        record_sample(&mut history, sample(10, 1.0));
+        record_sample(&mut history, sample(11, 1.002));
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].epoch, 10);
+        assert_eq!(history[1].rate, 1.002);
+    }
+}
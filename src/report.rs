@@ -0,0 +1,516 @@
+use {
+    crate::{
+        AppState, Config, UpdateOutcome,
+        client::{get_stake_pool, get_validator_list},
+        store::Store,
+    },
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    solana_commitment_config::CommitmentConfig,
+    solana_native_token::Sol,
+    solana_pubkey::Pubkey,
+    solana_signature::Signature,
+    std::{str::FromStr, time::Duration},
+};
+
+/// Number of epoch summaries kept in the store
+const MAX_SUMMARIES: usize = 30;
+
+/// SOL per pool token, `None` for an empty pool.
+pub fn exchange_rate(total_lamports: u64, pool_token_supply: u64) -> Option<f64> {
+    (pool_token_supply > 0).then(|| total_lamports as f64 / pool_token_supply as f64)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoolSummary {
+    pub pool: String,
+    pub pool_name: String,
+    pub updated: bool,
+    pub validator_count: usize,
+    pub validators_updated: usize,
+    pub transactions_sent: usize,
+    pub transactions_failed: usize,
+    /// Update chunks built and those skipped because another cranker had
+    /// already run them
+    #[serde(default)]
+    pub chunks_total: usize,
+    #[serde(default)]
+    pub chunks_skipped: usize,
+    pub fees: u64,
+    pub duration_secs: f64,
+    /// SOL per pool token at the end of the previous epoch
+    pub previous_rate: Option<f64>,
+    /// SOL per pool token after this epoch's update
+    pub rate: Option<f64>,
+    /// Vote accounts whose stake info is still behind the current epoch
+    pub stale_validators: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl PoolSummary {
+    pub fn rate_change_pct(&self) -> Option<f64> {
+        match (self.previous_rate, self.rate) {
+            (Some(previous), Some(rate)) if previous > 0.0 => {
+                Some((rate - previous) / previous * 100.0)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EpochSummary {
+    pub epoch: u64,
+    pub pools_updated: usize,
+    pub pools_failed: usize,
+    pub validators_updated: usize,
+    pub transactions_sent: usize,
+    pub transactions_failed: usize,
+    #[serde(default)]
+    pub chunks_skipped: usize,
+    pub fees: u64,
+    pub duration_secs: f64,
+    pub pools: Vec<PoolSummary>,
+}
+
+impl EpochSummary {
+    pub fn new(epoch: u64, duration: Duration, pools: Vec<PoolSummary>) -> Self {
+        Self {
+            epoch,
+            pools_updated: pools.iter().filter(|pool| pool.updated).count(),
+            pools_failed: pools.iter().filter(|pool| !pool.updated).count(),
+            validators_updated: pools.iter().map(|pool| pool.validators_updated).sum(),
+            transactions_sent: pools.iter().map(|pool| pool.transactions_sent).sum(),
+            transactions_failed: pools.iter().map(|pool| pool.transactions_failed).sum(),
+            chunks_skipped: pools.iter().map(|pool| pool.chunks_skipped).sum(),
+            fees: pools.iter().map(|pool| pool.fees).sum(),
+            duration_secs: duration.as_secs_f64(),
+            pools,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            format!("Crank summary for epoch {}", self.epoch),
+            format!(
+                "Pools updated: {}, failed: {}, validators updated: {}",
+                self.pools_updated, self.pools_failed, self.validators_updated
+            ),
+            format!(
+                "Transactions sent: {}, failed: {}, fees: {}, duration: {:.1}s",
+                self.transactions_sent,
+                self.transactions_failed,
+                Sol(self.fees),
+                self.duration_secs
+            ),
+        ];
+        if self.chunks_skipped > 0 {
+            lines.push(format!(
+                "Update chunks skipped, already run by another cranker: {}",
+                self.chunks_skipped
+            ));
+        }
+        for pool in &self.pools {
+            let rate = match (pool.rate, pool.rate_change_pct()) {
+                (Some(rate), Some(change)) => format!("{rate:.9} SOL/token ({change:+.4}%)"),
+                (Some(rate), None) => format!("{rate:.9} SOL/token"),
+                _ => "unknown rate".to_string(),
+            };
+            let status = if pool.updated { "updated" } else { "FAILED" };
+            lines.push(format!(
+                "- {} {}: {}/{} validators updated, {}",
+                pool.pool_name, status, pool.validators_updated, pool.validator_count, rate
+            ));
+            if !pool.stale_validators.is_empty() {
+                lines.push(format!(
+                    "  stale validators: {}",
+                    pool.stale_validators.join(", ")
+                ));
+            }
+            if let Some(error) = &pool.error {
+                lines.push(format!("  error: {error}"));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// Re-read the pool after an update attempt to work out the exchange rate
+/// change and which validators were left behind.
+pub async fn summarize_pool(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    pool_name: &str,
+    epoch: u64,
+    outcome: &UpdateOutcome,
+    error: Option<String>,
+) -> Result<PoolSummary> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+
+    let stale_validators: Vec<String> = validator_list
+        .validators
+        .iter()
+        .filter(|validator| u64::from_le_bytes(validator.last_update_epoch.0) < epoch)
+        .map(|validator| validator.vote_account_address.to_string())
+        .collect();
+    let current = validator_list.validators.len() - stale_validators.len();
+
+    // once the pool balance is updated the previous epoch's totals move into
+    // the `last_epoch_*` fields
+    let updated = stake_pool.last_update_epoch == epoch;
+    let (previous_rate, rate) = if updated {
+        (
+            exchange_rate(
+                stake_pool.last_epoch_total_lamports,
+                stake_pool.last_epoch_pool_token_supply,
+            ),
+            exchange_rate(stake_pool.total_lamports, stake_pool.pool_token_supply),
+        )
+    } else {
+        (
+            exchange_rate(stake_pool.total_lamports, stake_pool.pool_token_supply),
+            None,
+        )
+    };
+
+    Ok(PoolSummary {
+        pool: stake_pool_address.to_string(),
+        pool_name: pool_name.to_string(),
+        updated: updated && error.is_none(),
+        validator_count: validator_list.validators.len(),
+        validators_updated: current.saturating_sub(outcome.validators_already_current),
+        transactions_sent: outcome.signatures.len(),
+        transactions_failed: outcome.failed_transactions,
+        chunks_total: outcome.chunks_total,
+        chunks_skipped: outcome.chunks_skipped,
+        fees: outcome.fees,
+        duration_secs: outcome.duration.as_secs_f64(),
+        previous_rate,
+        rate,
+        stale_validators,
+        error,
+    })
+}
+
+/// Update chunks sent and skipped by the cranker since the state file was
+/// created.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct ChunkCounters {
+    pub sent: u64,
+    pub skipped: u64,
+    /// Last epoch the pool's chunks were counted for
+    #[serde(default)]
+    pub epoch: u64,
+    /// Last epoch the pool was seen not yet updated
+    #[serde(default)]
+    pub stale_epoch: u64,
+}
+
+impl ChunkCounters {
+    /// Whether another cranker updated the pool after this one saw it stale
+    /// in `epoch` and before it counted any of its chunks.
+    fn updated_elsewhere(&self, epoch: u64) -> bool {
+        self.stale_epoch >= epoch && self.epoch < epoch
+    }
+}
+
+/// Most signatures `getSignatureStatuses` accepts per request
+const MAX_SIGNATURES_PER_REQUEST: usize = 256;
+
+/// Add the chunks of `outcome` to the pool's counters, also for an update
+/// that failed part way. Only chunks whose transaction confirmed count as
+/// sent.
+pub async fn record_chunks(
+    config: &Config,
+    state: &AppState,
+    stake_pool_address: &Pubkey,
+    epoch: u64,
+    outcome: &UpdateOutcome,
+) -> Result<()> {
+    let mut sent = 0;
+    for transactions in outcome
+        .chunk_transactions
+        .chunks(MAX_SIGNATURES_PER_REQUEST)
+    {
+        let signatures = transactions
+            .iter()
+            .map(|(signature, _)| Signature::from_str(signature))
+            .collect::<Result<Vec<_>, _>>()?;
+        let statuses = config
+            .rpc_client
+            .get_signature_statuses(&signatures)
+            .await?
+            .value;
+        sent += transactions
+            .iter()
+            .zip(statuses)
+            .filter(|(_, status)| {
+                status.as_ref().is_some_and(|status| {
+                    status.err.is_none()
+                        && status.satisfies_commitment(CommitmentConfig::confirmed())
+                })
+            })
+            .map(|((_, chunks), _)| *chunks as u64)
+            .sum::<u64>();
+    }
+    add_chunks(
+        &state.store,
+        stake_pool_address.to_string(),
+        epoch,
+        sent,
+        outcome.chunks_skipped as u64,
+    )
+    .await
+}
+
+/// Note that the pool was not yet updated for `epoch`, so its chunks count
+/// as skipped if another cranker runs them.
+pub async fn record_pool_stale(
+    store: &Store,
+    stake_pool_address: &Pubkey,
+    epoch: u64,
+) -> Result<()> {
+    let pool = stake_pool_address.to_string();
+    let recorded = store
+        .read(|data| {
+            data.chunk_counters
+                .get(&pool)
+                .is_some_and(|counters| counters.stale_epoch >= epoch)
+        })
+        .await;
+    if recorded {
+        return Ok(());
+    }
+    store
+        .update(|data| data.chunk_counters.entry(pool).or_default().stale_epoch = epoch)
+        .await
+}
+
+/// Count every chunk of a pool that another cranker fully updated for
+/// `epoch` as skipped, once per epoch. Nothing is counted unless this
+/// cranker saw the pool stale in `epoch`, such as on a fresh state file.
+pub async fn record_pool_already_updated(
+    config: &Config,
+    state: &AppState,
+    stake_pool_address: &Pubkey,
+    validator_list_address: &Pubkey,
+    epoch: u64,
+) -> Result<()> {
+    let pool = stake_pool_address.to_string();
+    let updated_elsewhere = state
+        .store
+        .read(|data| {
+            data.chunk_counters
+                .get(&pool)
+                .is_some_and(|counters| counters.updated_elsewhere(epoch))
+        })
+        .await;
+    if !updated_elsewhere {
+        return Ok(());
+    }
+    let validator_list = get_validator_list(&config.rpc_client, validator_list_address).await?;
+    let chunks = validator_list
+        .validators
+        .len()
+        .div_ceil(spl_stake_pool::MAX_VALIDATORS_TO_UPDATE) as u64;
+    add_chunks(&state.store, pool, epoch, 0, chunks).await
+}
+
+async fn add_chunks(
+    store: &Store,
+    pool: String,
+    epoch: u64,
+    sent: u64,
+    skipped: u64,
+) -> Result<()> {
+    store
+        .update(|data| {
+            let counters = data.chunk_counters.entry(pool).or_default();
+            counters.sent += sent;
+            counters.skipped += skipped;
+            counters.epoch = epoch;
+        })
+        .await
+}
+
+/// Store the summary for the API and send it through the default notifier.
+pub async fn publish(state: &AppState, summary: EpochSummary) -> Result<()> {
+    let message = state
+        .store
+        .update(|data| {
+            // a retry later in the same epoch is folded into that epoch's summary
+            let summary = match data
+                .summaries
+                .iter()
+                .position(|existing| existing.epoch == summary.epoch)
+            {
+                Some(index) => {
+                    let existing = data.summaries.remove(index);
+                    let mut pools: Vec<PoolSummary> = existing
+                        .pools
+                        .into_iter()
+                        .filter(|pool| summary.pools.iter().all(|new| new.pool != pool.pool))
+                        .collect();
+                    pools.extend(summary.pools);
+                    EpochSummary::new(
+                        summary.epoch,
+                        Duration::from_secs_f64(existing.duration_secs + summary.duration_secs),
+                        pools,
+                    )
+                }
+                None => summary,
+            };
+            let message = summary.render();
+            data.summaries.push(summary);
+            if data.summaries.len() > MAX_SUMMARIES {
+                let excess = data.summaries.len() - MAX_SUMMARIES;
+                data.summaries.drain(..excess);
+            }
+            message
+        })
+        .await?;
+    state.alerts.info(state.notifiers.global(), &message).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(name: &str, updated: bool, chunks_skipped: usize) -> PoolSummary {
+        PoolSummary {
+            pool: Pubkey::new_unique().to_string(),
+            pool_name: name.to_string(),
+            updated,
+            validator_count: 4,
+            validators_updated: 3,
+            transactions_sent: 2,
+            transactions_failed: usize::from(!updated),
+            chunks_total: 2,
+            chunks_skipped,
+            fees: 10_000,
+            duration_secs: 1.0,
+            previous_rate: Some(1.0),
+            rate: updated.then_some(1.01),
+            stale_validators: Vec::new(),
+            error: (!updated).then(|| "timed out".to_string()),
+        }
+    }
+
+    async fn counters_of(store: &Store, stake_pool_address: &Pubkey) -> ChunkCounters {
+        store
+            .read(|data| data.chunk_counters[&stake_pool_address.to_string()])
+            .await
+    }
+
+    #[test]
+    fn exchange_rate_of_an_empty_pool_is_unknown() {
+        assert_eq!(exchange_rate(2_000, 1_000), Some(2.0));
+        assert_eq!(exchange_rate(2_000, 0), None);
+    }
+
+    #[test]
+    fn rate_change_needs_both_rates() {
+        let updated = pool("a", true, 0);
+        assert!((updated.rate_change_pct().unwrap() - 1.0).abs() < 1e-9);
+        assert_eq!(pool("b", false, 0).rate_change_pct(), None);
+    }
+
+    #[test]
+    fn epoch_summary_adds_up_the_pools() {
+        let summary = EpochSummary::new(
+            7,
+            Duration::from_secs(2),
+            vec![pool("a", true, 1), pool("b", false, 0)],
+        );
+        assert_eq!(summary.pools_updated, 1);
+        assert_eq!(summary.pools_failed, 1);
+        assert_eq!(summary.validators_updated, 6);
+        assert_eq!(summary.transactions_sent, 4);
+        assert_eq!(summary.transactions_failed, 1);
+        assert_eq!(summary.chunks_skipped, 1);
+        assert_eq!(summary.fees, 20_000);
+
+        let message = summary.render();
+        assert!(message.contains("Update chunks skipped, already run by another cranker: 1"));
+        assert!(message.contains("- b FAILED: 3/4 validators updated, unknown rate"));
+        assert!(message.contains("  error: timed out"));
+    }
+
+    #[test]
+    fn skipped_chunks_are_only_rendered_when_there_are_some() {
+        let summary = EpochSummary::new(7, Duration::ZERO, vec![pool("a", true, 0)]);
+        assert!(!summary.render().contains("skipped"));
+    }
+
+    #[test]
+    fn pools_are_counted_as_updated_elsewhere_once_seen_stale() {
+        let cases = [
+            // (stale epoch, counted epoch, updated elsewhere)
+            (0, 0, false),
+            (7, 0, true),
+            (7, 6, true),
+            (7, 7, false),
+            (6, 6, false),
+        ];
+        for (stale_epoch, epoch, expected) in cases {
+            let counters = ChunkCounters {
+                stale_epoch,
+                epoch,
+                ..ChunkCounters::default()
+            };
+            assert_eq!(counters.updated_elsewhere(7), expected, "{counters:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn a_fresh_state_file_does_not_count_an_updated_pool() {
+        let store = Store::temp();
+        let stake_pool_address = Pubkey::new_unique();
+        assert!(store.read(|data| data.chunk_counters.is_empty()).await);
+
+        record_pool_stale(&store, &stake_pool_address, 7)
+            .await
+            .unwrap();
+        assert!(
+            counters_of(&store, &stake_pool_address)
+                .await
+                .updated_elsewhere(7)
+        );
+        assert!(
+            !counters_of(&store, &stake_pool_address)
+                .await
+                .updated_elsewhere(8)
+        );
+    }
+
+    #[tokio::test]
+    async fn counted_chunks_are_not_counted_again_in_the_epoch() {
+        let store = Store::temp();
+        let stake_pool_address = Pubkey::new_unique();
+        record_pool_stale(&store, &stake_pool_address, 7)
+            .await
+            .unwrap();
+        add_chunks(&store, stake_pool_address.to_string(), 7, 2, 1)
+            .await
+            .unwrap();
+        add_chunks(&store, stake_pool_address.to_string(), 7, 1, 0)
+            .await
+            .unwrap();
+
+        let counters = counters_of(&store, &stake_pool_address).await;
+        assert_eq!((counters.sent, counters.skipped), (3, 1));
+        assert!(!counters.updated_elsewhere(7));
+
+        // seeing the pool stale again in the same epoch keeps it counted
+        record_pool_stale(&store, &stake_pool_address, 7)
+            .await
+            .unwrap();
+        assert!(
+            !counters_of(&store, &stake_pool_address)
+                .await
+                .updated_elsewhere(7)
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use {
+    crate::{
+        AppState, Config,
+        client::{get_stake_pool, get_validator_list},
+    },
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    solana_pubkey::Pubkey,
+    spl_stake_pool::state::ValidatorList,
+    std::collections::HashMap,
+};
+
+/// Number of epochs of rewards kept per pool
+const MAX_EPOCHS: usize = 30;
+
+/// A validator's balances in the validator list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidatorBalance {
+    pub active_lamports: u64,
+    pub transient_lamports: u64,
+    pub last_update_epoch: u64,
+}
+
+/// Balances of every validator in the list, keyed by vote account.
+pub fn snapshot(validator_list: &ValidatorList) -> HashMap<String, ValidatorBalance> {
+    validator_list
+        .validators
+        .iter()
+        .map(|validator| {
+            (
+                validator.vote_account_address.to_string(),
+                ValidatorBalance {
+                    active_lamports: u64::from_le_bytes(validator.active_stake_lamports.0),
+                    transient_lamports: u64::from_le_bytes(validator.transient_stake_lamports.0),
+                    last_update_epoch: u64::from_le_bytes(validator.last_update_epoch.0),
+                },
+            )
+        })
+        .collect()
+}
+
+/// The balances a pool's next update is measured against, kept between
+/// cycles so rewards are attributed even when another cranker sends the
+/// update.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RewardBaseline {
+    /// Epoch the pool was last updated in when the balances were read
+    pub epoch: u64,
+    pub balances: HashMap<String, ValidatorBalance>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ValidatorReward {
+    pub vote_account: String,
+    /// Active stake before the update
+    pub active_lamports: u64,
+    /// Change in active stake made by the update, negative after slashing
+    pub reward_lamports: i64,
+    /// Reward as a share of the active stake, in percent
+    pub epoch_yield_pct: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EpochRewards {
+    pub epoch: u64,
+    pub validators: Vec<ValidatorReward>,
+    /// Validators whose transient stake moved during the update, so the
+    /// change in active stake is not all rewards
+    pub unattributed: Vec<String>,
+}
+
+/// Compare the balances from before the update with the list after it.
+/// Only validators the update moved into `epoch` are counted.
+pub fn attribute(
+    epoch: u64,
+    before: &HashMap<String, ValidatorBalance>,
+    after: &HashMap<String, ValidatorBalance>,
+) -> EpochRewards {
+    let mut validators = Vec::new();
+    let mut unattributed = Vec::new();
+    for (vote_account, after) in after {
+        let Some(before) = before.get(vote_account) else {
+            continue;
+        };
+        if before.last_update_epoch >= epoch || after.last_update_epoch != epoch {
+            continue;
+        }
+        // a merged or withdrawn transient account changes active stake by
+        // more than the rewards
+        if before.transient_lamports != 0 || after.transient_lamports != 0 {
+            unattributed.push(vote_account.clone());
+            continue;
+        }
+        let reward_lamports = after.active_lamports as i64 - before.active_lamports as i64;
+        validators.push(ValidatorReward {
+            vote_account: vote_account.clone(),
+            active_lamports: before.active_lamports,
+            reward_lamports,
+            epoch_yield_pct: if before.active_lamports == 0 {
+                0.0
+            } else {
+                reward_lamports as f64 / before.active_lamports as f64 * 100.0
+            },
+        });
+    }
+    validators.sort_by(|a, b| b.epoch_yield_pct.total_cmp(&a.epoch_yield_pct));
+    unattributed.sort();
+    EpochRewards {
+        epoch,
+        validators,
+        unattributed,
+    }
+}
+
+/// A validator's realized return over the recorded epochs.
+#[derive(Clone, Debug, Serialize)]
+pub struct ValidatorYield {
+    pub vote_account: String,
+    pub epochs: usize,
+    pub total_reward_lamports: i64,
+    pub average_epoch_yield_pct: f64,
+    pub latest_epoch_yield_pct: f64,
+}
+
+/// Rank validators by their average epoch yield, best first.
+pub fn rank(history: &[EpochRewards]) -> Vec<ValidatorYield> {
+    let mut yields: HashMap<&str, ValidatorYield> = HashMap::new();
+    // oldest first, so the last epoch seen is the latest
+    for epoch in history {
+        for reward in &epoch.validators {
+            let entry = yields
+                .entry(&reward.vote_account)
+                .or_insert_with(|| ValidatorYield {
+                    vote_account: reward.vote_account.clone(),
+                    epochs: 0,
+                    total_reward_lamports: 0,
+                    average_epoch_yield_pct: 0.0,
+                    latest_epoch_yield_pct: 0.0,
+                });
+            entry.epochs += 1;
+            entry.total_reward_lamports += reward.reward_lamports;
+            entry.average_epoch_yield_pct += reward.epoch_yield_pct;
+            entry.latest_epoch_yield_pct = reward.epoch_yield_pct;
+        }
+    }
+    let mut ranked: Vec<ValidatorYield> = yields
+        .into_values()
+        .map(|mut entry| {
+            entry.average_epoch_yield_pct /= entry.epochs as f64;
+            entry
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.average_epoch_yield_pct
+            .total_cmp(&a.average_epoch_yield_pct)
+    });
+    ranked
+}
+
+/// Attribute the rewards of the pool's update for `epoch`, whoever sent it,
+/// and keep the current balances as the baseline for the next update.
+/// `balances_before` are the balances read right before this cranker's own
+/// update, the stored baseline is used otherwise.
+pub async fn record_rewards(
+    config: &Config,
+    state: &AppState,
+    stake_pool_address: &Pubkey,
+    epoch: u64,
+    balances_before: Option<&HashMap<String, ValidatorBalance>>,
+) -> Result<()> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let balances = snapshot(&validator_list);
+    let pool = stake_pool_address.to_string();
+    let (recorded, baseline) = state
+        .store
+        .read(|data| {
+            (
+                data.validator_rewards
+                    .get(&pool)
+                    .is_some_and(|history| history.iter().any(|rewards| rewards.epoch == epoch)),
+                data.reward_baselines.get(&pool).cloned(),
+            )
+        })
+        .await;
+
+    let before = match balances_before.filter(|before| !before.is_empty()) {
+        Some(before) => Some(before),
+        None => baseline
+            .as_ref()
+            .filter(|baseline| baseline.epoch < epoch)
+            .map(|baseline| &baseline.balances),
+    };
+    let rewards = before
+        .filter(|_| stake_pool.last_update_epoch == epoch && !recorded)
+        .map(|before| attribute(epoch, before, &balances));
+
+    // validators a partial update already moved past the pool keep their
+    // earlier balances, the rest of the update is measured against them
+    let mut next = RewardBaseline {
+        epoch: stake_pool.last_update_epoch,
+        balances,
+    };
+    for (vote_account, balance) in next.balances.iter_mut() {
+        let previous = baseline
+            .as_ref()
+            .filter(|_| balance.last_update_epoch > stake_pool.last_update_epoch)
+            .and_then(|baseline| baseline.balances.get(vote_account));
+        if let Some(previous) = previous {
+            *balance = *previous;
+        }
+    }
+    let baseline_changed = baseline
+        .as_ref()
+        .is_none_or(|baseline| baseline.epoch != next.epoch || baseline.balances != next.balances);
+    if rewards.is_none() && !baseline_changed {
+        return Ok(());
+    }
+
+    state
+        .store
+        .update(|data| {
+            if let Some(rewards) = rewards {
+                let history = data.validator_rewards.entry(pool.clone()).or_default();
+                history.retain(|existing| existing.epoch != rewards.epoch);
+                history.push(rewards);
+                history.sort_by_key(|rewards| rewards.epoch);
+                if history.len() > MAX_EPOCHS {
+                    let excess = history.len() - MAX_EPOCHS;
+                    history.drain(..excess);
+                }
+            }
+            data.reward_baselines.insert(pool, next);
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH: u64 = 10;
+
+    fn balance(active_lamports: u64, transient_lamports: u64, epoch: u64) -> ValidatorBalance {
+        ValidatorBalance {
+            active_lamports,
+            transient_lamports,
+            last_update_epoch: epoch,
+        }
+    }
+
+    fn balances(entries: &[(&str, ValidatorBalance)]) -> HashMap<String, ValidatorBalance> {
+        entries
+            .iter()
+            .map(|(vote_account, balance)| (vote_account.to_string(), *balance))
+            .collect()
+    }
+
+    fn yields(rewards: &EpochRewards) -> Vec<(&str, i64, f64)> {
+        rewards
+            .validators
+            .iter()
+            .map(|reward| {
+                (
+                    reward.vote_account.as_str(),
+                    reward.reward_lamports,
+                    reward.epoch_yield_pct,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rewards_are_weighted_by_active_stake() {
+        let before = balances(&[
+            ("small", balance(1_000, 0, EPOCH - 1)),
+            ("large", balance(4_000, 0, EPOCH - 1)),
+            ("slashed", balance(2_000, 0, EPOCH - 1)),
+            ("empty", balance(0, 0, EPOCH - 1)),
+        ]);
+        let after = balances(&[
+            ("small", balance(1_010, 0, EPOCH)),
+            ("large", balance(4_010, 0, EPOCH)),
+            ("slashed", balance(1_990, 0, EPOCH)),
+            ("empty", balance(0, 0, EPOCH)),
+        ]);
+        let rewards = attribute(EPOCH, &before, &after);
+        assert_eq!(
+            yields(&rewards),
+            vec![
+                ("small", 10, 1.0),
+                ("large", 10, 0.25),
+                ("empty", 0, 0.0),
+                ("slashed", -10, -0.5),
+            ]
+        );
+        assert!(rewards.unattributed.is_empty());
+    }
+
+    #[test]
+    fn only_validators_moved_into_the_epoch_are_counted() {
+        let before = balances(&[
+            ("updated", balance(1_000, 0, EPOCH - 1)),
+            ("already", balance(1_000, 0, EPOCH)),
+            ("stale", balance(1_000, 0, EPOCH - 1)),
+            ("moving", balance(1_000, 500, EPOCH - 1)),
+            ("merged", balance(1_000, 0, EPOCH - 1)),
+        ]);
+        let after = balances(&[
+            ("updated", balance(1_010, 0, EPOCH)),
+            ("already", balance(1_020, 0, EPOCH)),
+            ("stale", balance(1_000, 0, EPOCH - 1)),
+            ("moving", balance(1_510, 0, EPOCH)),
+            ("merged", balance(1_010, 300, EPOCH)),
+            ("joined", balance(1_000, 0, EPOCH)),
+        ]);
+        let rewards = attribute(EPOCH, &before, &after);
+        assert_eq!(yields(&rewards), vec![("updated", 10, 1.0)]);
+        assert_eq!(rewards.unattributed, vec!["merged", "moving"]);
+    }
+
+    fn epoch(epoch: u64, yields: &[(&str, i64, f64)]) -> EpochRewards {
+        EpochRewards {
+            epoch,
+            validators: yields
+                .iter()
+                .map(
+                    |&(vote_account, reward_lamports, epoch_yield_pct)| ValidatorReward {
+                        vote_account: vote_account.to_string(),
+                        active_lamports: 0,
+                        reward_lamports,
+                        epoch_yield_pct,
+                    },
+                )
+                .collect(),
+            unattributed: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn rank_orders_by_average_yield() {
+        let history = [
+            epoch(8, &[("a", 10, 0.1), ("b", 30, 0.3)]),
+            epoch(9, &[("a", 50, 0.5), ("b", 10, 0.1), ("c", 15, 0.15)]),
+        ];
+        let ranking = rank(&history);
+        let ranked: Vec<(&str, usize, i64, f64)> = ranking
+            .iter()
+            .map(|entry| {
+                (
+                    entry.vote_account.as_str(),
+                    entry.epochs,
+                    entry.total_reward_lamports,
+                    entry.latest_epoch_yield_pct,
+                )
+            })
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![("a", 2, 60, 0.5), ("b", 2, 40, 0.1), ("c", 1, 15, 0.15)]
+        );
+        for (entry, average) in ranking.iter().zip([0.3, 0.2, 0.15]) {
+            assert!((entry.average_epoch_yield_pct - average).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rank_of_no_history_is_empty() {
+        assert!(rank(&[]).is_empty());
+    }
+}
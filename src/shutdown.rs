@@ -0,0 +1,131 @@
+use {
+    std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    tokio::{
+        sync::Notify,
+        time::{Duration, sleep},
+    },
+};
+
+#[derive(Default)]
+struct Inner {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+/// Whether the process is shutting down, shared by the signal handler, the
+/// worker and the HTTP server.
+#[derive(Clone, Default)]
+pub struct Shutdown(Arc<Inner>);
+
+impl Shutdown {
+    pub fn request(&self) {
+        self.0.requested.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.requested.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once shutdown has been requested.
+    pub async fn requested(&self) {
+        // register before checking the flag so a concurrent request is not missed
+        let notified = self.0.notify.notified();
+        if self.is_requested() {
+            return;
+        }
+        notified.await;
+    }
+
+    /// Sleep for `duration` unless shutdown is requested first. Returns
+    /// whether the whole duration passed.
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        tokio::select! {
+            () = sleep(duration) => true,
+            () = self.requested() => false,
+        }
+    }
+}
+
+/// Wait for SIGINT or, on unix, SIGTERM.
+pub async fn signal() -> std::io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|()| "SIGINT"),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.map(|()| "SIGINT")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tokio::time::timeout};
+
+    /// Far longer than any test waits, so only a shutdown request ends it
+    const LONG: Duration = Duration::from_secs(3600);
+
+    #[tokio::test]
+    async fn a_pending_sleep_returns_promptly_on_request() {
+        let shutdown = Shutdown::default();
+        let sleeping = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.sleep(LONG).await }
+        });
+        // let the sleep start waiting before the request
+        tokio::task::yield_now().await;
+        shutdown.request();
+        let completed = timeout(Duration::from_secs(1), sleeping)
+            .await
+            .expect("sleep returned on request")
+            .unwrap();
+        assert!(!completed);
+    }
+
+    #[tokio::test]
+    async fn requested_returns_at_once_after_a_request() {
+        let shutdown = Shutdown::default();
+        shutdown.request();
+        timeout(Duration::from_secs(1), shutdown.requested())
+            .await
+            .expect("requested returned");
+        assert!(!shutdown.sleep(LONG).await);
+    }
+
+    #[tokio::test]
+    async fn sleep_completes_without_a_request() {
+        let shutdown = Shutdown::default();
+        assert!(shutdown.sleep(Duration::from_millis(1)).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn a_request_racing_the_check_is_not_missed() {
+        // a waiter that checked the flag before registering for the
+        // notification would miss a request landing in between and hang
+        for _ in 0..1000 {
+            let shutdown = Shutdown::default();
+            let waiter = tokio::spawn({
+                let shutdown = shutdown.clone();
+                async move { shutdown.requested().await }
+            });
+            let requester = tokio::spawn({
+                let shutdown = shutdown.clone();
+                async move { shutdown.request() }
+            });
+            requester.await.unwrap();
+            timeout(Duration::from_secs(1), waiter)
+                .await
+                .expect("waiter saw the request")
+                .unwrap();
+        }
+    }
+}
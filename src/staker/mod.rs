@@ -0,0 +1,53 @@
+pub mod preferred;
+pub mod rebalance;
+pub mod reserve;
+pub mod validator_set;
+
+use {
+    crate::{Config, checked_transaction_with_signers, send_transaction},
+    anyhow::Result,
+    solana_instruction::Instruction,
+    solana_signer::Signer,
+};
+
+/// The pool staker signer, required for every instruction in this module.
+pub fn staker(config: &Config) -> Result<&dyn Signer> {
+    config
+        .staker
+        .as_deref()
+        .map(|staker| staker as &dyn Signer)
+        .ok_or_else(|| anyhow::anyhow!("STAKER_PRIVATE_KEY is not set"))
+}
+
+/// Send each instruction in its own transaction signed by the fee payer and
+/// the staker, or only simulate them when `dry_run` is set.
+pub async fn send_staker_instructions(
+    config: &Config,
+    instructions: Vec<Instruction>,
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let staker = staker(config)?;
+    let mut signatures = Vec::new();
+    for instruction in instructions {
+        let (transaction, _) = checked_transaction_with_signers(
+            config,
+            &[instruction],
+            &[config.fee_payer.as_ref(), staker],
+        )
+        .await?;
+        if dry_run {
+            let result = config
+                .rpc_client
+                .simulate_transaction(&transaction)
+                .await?
+                .value;
+            tracing::info!("Simulated staker transaction: {:?}", result);
+            if let Some(err) = result.err {
+                anyhow::bail!("Staker transaction simulation failed: {}", err);
+            }
+        } else {
+            signatures.extend(send_transaction(config, transaction).await?);
+        }
+    }
+    Ok(signatures)
+}
@@ -0,0 +1,201 @@
+use {
+    super::{rebalance::Weights, send_staker_instructions, staker},
+    crate::{
+        Config,
+        client::{get_stake_pool, get_validator_list},
+        config::RebalanceStrategy,
+    },
+    anyhow::Result,
+    solana_pubkey::Pubkey,
+    solana_signer::Signer,
+    spl_stake_pool::{instruction::PreferredValidatorType, state::StakeStatus},
+    std::{collections::HashMap, fmt},
+};
+
+/// A change to one of the pool's preferred validators.
+#[derive(Clone, Debug)]
+pub struct PreferredChange {
+    pub validator_type: PreferredValidatorType,
+    pub previous: Option<Pubkey>,
+    pub vote_account: Pubkey,
+}
+
+impl fmt::Display for PreferredChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.validator_type {
+            PreferredValidatorType::Deposit => "deposit",
+            PreferredValidatorType::Withdraw => "withdraw",
+        };
+        match self.previous {
+            Some(previous) => write!(
+                f,
+                "preferred {kind} validator {previous} -> {}",
+                self.vote_account
+            ),
+            None => write!(f, "preferred {kind} validator set to {}", self.vote_account),
+        }
+    }
+}
+
+/// Pick the validators furthest below and above their target share:
+/// deposits go to the first, withdrawals come from the second.
+fn choose(
+    stakes: &[(Pubkey, u64)],
+    weights: &HashMap<Pubkey, f64>,
+) -> (Option<Pubkey>, Option<Pubkey>) {
+    let total_weight: f64 = stakes
+        .iter()
+        .map(|(vote_account, _)| weights.get(vote_account).copied().unwrap_or_default())
+        .sum();
+    if total_weight <= 0.0 {
+        return (None, None);
+    }
+    let total_lamports: u64 = stakes.iter().map(|(_, lamports)| lamports).sum();
+
+    let mut deposit: Option<(Pubkey, f64)> = None;
+    let mut withdraw: Option<(Pubkey, f64)> = None;
+    for (vote_account, lamports) in stakes {
+        let weight = weights.get(vote_account).copied().unwrap_or_default();
+        let target = total_lamports as f64 * weight / total_weight;
+        let gap = target - *lamports as f64;
+        if gap > 0.0 && deposit.is_none_or(|(_, best)| gap > best) {
+            deposit = Some((*vote_account, gap));
+        }
+        if gap < 0.0 && withdraw.is_none_or(|(_, best)| -gap > best) {
+            withdraw = Some((*vote_account, -gap));
+        }
+    }
+    (
+        deposit.map(|(vote_account, _)| vote_account),
+        withdraw.map(|(vote_account, _)| vote_account),
+    )
+}
+
+/// Point the pool's preferred deposit and withdraw validators at the most
+/// under-target and most over-target validators. A preferred validator is
+/// left alone when every validator is on target.
+pub async fn rotate_preferred_validators(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    strategy: RebalanceStrategy,
+    configured_weights: &HashMap<String, f64>,
+    max_commission: Option<u8>,
+    dry_run: bool,
+) -> Result<Vec<PreferredChange>> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+
+    // without a rebalance strategy every validator gets an equal share
+    let strategy = match strategy {
+        RebalanceStrategy::None => RebalanceStrategy::Equal,
+        strategy => strategy,
+    };
+    let weights =
+        Weights::fetch(config, strategy, configured_weights, max_commission, epoch).await?;
+
+    let stakes: Vec<(Pubkey, u64)> = validator_list
+        .validators
+        .iter()
+        .filter(|validator| {
+            matches!(
+                StakeStatus::try_from(validator.status),
+                Ok(StakeStatus::Active)
+            )
+        })
+        .map(|validator| {
+            (
+                validator.vote_account_address,
+                u64::from_le_bytes(validator.active_stake_lamports.0)
+                    .saturating_add(u64::from_le_bytes(validator.transient_stake_lamports.0)),
+            )
+        })
+        .collect();
+    let validator_weights = stakes
+        .iter()
+        .map(|(vote_account, _)| (*vote_account, weights.get(vote_account)))
+        .collect();
+    let (deposit, withdraw) = choose(&stakes, &validator_weights);
+
+    let mut changes = Vec::new();
+    for (validator_type, previous, chosen) in [
+        (
+            PreferredValidatorType::Deposit,
+            stake_pool.preferred_deposit_validator_vote_address,
+            deposit,
+        ),
+        (
+            PreferredValidatorType::Withdraw,
+            stake_pool.preferred_withdraw_validator_vote_address,
+            withdraw,
+        ),
+    ] {
+        if let Some(vote_account) = chosen.filter(|chosen| previous != Some(*chosen)) {
+            changes.push(PreferredChange {
+                validator_type,
+                previous,
+                vote_account,
+            });
+        }
+    }
+
+    let staker = staker(config)?.pubkey();
+    let instructions = changes
+        .iter()
+        .map(|change| {
+            spl_stake_pool::instruction::set_preferred_validator(
+                &config.stake_pool_program_id,
+                stake_pool_address,
+                &staker,
+                &stake_pool.validator_list,
+                change.validator_type,
+                Some(change.vote_account),
+            )
+        })
+        .collect();
+    send_staker_instructions(config, instructions, dry_run).await?;
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choose_picks_the_largest_gaps() {
+        let [a, b, c] = [(); 3].map(|()| Pubkey::new_unique());
+        let weights =
+            |values: [f64; 3]| HashMap::from([(a, values[0]), (b, values[1]), (c, values[2])]);
+        for (lamports, weights, expected) in [
+            ([100, 300, 200], weights([1.0; 3]), (Some(a), Some(b))),
+            ([200, 200, 200], weights([1.0; 3]), (None, None)),
+            ([100, 300, 0], weights([3.0, 1.0, 0.0]), (Some(a), Some(b))),
+            // a zero weight puts the whole stake above target
+            (
+                [300, 300, 300],
+                weights([1.0, 1.0, 0.0]),
+                (Some(a), Some(c)),
+            ),
+            ([100, 300, 200], weights([0.0; 3]), (None, None)),
+        ] {
+            let stakes = [(a, lamports[0]), (b, lamports[1]), (c, lamports[2])];
+            assert_eq!(choose(&stakes, &weights), expected, "{lamports:?}");
+        }
+    }
+
+    #[test]
+    fn validators_without_a_weight_are_drained() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let stakes = [(a, 100), (b, 100)];
+        assert_eq!(
+            choose(&stakes, &HashMap::from([(a, 1.0)])),
+            (Some(a), Some(b))
+        );
+    }
+
+    #[test]
+    fn empty_pool_has_no_preferred_validators() {
+        assert_eq!(choose(&[], &HashMap::new()), (None, None));
+    }
+}
@@ -0,0 +1,446 @@
+use {
+    super::{reserve, send_staker_instructions},
+    crate::{
+        Config,
+        client::{get_stake_pool, get_validator_list},
+        config::RebalanceStrategy,
+    },
+    anyhow::Result,
+    solana_native_token::Sol,
+    solana_program::stake::state::StakeStateV2,
+    solana_pubkey::Pubkey,
+    spl_stake_pool::state::StakeStatus,
+    std::{
+        collections::{HashMap, HashSet},
+        fmt,
+        num::NonZeroU32,
+    },
+};
+
+/// Number of completed epochs of vote credits used by the performance strategy
+const PERFORMANCE_EPOCHS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Increase,
+    Decrease,
+}
+
+#[derive(Clone, Debug)]
+pub struct RebalanceAction {
+    pub vote_account: Pubkey,
+    pub direction: Direction,
+    pub lamports: u64,
+    pub current: u64,
+    pub target: u64,
+}
+
+impl fmt::Display for RebalanceAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} by {} (current {}, target {})",
+            self.direction,
+            self.vote_account,
+            Sol(self.lamports),
+            Sol(self.current),
+            Sol(self.target)
+        )
+    }
+}
+
+/// A validator that can take part in this epoch's rebalance.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub vote_account: Pubkey,
+    pub validator_seed: Option<NonZeroU32>,
+    pub transient_seed: u64,
+    pub active_lamports: u64,
+    pub weight: f64,
+}
+
+/// Limits that every increase and decrease has to respect.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Smallest delegation the stake pool program accepts
+    pub minimum_delegation: u64,
+    /// Rent exemption for a stake account
+    pub stake_rent: u64,
+    /// Ignore moves smaller than this
+    pub min_move: u64,
+}
+
+/// Work out the increases and decreases that move each candidate towards
+/// its weighted share of the stake.
+///
+/// Decreases land in the reserve at the next epoch boundary, so increases
+/// are only funded from what the reserve can spare right now. Every move is
+/// at least the minimum delegation and decreases leave the validator stake
+/// account with rent plus the minimum delegation.
+pub fn plan(
+    candidates: &[Candidate],
+    reserve_available: u64,
+    limits: Limits,
+) -> Vec<RebalanceAction> {
+    let total_weight: f64 = candidates.iter().map(|candidate| candidate.weight).sum();
+    if total_weight <= 0.0 {
+        return Vec::new();
+    }
+    let total_lamports: u64 = candidates
+        .iter()
+        .map(|candidate| candidate.active_lamports)
+        .sum::<u64>()
+        .saturating_add(reserve_available);
+    let validator_floor = limits.stake_rent.saturating_add(limits.minimum_delegation);
+    let min_move = limits.min_move.max(limits.minimum_delegation);
+
+    let mut decreases = Vec::new();
+    let mut increases = Vec::new();
+    for candidate in candidates {
+        let target = (total_lamports as f64 * candidate.weight / total_weight) as u64;
+        let target = target.max(validator_floor);
+        if target < candidate.active_lamports {
+            let lamports = (candidate.active_lamports - target)
+                .min(candidate.active_lamports.saturating_sub(validator_floor));
+            if lamports >= min_move {
+                decreases.push(RebalanceAction {
+                    vote_account: candidate.vote_account,
+                    direction: Direction::Decrease,
+                    lamports,
+                    current: candidate.active_lamports,
+                    target,
+                });
+            }
+        } else if target - candidate.active_lamports >= min_move {
+            increases.push(RebalanceAction {
+                vote_account: candidate.vote_account,
+                direction: Direction::Increase,
+                lamports: target - candidate.active_lamports,
+                current: candidate.active_lamports,
+                target,
+            });
+        }
+    }
+
+    // fund the biggest shortfalls first, each increase also pays rent for
+    // its transient stake account
+    increases.sort_by(|a, b| b.lamports.cmp(&a.lamports));
+    let mut budget = reserve_available;
+    let mut funded = Vec::new();
+    for mut action in increases {
+        let available = budget.saturating_sub(limits.stake_rent);
+        action.lamports = action.lamports.min(available);
+        if action.lamports < min_move {
+            continue;
+        }
+        budget -= action.lamports + limits.stake_rent;
+        funded.push(action);
+    }
+
+    decreases.extend(funded);
+    decreases
+}
+
+/// The share of the pool's stake each validator should get.
+pub struct Weights {
+    strategy: RebalanceStrategy,
+    weights: HashMap<String, f64>,
+    /// Vote accounts charging more than the allowed commission
+    excluded: HashSet<String>,
+}
+
+impl Weights {
+    /// Load the weights for `strategy`. Validators charging more than
+    /// `max_commission` get a zero weight so their stake drains to the others.
+    pub async fn fetch(
+        config: &Config,
+        strategy: RebalanceStrategy,
+        configured_weights: &HashMap<String, f64>,
+        max_commission: Option<u8>,
+        epoch: u64,
+    ) -> Result<Self> {
+        let weights = match strategy {
+            RebalanceStrategy::Performance => performance_weights(config, epoch).await?,
+            _ => configured_weights.clone(),
+        };
+        let excluded = match max_commission {
+            Some(max_commission) => {
+                let vote_accounts = config.rpc_client.get_vote_accounts().await?;
+                vote_accounts
+                    .current
+                    .into_iter()
+                    .chain(vote_accounts.delinquent)
+                    .filter(|vote_account| vote_account.commission > max_commission)
+                    .map(|vote_account| vote_account.vote_pubkey)
+                    .collect()
+            }
+            None => HashSet::new(),
+        };
+        Ok(Self {
+            strategy,
+            weights,
+            excluded,
+        })
+    }
+
+    pub fn get(&self, vote_account: &Pubkey) -> f64 {
+        let vote_account = vote_account.to_string();
+        match self.strategy {
+            _ if self.excluded.contains(&vote_account) => 0.0,
+            RebalanceStrategy::Equal => 1.0,
+            RebalanceStrategy::Weighted => self.weights.get(&vote_account).copied().unwrap_or(1.0),
+            _ => self.weights.get(&vote_account).copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// Fetch the pool, compute the plan for `strategy` and send the increase and
+/// decrease instructions as the staker. Increases leave `reserve_target_pct`
+/// of the pool in the reserve.
+#[allow(clippy::too_many_arguments)]
+pub async fn rebalance_pool(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    strategy: RebalanceStrategy,
+    configured_weights: &HashMap<String, f64>,
+    max_commission: Option<u8>,
+    reserve_target_pct: Option<f64>,
+    min_move: u64,
+    dry_run: bool,
+) -> Result<Vec<RebalanceAction>> {
+    if strategy == RebalanceStrategy::None {
+        return Ok(Vec::new());
+    }
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+
+    let weights =
+        Weights::fetch(config, strategy, configured_weights, max_commission, epoch).await?;
+
+    let candidates: Vec<Candidate> = validator_list
+        .validators
+        .iter()
+        .filter(|validator| {
+            // a validator can only have one transient stake account at a time
+            matches!(
+                StakeStatus::try_from(validator.status),
+                Ok(StakeStatus::Active)
+            ) && u64::from_le_bytes(validator.transient_stake_lamports.0) == 0
+                && u64::from_le_bytes(validator.last_update_epoch.0) == epoch
+        })
+        .map(|validator| {
+            let vote_account = validator.vote_account_address;
+            let weight = weights.get(&vote_account);
+            Candidate {
+                vote_account,
+                validator_seed: NonZeroU32::new(u32::from_le_bytes(
+                    validator.validator_seed_suffix.0,
+                )),
+                transient_seed: u64::from_le_bytes(validator.transient_seed_suffix.0),
+                active_lamports: u64::from_le_bytes(validator.active_stake_lamports.0),
+                weight,
+            }
+        })
+        .collect();
+
+    let stake_rent = config
+        .rpc_client
+        .get_minimum_balance_for_rent_exemption(StakeStateV2::size_of())
+        .await?;
+    let minimum_delegation =
+        spl_stake_pool::minimum_delegation(config.rpc_client.get_stake_minimum_delegation().await?);
+    // the reserve has to stay rent exempt and keep its liquidity target
+    let reserve_target = reserve_target_pct
+        .map(|pct| reserve::target_lamports(stake_pool.total_lamports, pct))
+        .unwrap_or_default();
+    let reserve_available = config
+        .rpc_client
+        .get_balance(&stake_pool.reserve_stake)
+        .await?
+        .saturating_sub(stake_rent)
+        .saturating_sub(reserve_target);
+
+    let actions = plan(
+        &candidates,
+        reserve_available,
+        Limits {
+            minimum_delegation,
+            stake_rent,
+            min_move,
+        },
+    );
+    for action in &actions {
+        tracing::info!("Rebalance {}: {}", stake_pool_address, action);
+    }
+
+    let seeds: HashMap<Pubkey, &Candidate> = candidates
+        .iter()
+        .map(|candidate| (candidate.vote_account, candidate))
+        .collect();
+    let instructions = actions
+        .iter()
+        .map(|action| {
+            let candidate = seeds[&action.vote_account];
+            match action.direction {
+                Direction::Increase => {
+                    spl_stake_pool::instruction::increase_validator_stake_with_vote(
+                        &config.stake_pool_program_id,
+                        &stake_pool,
+                        stake_pool_address,
+                        &action.vote_account,
+                        action.lamports,
+                        candidate.validator_seed,
+                        candidate.transient_seed,
+                    )
+                }
+                // builds a `DecreaseValidatorStakeWithReserve` instruction
+                Direction::Decrease => {
+                    spl_stake_pool::instruction::decrease_validator_stake_with_vote(
+                        &config.stake_pool_program_id,
+                        &stake_pool,
+                        stake_pool_address,
+                        &action.vote_account,
+                        action.lamports,
+                        candidate.validator_seed,
+                        candidate.transient_seed,
+                    )
+                }
+            }
+        })
+        .collect();
+    send_staker_instructions(config, instructions, dry_run).await?;
+
+    Ok(actions)
+}
+
+/// Weight each vote account by the credits it earned over the last few
+/// completed epochs, net of commission. Delinquent validators get nothing.
+async fn performance_weights(config: &Config, epoch: u64) -> Result<HashMap<String, f64>> {
+    let vote_accounts = config.rpc_client.get_vote_accounts().await?;
+    Ok(vote_accounts
+        .current
+        .into_iter()
+        .map(|vote_account| {
+            let credits: u64 = vote_account
+                .epoch_credits
+                .iter()
+                .rev()
+                .filter(|(credit_epoch, _, _)| *credit_epoch < epoch)
+                .take(PERFORMANCE_EPOCHS)
+                .map(|(_, credits, previous_credits)| credits.saturating_sub(*previous_credits))
+                .sum();
+            let weight =
+                credits as f64 * f64::from(100u8.saturating_sub(vote_account.commission)) / 100.0;
+            (vote_account.vote_pubkey, weight)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        minimum_delegation: 1_000,
+        stake_rent: 100,
+        min_move: 0,
+    };
+
+    fn candidate(active_lamports: u64, weight: f64) -> Candidate {
+        Candidate {
+            vote_account: Pubkey::new_unique(),
+            validator_seed: None,
+            transient_seed: 0,
+            active_lamports,
+            weight,
+        }
+    }
+
+    fn moves(actions: &[RebalanceAction], direction: Direction) -> Vec<(Pubkey, u64)> {
+        actions
+            .iter()
+            .filter(|action| action.direction == direction)
+            .map(|action| (action.vote_account, action.lamports))
+            .collect()
+    }
+
+    #[test]
+    fn balanced_pool_needs_no_moves() {
+        let candidates = [candidate(5_000, 1.0), candidate(5_000, 1.0)];
+        assert!(plan(&candidates, 0, LIMITS).is_empty());
+    }
+
+    #[test]
+    fn zero_weights_plan_nothing() {
+        let candidates = [candidate(9_000, 0.0), candidate(1_000, 0.0)];
+        assert!(plan(&candidates, 10_000, LIMITS).is_empty());
+    }
+
+    #[test]
+    fn increases_wait_for_reserve_funds() {
+        let candidates = [candidate(9_000, 1.0), candidate(1_000, 1.0)];
+        let actions = plan(&candidates, 0, LIMITS);
+        assert_eq!(
+            moves(&actions, Direction::Decrease),
+            vec![(candidates[0].vote_account, 4_000)]
+        );
+        assert!(moves(&actions, Direction::Increase).is_empty());
+    }
+
+    #[test]
+    fn increases_are_limited_by_reserve_and_rent() {
+        let candidates = [candidate(2_000, 1.0), candidate(2_000, 1.0)];
+        let actions = plan(&candidates, 6_000, LIMITS);
+        let increases = moves(&actions, Direction::Increase);
+        assert_eq!(increases.len(), 2);
+        let funded: u64 = increases.iter().map(|(_, lamports)| lamports).sum();
+        // each increase also pays rent for its transient stake account
+        assert_eq!(funded, 3_000 + 2_800);
+        assert!(funded + 2 * LIMITS.stake_rent <= 6_000);
+    }
+
+    #[test]
+    fn biggest_shortfall_is_funded_first() {
+        let candidates = [
+            candidate(1_100, 1.0),
+            candidate(3_100, 1.0),
+            candidate(5_800, 1.0),
+        ];
+        let actions = plan(&candidates, 3_000, LIMITS);
+        assert_eq!(
+            moves(&actions, Direction::Increase),
+            vec![(candidates[0].vote_account, 2_900)]
+        );
+        assert_eq!(
+            moves(&actions, Direction::Decrease),
+            vec![(candidates[2].vote_account, 5_800 - 4_333)]
+        );
+    }
+
+    #[test]
+    fn decreases_leave_rent_and_minimum_delegation() {
+        let candidates = [candidate(5_000, 0.0), candidate(5_000, 1.0)];
+        let actions = plan(&candidates, 0, LIMITS);
+        let floor = LIMITS.stake_rent + LIMITS.minimum_delegation;
+        assert_eq!(
+            moves(&actions, Direction::Decrease),
+            vec![(candidates[0].vote_account, 5_000 - floor)]
+        );
+        assert_eq!(actions[0].target, floor);
+    }
+
+    #[test]
+    fn small_moves_are_ignored() {
+        // 400 lamports off target, below the minimum delegation
+        let candidates = [candidate(5_400, 1.0), candidate(4_600, 1.0)];
+        assert!(plan(&candidates, 0, LIMITS).is_empty());
+
+        let limits = Limits {
+            min_move: 5_000,
+            ..LIMITS
+        };
+        let candidates = [candidate(9_000, 1.0), candidate(1_000, 1.0)];
+        assert!(plan(&candidates, 0, limits).is_empty());
+    }
+}
@@ -0,0 +1,412 @@
+use {
+    super::{
+        rebalance::{Candidate, Direction, Limits, RebalanceAction},
+        send_staker_instructions,
+    },
+    crate::{
+        AppState, Config,
+        alerts::Severity,
+        client::{get_stake_pool, get_validator_list},
+        notifier::Notifier,
+    },
+    anyhow::Result,
+    solana_native_token::Sol,
+    solana_program::stake::state::StakeStateV2,
+    solana_pubkey::Pubkey,
+    spl_stake_pool::state::{StakeStatus, ValidatorStakeInfo},
+    std::num::NonZeroU32,
+};
+
+/// Accounts per `getMultipleAccounts` request
+const MAX_ACCOUNTS_PER_REQUEST: usize = 100;
+
+/// The reserve balance of a pool against its liquidity target.
+#[derive(Clone, Debug)]
+pub struct ReserveStatus {
+    /// Lamports in the reserve above its rent exemption
+    pub reserve_lamports: u64,
+    /// Transient stake of decreases already sent, which lands in the reserve
+    /// at the next epoch boundary. Only counted when the reserve is managed
+    pub incoming_lamports: u64,
+    pub total_lamports: u64,
+    pub target_lamports: u64,
+    /// Stake moved to or from the reserve this epoch
+    pub actions: Vec<RebalanceAction>,
+}
+
+impl ReserveStatus {
+    /// Share of the pool that can be withdrawn instantly, in percent.
+    pub fn liquidity_pct(&self) -> f64 {
+        if self.total_lamports == 0 {
+            return 0.0;
+        }
+        self.reserve_lamports as f64 / self.total_lamports as f64 * 100.0
+    }
+}
+
+/// Lamports of a pool with `total_lamports` that should sit in the reserve.
+pub fn target_lamports(total_lamports: u64, target_pct: f64) -> u64 {
+    (total_lamports as f64 * target_pct / 100.0) as u64
+}
+
+/// Work out the moves that bring the reserve back to `target_lamports`.
+///
+/// A short reserve is refilled by decreasing the largest validators first,
+/// the stake lands in the reserve at the next epoch boundary. The
+/// `incoming_lamports` of decreases already in flight count towards the
+/// refill but cannot be delegated yet. An excess is delegated to the
+/// smallest validator in one increase.
+pub fn plan(
+    candidates: &[Candidate],
+    reserve_lamports: u64,
+    incoming_lamports: u64,
+    target_lamports: u64,
+    limits: Limits,
+) -> Vec<RebalanceAction> {
+    let validator_floor = limits.stake_rent.saturating_add(limits.minimum_delegation);
+    let min_move = limits.min_move.max(limits.minimum_delegation);
+    let expected_lamports = reserve_lamports.saturating_add(incoming_lamports);
+    let mut actions = Vec::new();
+
+    if expected_lamports < target_lamports {
+        let mut needed = target_lamports - expected_lamports;
+        if needed < min_move {
+            return actions;
+        }
+        let mut by_stake: Vec<&Candidate> = candidates.iter().collect();
+        by_stake.sort_by(|a, b| b.active_lamports.cmp(&a.active_lamports));
+        for candidate in by_stake {
+            let lamports = needed.min(candidate.active_lamports.saturating_sub(validator_floor));
+            if lamports < limits.minimum_delegation {
+                continue;
+            }
+            actions.push(RebalanceAction {
+                vote_account: candidate.vote_account,
+                direction: Direction::Decrease,
+                lamports,
+                current: candidate.active_lamports,
+                target: candidate.active_lamports - lamports,
+            });
+            needed -= lamports;
+            if needed < limits.minimum_delegation {
+                break;
+            }
+        }
+    } else {
+        // the increase also pays rent for the transient stake account
+        let excess = reserve_lamports
+            .saturating_sub(target_lamports)
+            .saturating_sub(limits.stake_rent);
+        if excess < min_move {
+            return actions;
+        }
+        if let Some(candidate) = candidates
+            .iter()
+            .min_by_key(|candidate| candidate.active_lamports)
+        {
+            actions.push(RebalanceAction {
+                vote_account: candidate.vote_account,
+                direction: Direction::Increase,
+                lamports: excess,
+                current: candidate.active_lamports,
+                target: candidate.active_lamports.saturating_add(excess),
+            });
+        }
+    }
+    actions
+}
+
+/// Compare the pool's reserve with `target_pct` of its total lamports and,
+/// when `manage` is set, move stake to or from the reserve as the staker.
+/// Runs after the rebalance, whose decreases are counted as incoming.
+pub async fn manage_reserve(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    target_pct: f64,
+    manage: bool,
+    min_move: u64,
+    dry_run: bool,
+) -> Result<ReserveStatus> {
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let stake_rent = config
+        .rpc_client
+        .get_minimum_balance_for_rent_exemption(StakeStateV2::size_of())
+        .await?;
+    let reserve_lamports = config
+        .rpc_client
+        .get_balance(&stake_pool.reserve_stake)
+        .await?
+        .saturating_sub(stake_rent);
+    let mut status = ReserveStatus {
+        reserve_lamports,
+        incoming_lamports: 0,
+        total_lamports: stake_pool.total_lamports,
+        target_lamports: target_lamports(stake_pool.total_lamports, target_pct),
+        actions: Vec::new(),
+    };
+    if !manage {
+        return Ok(status);
+    }
+
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+    let epoch = config.rpc_client.get_epoch_info().await?.epoch;
+    let candidates: Vec<Candidate> = validator_list
+        .validators
+        .iter()
+        .filter(|validator| {
+            // a validator can only have one transient stake account at a time
+            matches!(
+                StakeStatus::try_from(validator.status),
+                Ok(StakeStatus::Active)
+            ) && u64::from_le_bytes(validator.transient_stake_lamports.0) == 0
+                && u64::from_le_bytes(validator.last_update_epoch.0) == epoch
+        })
+        .map(|validator| Candidate {
+            vote_account: validator.vote_account_address,
+            validator_seed: NonZeroU32::new(u32::from_le_bytes(validator.validator_seed_suffix.0)),
+            transient_seed: u64::from_le_bytes(validator.transient_seed_suffix.0),
+            active_lamports: u64::from_le_bytes(validator.active_stake_lamports.0),
+            weight: 1.0,
+        })
+        .collect();
+    let minimum_delegation =
+        spl_stake_pool::minimum_delegation(config.rpc_client.get_stake_minimum_delegation().await?);
+    status.incoming_lamports = incoming_decreases(
+        config,
+        stake_pool_address,
+        &validator_list.validators,
+        epoch,
+    )
+    .await?;
+
+    status.actions = plan(
+        &candidates,
+        status.reserve_lamports,
+        status.incoming_lamports,
+        status.target_lamports,
+        Limits {
+            minimum_delegation,
+            stake_rent,
+            min_move,
+        },
+    );
+
+    let instructions = status
+        .actions
+        .iter()
+        .filter_map(|action| {
+            let candidate = candidates
+                .iter()
+                .find(|candidate| candidate.vote_account == action.vote_account)?;
+            Some(match action.direction {
+                Direction::Increase => {
+                    spl_stake_pool::instruction::increase_validator_stake_with_vote(
+                        &config.stake_pool_program_id,
+                        &stake_pool,
+                        stake_pool_address,
+                        &action.vote_account,
+                        action.lamports,
+                        candidate.validator_seed,
+                        candidate.transient_seed,
+                    )
+                }
+                Direction::Decrease => {
+                    spl_stake_pool::instruction::decrease_validator_stake_with_vote(
+                        &config.stake_pool_program_id,
+                        &stake_pool,
+                        stake_pool_address,
+                        &action.vote_account,
+                        action.lamports,
+                        candidate.validator_seed,
+                        candidate.transient_seed,
+                    )
+                }
+            })
+        })
+        .collect();
+    send_staker_instructions(config, instructions, dry_run).await?;
+
+    Ok(status)
+}
+
+/// Transient stake this epoch's decreases are moving back to the reserve.
+/// Increases hold transient stake too, only deactivating accounts count.
+async fn incoming_decreases(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    validators: &[ValidatorStakeInfo],
+    epoch: u64,
+) -> Result<u64> {
+    let addresses: Vec<Pubkey> = validators
+        .iter()
+        .filter(|validator| {
+            u64::from_le_bytes(validator.transient_stake_lamports.0) > 0
+                && u64::from_le_bytes(validator.last_update_epoch.0) == epoch
+        })
+        .map(|validator| {
+            spl_stake_pool::find_transient_stake_program_address(
+                &config.stake_pool_program_id,
+                &validator.vote_account_address,
+                stake_pool_address,
+                u64::from_le_bytes(validator.transient_seed_suffix.0),
+            )
+            .0
+        })
+        .collect();
+    let mut incoming = 0u64;
+    for chunk in addresses.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+        for account in config
+            .rpc_client
+            .get_multiple_accounts(chunk)
+            .await?
+            .into_iter()
+            .flatten()
+        {
+            let deactivating = matches!(
+                bincode::deserialize::<StakeStateV2>(&account.data),
+                Ok(StakeStateV2::Stake(_, stake, _)) if stake.delegation.deactivation_epoch != u64::MAX
+            );
+            if deactivating {
+                incoming = incoming.saturating_add(account.lamports);
+            }
+        }
+    }
+    Ok(incoming)
+}
+
+/// Alert while the reserve of the pool holds less than `alert_pct` of its
+/// lamports and resolve once it recovers. Runs every cycle, so a reserve
+/// drained mid-epoch is caught whoever cranked the pool.
+pub async fn check_liquidity(
+    config: &Config,
+    state: &AppState,
+    notifier: &dyn Notifier,
+    stake_pool_address: &Pubkey,
+    pool_name: &str,
+    alert_pct: f64,
+) -> Result<()> {
+    let status = manage_reserve(config, stake_pool_address, 0.0, false, 0, true).await?;
+    let alert_key = format!("reserve-low:{stake_pool_address}");
+    let liquidity_pct = status.liquidity_pct();
+    if liquidity_pct < alert_pct {
+        state
+            .alerts
+            .raise(
+                notifier,
+                &alert_key,
+                Severity::Warn,
+                &format!(
+                    "Reserve of stake pool {} holds {} ({:.2}% of the pool), below the {}% threshold",
+                    pool_name,
+                    Sol(status.reserve_lamports),
+                    liquidity_pct,
+                    alert_pct
+                ),
+            )
+            .await;
+    } else {
+        state
+            .alerts
+            .resolve(
+                notifier,
+                &alert_key,
+                &format!(
+                    "Reserve of stake pool {pool_name} is back at {liquidity_pct:.2}% of the pool"
+                ),
+            )
+            .await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        minimum_delegation: 1_000,
+        stake_rent: 100,
+        min_move: 0,
+    };
+
+    fn candidate(active_lamports: u64) -> Candidate {
+        Candidate {
+            vote_account: Pubkey::new_unique(),
+            validator_seed: None,
+            transient_seed: 0,
+            active_lamports,
+            weight: 1.0,
+        }
+    }
+
+    fn moves(actions: &[RebalanceAction]) -> Vec<(Pubkey, Direction, u64)> {
+        actions
+            .iter()
+            .map(|action| (action.vote_account, action.direction, action.lamports))
+            .collect()
+    }
+
+    #[test]
+    fn reserve_on_target_needs_no_moves() {
+        let candidates = [candidate(10_000)];
+        assert!(plan(&candidates, 5_000, 0, 5_000, LIMITS).is_empty());
+    }
+
+    #[test]
+    fn short_reserve_decreases_the_largest_validators_first() {
+        let candidates = [candidate(3_000), candidate(8_000), candidate(5_000)];
+        let actions = plan(&candidates, 1_000, 0, 11_000, LIMITS);
+        // each validator keeps its rent and minimum delegation
+        assert_eq!(
+            moves(&actions),
+            vec![
+                (candidates[1].vote_account, Direction::Decrease, 6_900),
+                (candidates[2].vote_account, Direction::Decrease, 3_100),
+            ]
+        );
+        assert_eq!(actions[0].target, 1_100);
+    }
+
+    #[test]
+    fn incoming_decreases_count_towards_a_short_reserve() {
+        let candidates = [candidate(20_000)];
+        assert!(plan(&candidates, 1_000, 4_000, 5_000, LIMITS).is_empty());
+        assert_eq!(
+            moves(&plan(&candidates, 1_000, 2_000, 5_000, LIMITS)),
+            vec![(candidates[0].vote_account, Direction::Decrease, 2_000)]
+        );
+    }
+
+    #[test]
+    fn incoming_decreases_are_not_delegated() {
+        let candidates = [candidate(20_000)];
+        assert!(plan(&candidates, 5_000, 9_000, 5_000, LIMITS).is_empty());
+    }
+
+    #[test]
+    fn excess_goes_to_the_smallest_validator() {
+        let candidates = [candidate(8_000), candidate(3_000)];
+        let actions = plan(&candidates, 9_000, 0, 5_000, LIMITS);
+        // the increase pays rent for its transient stake account
+        assert_eq!(
+            moves(&actions),
+            vec![(candidates[1].vote_account, Direction::Increase, 3_900)]
+        );
+        assert_eq!(actions[0].target, 6_900);
+    }
+
+    #[test]
+    fn small_moves_are_ignored() {
+        let candidates = [candidate(20_000)];
+        // below the minimum delegation either way
+        assert!(plan(&candidates, 4_500, 0, 5_000, LIMITS).is_empty());
+        assert!(plan(&candidates, 5_900, 0, 5_000, LIMITS).is_empty());
+
+        let limits = Limits {
+            min_move: 10_000,
+            ..LIMITS
+        };
+        assert!(plan(&candidates, 0, 0, 5_000, limits).is_empty());
+        assert!(plan(&candidates, 12_000, 0, 5_000, limits).is_empty());
+    }
+}
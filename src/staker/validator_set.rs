@@ -0,0 +1,290 @@
+use {
+    super::send_staker_instructions,
+    crate::{
+        Config,
+        client::{get_stake_pool, get_validator_list},
+    },
+    anyhow::{Context, Result},
+    solana_pubkey::Pubkey,
+    spl_stake_pool::state::{StakeStatus, ValidatorStakeInfo},
+    std::{collections::HashSet, fmt, num::NonZeroU32, str::FromStr},
+};
+
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// In the allowlist but not in the pool
+    Add(Pubkey),
+    /// Active in the pool but not in the allowlist
+    Remove {
+        vote_account: Pubkey,
+        validator_seed: Option<NonZeroU32>,
+        transient_seed: u64,
+    },
+    /// In the allowlist but still being removed from the pool, it can be
+    /// added back once the removal completes
+    PendingRemoval(Pubkey),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Add(vote_account) => write!(f, "+ add    {vote_account}"),
+            Change::Remove { vote_account, .. } => write!(f, "- remove {vote_account}"),
+            Change::PendingRemoval(vote_account) => {
+                write!(f, "~ wait   {vote_account} (removal in progress)")
+            }
+        }
+    }
+}
+
+/// The changes needed to make a pool's validator list match its allowlist.
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub stake_pool_address: Pubkey,
+    pub changes: Vec<Change>,
+    /// Number of validators the list can hold
+    pub max_validators: u32,
+    /// Number of entries in the list before the changes
+    pub current_validators: usize,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.changes
+            .iter()
+            .all(|change| matches!(change, Change::PendingRemoval(_)))
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Stake pool {} ({}/{} validators)",
+            self.stake_pool_address, self.current_validators, self.max_validators
+        )?;
+        if self.changes.is_empty() {
+            return writeln!(f, "  no changes, validator list matches the allowlist");
+        }
+        for change in &self.changes {
+            writeln!(f, "  {change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the on-chain validator list with `allowlist`.
+pub async fn plan(
+    config: &Config,
+    stake_pool_address: &Pubkey,
+    allowlist: &[String],
+) -> Result<Plan> {
+    let desired = allowlist
+        .iter()
+        .map(|vote_account| {
+            Pubkey::from_str(vote_account)
+                .with_context(|| format!("Invalid vote account in allowlist: {vote_account}"))
+        })
+        .collect::<Result<HashSet<Pubkey>>>()?;
+
+    let stake_pool = get_stake_pool(&config.rpc_client, stake_pool_address).await?;
+    let validator_list = get_validator_list(&config.rpc_client, &stake_pool.validator_list).await?;
+
+    Ok(Plan {
+        stake_pool_address: *stake_pool_address,
+        changes: diff(&desired, &validator_list.validators),
+        max_validators: validator_list.header.max_validators,
+        current_validators: validator_list.validators.len(),
+    })
+}
+
+/// The changes that turn the listed `validators` into `desired`, removals and
+/// pending removals in list order followed by additions sorted by address.
+fn diff(desired: &HashSet<Pubkey>, validators: &[ValidatorStakeInfo]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut listed = HashSet::new();
+    for validator in validators {
+        let vote_account = validator.vote_account_address;
+        listed.insert(vote_account);
+        let active = matches!(
+            StakeStatus::try_from(validator.status),
+            Ok(StakeStatus::Active)
+        );
+        match (desired.contains(&vote_account), active) {
+            (true, false) => changes.push(Change::PendingRemoval(vote_account)),
+            (false, true) => changes.push(Change::Remove {
+                vote_account,
+                validator_seed: NonZeroU32::new(u32::from_le_bytes(
+                    validator.validator_seed_suffix.0,
+                )),
+                transient_seed: u64::from_le_bytes(validator.transient_seed_suffix.0),
+            }),
+            _ => {}
+        }
+    }
+    let mut additions: Vec<Pubkey> = desired.difference(&listed).copied().collect();
+    additions.sort();
+    changes.extend(additions.into_iter().map(Change::Add));
+    changes
+}
+
+/// Fail when the additions in `plan` would not fit in the validator list.
+fn check_capacity(plan: &Plan) -> Result<()> {
+    let additions = plan
+        .changes
+        .iter()
+        .filter(|change| matches!(change, Change::Add(_)))
+        .count();
+    let removals = plan
+        .changes
+        .iter()
+        .filter(|change| matches!(change, Change::Remove { .. }))
+        .count();
+    // removed validators keep their slot until they are cleaned up at the
+    // next epoch update, so they still count against the limit
+    if plan.current_validators + additions > plan.max_validators as usize {
+        anyhow::bail!(
+            "Adding {} validators to stake pool {} would exceed its limit of {} ({} listed, {} being removed)",
+            additions,
+            plan.stake_pool_address,
+            plan.max_validators,
+            plan.current_validators,
+            removals
+        );
+    }
+    Ok(())
+}
+
+/// Send the staker instructions for `plan`, removals first.
+pub async fn apply(config: &Config, plan: &Plan, dry_run: bool) -> Result<Vec<String>> {
+    check_capacity(plan)?;
+
+    let stake_pool = get_stake_pool(&config.rpc_client, &plan.stake_pool_address).await?;
+    let mut instructions = Vec::new();
+    for change in &plan.changes {
+        if let Change::Remove {
+            vote_account,
+            validator_seed,
+            transient_seed,
+        } = change
+        {
+            instructions.push(
+                spl_stake_pool::instruction::remove_validator_from_pool_with_vote(
+                    &config.stake_pool_program_id,
+                    &stake_pool,
+                    &plan.stake_pool_address,
+                    vote_account,
+                    *validator_seed,
+                    *transient_seed,
+                ),
+            );
+        }
+    }
+    for change in &plan.changes {
+        if let Change::Add(vote_account) = change {
+            instructions.push(
+                spl_stake_pool::instruction::add_validator_to_pool_with_vote(
+                    &config.stake_pool_program_id,
+                    &stake_pool,
+                    &plan.stake_pool_address,
+                    vote_account,
+                    None,
+                ),
+            );
+        }
+    }
+
+    send_staker_instructions(config, instructions, dry_run).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator(vote_account: Pubkey, status: StakeStatus) -> ValidatorStakeInfo {
+        ValidatorStakeInfo {
+            vote_account_address: vote_account,
+            status: status.into(),
+            ..ValidatorStakeInfo::default()
+        }
+    }
+
+    fn plan(changes: Vec<Change>, current_validators: usize, max_validators: u32) -> Plan {
+        Plan {
+            stake_pool_address: Pubkey::new_unique(),
+            changes,
+            max_validators,
+            current_validators,
+        }
+    }
+
+    #[test]
+    fn diff_matches_the_allowlist() {
+        let kept = Pubkey::new_unique();
+        let dropped = Pubkey::new_unique();
+        let readded = Pubkey::new_unique();
+        let gone = Pubkey::new_unique();
+        let mut new = [Pubkey::new_unique(), Pubkey::new_unique()];
+        new.sort();
+        let desired = HashSet::from([kept, readded, new[1], new[0]]);
+        let validators = [
+            validator(kept, StakeStatus::Active),
+            validator(dropped, StakeStatus::Active),
+            validator(readded, StakeStatus::DeactivatingValidator),
+            validator(gone, StakeStatus::ReadyForRemoval),
+        ];
+
+        let changes: Vec<String> = diff(&desired, &validators)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                Change::Remove {
+                    vote_account: dropped,
+                    validator_seed: None,
+                    transient_seed: 0,
+                }
+                .to_string(),
+                Change::PendingRemoval(readded).to_string(),
+                Change::Add(new[0]).to_string(),
+                Change::Add(new[1]).to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn matching_list_needs_no_changes() {
+        let vote_account = Pubkey::new_unique();
+        let validators = [validator(vote_account, StakeStatus::Active)];
+        assert!(diff(&HashSet::from([vote_account]), &validators).is_empty());
+    }
+
+    #[test]
+    fn only_pending_removals_is_an_empty_plan() {
+        let pending = plan(vec![Change::PendingRemoval(Pubkey::new_unique())], 1, 1);
+        assert!(pending.is_empty());
+        let adding = plan(vec![Change::Add(Pubkey::new_unique())], 0, 1);
+        assert!(!adding.is_empty());
+    }
+
+    #[test]
+    fn removed_validators_still_take_a_slot() {
+        let remove = Change::Remove {
+            vote_account: Pubkey::new_unique(),
+            validator_seed: None,
+            transient_seed: 0,
+        };
+        let add = || Change::Add(Pubkey::new_unique());
+        for (changes, current_validators, max_validators, fits) in [
+            (vec![add()], 1, 2, true),
+            (vec![add(), add()], 1, 2, false),
+            (vec![remove.clone(), add()], 2, 2, false),
+            (vec![remove, add()], 2, 3, true),
+        ] {
+            let plan = plan(changes, current_validators, max_validators);
+            assert_eq!(check_capacity(&plan).is_ok(), fits, "{plan}");
+        }
+    }
+}
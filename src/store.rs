@@ -0,0 +1,149 @@
+use {
+    crate::{
+        alerts::ActiveAlert,
+        audit::AuditReport,
+        balance::BalanceSample,
+        monitor::{commission::CommissionSample, delinquency::DelinquencyRecord},
+        rates::RateSample,
+        report::{ChunkCounters, EpochSummary},
+        rewards::{EpochRewards, RewardBaseline},
+        supervisor::WorkerRestart,
+    },
+    anyhow::{Context, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{HashMap, HashSet},
+        path::PathBuf,
+        sync::Arc,
+    },
+    tokio::sync::RwLock,
+};
+
+/// Everything the cranker remembers between cycles and restarts.
+#[derive(Default, Serialize, Deserialize)]
+pub struct StoreData {
+    /// One fee payer balance sample per epoch, oldest first
+    #[serde(default)]
+    pub fee_payer_balances: Vec<BalanceSample>,
+
+    /// Conditions that have been raised and not yet resolved, keyed by alert key
+    #[serde(default)]
+    pub alerts: HashMap<String, ActiveAlert>,
+
+    /// Crank summaries of the most recent epochs, oldest first
+    #[serde(default)]
+    pub summaries: Vec<EpochSummary>,
+
+    /// Pool validators that stopped voting, keyed by `<pool>:<vote account>`
+    #[serde(default)]
+    pub delinquency: HashMap<String, DelinquencyRecord>,
+
+    /// Commission per epoch of every pool validator, keyed by vote account
+    #[serde(default)]
+    pub commissions: HashMap<String, Vec<CommissionSample>>,
+
+    /// Scheduled fee changes that were already announced
+    #[serde(default)]
+    pub announced_fee_changes: HashSet<String>,
+
+    /// Latest invariant audit of each pool, keyed by pool address
+    #[serde(default)]
+    pub audits: HashMap<String, AuditReport>,
+
+    /// Exchange rate after each epoch's update per pool, oldest first
+    #[serde(default)]
+    pub rates: HashMap<String, Vec<RateSample>>,
+
+    /// Rewards earned by each validator per epoch per pool, oldest first
+    #[serde(default)]
+    pub validator_rewards: HashMap<String, Vec<EpochRewards>>,
+
+    /// Validator balances each pool's next update is measured against
+    #[serde(default)]
+    pub reward_baselines: HashMap<String, RewardBaseline>,
+
+    /// Address lookup table created by the cranker per pool
+    #[serde(default)]
+    pub lookup_tables: HashMap<String, String>,
+
+    /// Update chunks sent and skipped per pool
+    #[serde(default)]
+    pub chunk_counters: HashMap<String, ChunkCounters>,
+
+    /// Epoch of the last transient stake cleanup attempt per pool
+    #[serde(default)]
+    pub transient_cleanups: HashMap<String, u64>,
+
+    /// Most recent worker restarts, oldest first
+    #[serde(default)]
+    pub worker_restarts: Vec<WorkerRestart>,
+}
+
+/// Cloneable handle to the JSON file backed state shared by the worker and
+/// the HTTP server.
+#[derive(Clone)]
+pub struct Store {
+    path: PathBuf,
+    data: Arc<RwLock<StoreData>>,
+}
+
+impl Store {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let data = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Failed to parse state file {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => StoreData::default(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read state file {}", path.display()));
+            }
+        };
+        Ok(Self {
+            path,
+            data: Arc::new(RwLock::new(data)),
+        })
+    }
+
+    /// An empty store backed by a file of its own in the temp directory.
+    #[cfg(any(test, feature = "dev-context-only-utils"))]
+    pub fn temp() -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "cranker-state-{}-{}.json",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_file(&path);
+        Self::load(path).expect("empty state file")
+    }
+
+    /// Run `f` against a read-only view of the state.
+    pub async fn read<R>(&self, f: impl FnOnce(&StoreData) -> R) -> R {
+        let data = self.data.read().await;
+        f(&data)
+    }
+
+    /// Write the current state to disk, on shutdown.
+    pub async fn flush(&self) -> Result<()> {
+        self.update(|_| ()).await
+    }
+
+    /// Run `f` against the state and persist the result to disk.
+    pub async fn update<R>(&self, f: impl FnOnce(&mut StoreData) -> R) -> Result<R> {
+        let mut data = self.data.write().await;
+        let result = f(&mut data);
+        let bytes = serde_json::to_vec_pretty(&*data).context("Failed to serialize state")?;
+        // write to a sibling file first so a crash never leaves a truncated state file
+        let tmp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, bytes)
+            .await
+            .with_context(|| format!("Failed to write state file {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, &self.path)
+            .await
+            .with_context(|| format!("Failed to replace state file {}", self.path.display()))?;
+        Ok(result)
+    }
+}
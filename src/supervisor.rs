@@ -0,0 +1,202 @@
+use {
+    crate::{AppState, alerts::Severity},
+    serde::{Deserialize, Serialize},
+    std::{
+        any::Any,
+        future::Future,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    tokio::{
+        task::JoinHandle,
+        time::{Duration, Instant, sleep},
+    },
+};
+
+pub const WORKER_ALERT_KEY: &str = "worker-stopped";
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// A worker that ran at least this long is considered healthy again and the
+/// next restart starts from the initial backoff
+const HEALTHY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Restarts kept in the state file
+const MAX_RECORDED: usize = 20;
+
+/// A worker task that stopped and was restarted.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorkerRestart {
+    /// Unix timestamp of the stop
+    pub at: u64,
+    /// Panic message, or why the task ended otherwise
+    pub message: String,
+    /// Consecutive restarts since the worker was last healthy
+    pub attempt: u32,
+    pub backoff_secs: u64,
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panic with a non-string payload".to_string()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Aborts the running worker when the supervisor is dropped, which a join
+/// handle alone would leave running detached.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Run the task built by `start` until shutdown, restarting it with an
+/// exponential backoff whenever it panics or returns early. Each restart is
+/// recorded in the state file and raised as a critical alert, which the
+/// worker resolves once it completes a cycle.
+pub async fn supervise<F, Fut>(state: AppState, start: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    supervise_with_backoff(state, INITIAL_BACKOFF, start).await
+}
+
+/// [`supervise`], with the first restart after `initial_backoff`.
+async fn supervise_with_backoff<F, Fut>(state: AppState, initial_backoff: Duration, mut start: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = initial_backoff;
+    let mut attempt = 0u32;
+    loop {
+        let started = Instant::now();
+        let mut worker = AbortOnDrop(tokio::spawn(start()));
+        let result = (&mut worker.0).await;
+        if state.shutdown.is_requested() {
+            return;
+        }
+        let message = match result {
+            Ok(()) => "the worker returned unexpectedly".to_string(),
+            Err(err) if err.is_panic() => panic_message(err.into_panic()),
+            Err(err) => err.to_string(),
+        };
+        if started.elapsed() >= HEALTHY_AFTER {
+            backoff = initial_backoff;
+            attempt = 0;
+        }
+        attempt = attempt.saturating_add(1);
+        tracing::error!(
+            "Worker stopped: {}, restarting in {:?} (attempt {})",
+            message,
+            backoff,
+            attempt
+        );
+
+        let restart = WorkerRestart {
+            at: now(),
+            message: message.clone(),
+            attempt,
+            backoff_secs: backoff.as_secs(),
+        };
+        let recorded = state
+            .store
+            .update(|data| {
+                data.worker_restarts.push(restart);
+                let excess = data.worker_restarts.len().saturating_sub(MAX_RECORDED);
+                data.worker_restarts.drain(..excess);
+            })
+            .await;
+        if let Err(err) = recorded {
+            tracing::error!("Failed to record the worker restart: {:#?}", err);
+        }
+        state
+            .alerts
+            .raise(
+                state.notifiers.global(),
+                WORKER_ALERT_KEY,
+                Severity::Critical,
+                &format!(
+                    "Cranker worker stopped: {message}. Restarting in {backoff:?}, attempt {attempt}"
+                ),
+            )
+            .await;
+
+        tokio::select! {
+            () = sleep(backoff) => {}
+            () = state.shutdown.requested() => return,
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::notifier::MockNotifier,
+        std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        tokio::time::timeout,
+    };
+
+    #[tokio::test]
+    async fn a_panicking_worker_is_recorded_alerted_and_restarted() {
+        let notifier = Arc::new(MockNotifier::default());
+        let state = AppState::temp(notifier.clone());
+        let starts = Arc::new(AtomicUsize::new(0));
+
+        let worker_state = state.clone();
+        let worker_starts = starts.clone();
+        let supervisor =
+            supervise_with_backoff(state.clone(), Duration::from_millis(10), move || {
+                let state = worker_state.clone();
+                let start = worker_starts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if start == 0 {
+                        panic!("boom");
+                    }
+                    // the restarted worker stops the supervisor
+                    state.shutdown.request();
+                }
+            });
+        timeout(Duration::from_secs(5), supervisor)
+            .await
+            .expect("supervisor returned after shutdown");
+
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        let restarts = state.store.read(|data| data.worker_restarts.clone()).await;
+        assert_eq!(restarts.len(), 1);
+        assert_eq!(restarts[0].message, "boom");
+        assert_eq!(restarts[0].attempt, 1);
+        assert!(
+            state
+                .store
+                .read(|data| data.alerts.contains_key(WORKER_ALERT_KEY))
+                .await
+        );
+        assert_eq!(
+            *notifier.messages.lock().unwrap(),
+            vec![
+                "[CRITICAL] Cranker worker stopped: boom. Restarting in 10ms, attempt 1"
+                    .to_string()
+            ]
+        );
+    }
+}
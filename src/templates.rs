@@ -0,0 +1,381 @@
+use {
+    crate::{UpdateOutcome, config::StakePoolConfig},
+    anyhow::Result,
+    solana_native_token::Sol,
+};
+
+const DEFAULT_EPOCH_CHANGED: &str =
+    "Epoch changed, executing update for stake pool {pool_name} for epoch {epoch}";
+const DEFAULT_UPDATE_SUCCEEDED: &str = "Updated stake pool {pool_name} for epoch {epoch}: \
+     {validator_count} validators in {transactions} transactions, fees {fees}, took {duration}";
+const DEFAULT_UPDATE_FAILED: &str = "Failed to run command to update stake pool {pool_name}";
+const DEFAULT_RPC_FAILED: &str =
+    "Rpc is failing to get the latest epoch info. Retrying again in 30 minutes";
+
+/// A value that can be substituted into a message template as `{name}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Var {
+    Pool,
+    PoolName,
+    Epoch,
+    ValidatorCount,
+    Transactions,
+    Signatures,
+    Fees,
+    Duration,
+    Error,
+}
+
+impl Var {
+    const ALL: [Var; 9] = [
+        Var::Pool,
+        Var::PoolName,
+        Var::Epoch,
+        Var::ValidatorCount,
+        Var::Transactions,
+        Var::Signatures,
+        Var::Fees,
+        Var::Duration,
+        Var::Error,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Var::Pool => "pool",
+            Var::PoolName => "pool_name",
+            Var::Epoch => "epoch",
+            Var::ValidatorCount => "validator_count",
+            Var::Transactions => "transactions",
+            Var::Signatures => "signatures",
+            Var::Fees => "fees",
+            Var::Duration => "duration",
+            Var::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Var(Var),
+}
+
+/// A parsed message template. `{name}` is replaced by a variable and `{{` /
+/// `}}` produce literal braces.
+#[derive(Clone, Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse `text`, rejecting unbalanced braces and any variable not in
+    /// `allowed` so typos are caught at startup rather than in an alert.
+    fn parse(name: &str, text: &str, allowed: &[Var]) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut var_name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => var_name.push(c),
+                            None => anyhow::bail!("Template {} has an unclosed '{{'", name),
+                        }
+                    }
+                    let var = Var::ALL
+                        .into_iter()
+                        .find(|var| var.name() == var_name.trim())
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "Template {} uses unknown variable {{{}}}",
+                                name,
+                                var_name
+                            )
+                        })?;
+                    if !allowed.contains(&var) {
+                        anyhow::bail!(
+                            "Template {} cannot use {{{}}}, available variables are: {}",
+                            name,
+                            var_name,
+                            allowed
+                                .iter()
+                                .map(|var| format!("{{{}}}", var.name()))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Var(var));
+                }
+                '}' => anyhow::bail!("Template {} has an unmatched '}}'", name),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    pub fn render(&self, context: &MessageContext) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Var(var) => context.value(*var),
+            })
+            .collect()
+    }
+}
+
+/// The values available when rendering a template.
+pub struct MessageContext<'a> {
+    pub pool: &'a str,
+    pub pool_name: &'a str,
+    pub epoch: u64,
+    pub outcome: Option<&'a UpdateOutcome>,
+    pub error: Option<String>,
+}
+
+impl MessageContext<'_> {
+    fn value(&self, var: Var) -> String {
+        match var {
+            Var::Pool => self.pool.to_string(),
+            Var::PoolName => self.pool_name.to_string(),
+            Var::Epoch => self.epoch.to_string(),
+            Var::ValidatorCount => self
+                .outcome
+                .map(|outcome| outcome.validator_count.to_string())
+                .unwrap_or_default(),
+            Var::Transactions => self
+                .outcome
+                .map(|outcome| outcome.signatures.len().to_string())
+                .unwrap_or_default(),
+            Var::Signatures => self
+                .outcome
+                .map(|outcome| {
+                    outcome
+                        .signatures
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default(),
+            Var::Fees => self
+                .outcome
+                .map(|outcome| Sol(outcome.fees).to_string())
+                .unwrap_or_default(),
+            Var::Duration => self
+                .outcome
+                .map(|outcome| format!("{:.1}s", outcome.duration.as_secs_f64()))
+                .unwrap_or_default(),
+            Var::Error => self.error.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// The templates for every message the worker sends.
+#[derive(Clone, Debug)]
+pub struct MessageTemplates {
+    pub epoch_changed: Template,
+    pub update_succeeded: Template,
+    pub update_failed: Template,
+    pub rpc_failed: Template,
+}
+
+impl MessageTemplates {
+    pub fn from_config(config: &StakePoolConfig) -> Result<Self> {
+        Self::new(
+            config.template_epoch_changed.as_deref(),
+            config.template_update_succeeded.as_deref(),
+            config.template_update_failed.as_deref(),
+            config.template_rpc_failed.as_deref(),
+        )
+    }
+
+    /// The default templates, without overrides.
+    #[cfg(any(test, feature = "dev-context-only-utils"))]
+    pub fn defaults() -> Self {
+        Self::new(None, None, None, None).expect("default templates parse")
+    }
+
+    /// Parse the `TEMPLATE_*` overrides, falling back to the defaults.
+    fn new(
+        epoch_changed: Option<&str>,
+        update_succeeded: Option<&str>,
+        update_failed: Option<&str>,
+        rpc_failed: Option<&str>,
+    ) -> Result<Self> {
+        let pool_vars = [Var::Pool, Var::PoolName, Var::Epoch];
+        Ok(Self {
+            epoch_changed: Template::parse(
+                "TEMPLATE_EPOCH_CHANGED",
+                epoch_changed.unwrap_or(DEFAULT_EPOCH_CHANGED),
+                &pool_vars,
+            )?,
+            update_succeeded: Template::parse(
+                "TEMPLATE_UPDATE_SUCCEEDED",
+                update_succeeded.unwrap_or(DEFAULT_UPDATE_SUCCEEDED),
+                &[
+                    Var::Pool,
+                    Var::PoolName,
+                    Var::Epoch,
+                    Var::ValidatorCount,
+                    Var::Transactions,
+                    Var::Signatures,
+                    Var::Fees,
+                    Var::Duration,
+                ],
+            )?,
+            update_failed: Template::parse(
+                "TEMPLATE_UPDATE_FAILED",
+                update_failed.unwrap_or(DEFAULT_UPDATE_FAILED),
+                &[Var::Pool, Var::PoolName, Var::Epoch, Var::Error],
+            )?,
+            rpc_failed: Template::parse(
+                "TEMPLATE_RPC_FAILED",
+                rpc_failed.unwrap_or(DEFAULT_RPC_FAILED),
+                &[Var::Pool, Var::PoolName, Var::Error],
+            )?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::time::Duration};
+
+    const ALL: &[Var] = &Var::ALL;
+
+    fn context(outcome: Option<&UpdateOutcome>) -> MessageContext<'_> {
+        MessageContext {
+            pool: "Pool1111",
+            pool_name: "jito",
+            epoch: 700,
+            outcome,
+            error: Some("rpc timeout".to_string()),
+        }
+    }
+
+    fn render(text: &str) -> String {
+        Template::parse("TEST", text, ALL)
+            .unwrap()
+            .render(&context(None))
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        assert_eq!(
+            render("{pool_name} ({pool}) epoch {epoch}: {error}"),
+            "jito (Pool1111) epoch 700: rpc timeout"
+        );
+        assert_eq!(render("{ epoch }"), "700");
+        assert_eq!(render("no variables"), "no variables");
+    }
+
+    #[test]
+    fn outcome_variables() {
+        let outcome = UpdateOutcome {
+            validator_count: 12,
+            signatures: vec!["sig1".to_string(), "sig2".to_string()],
+            fees: 10_000,
+            duration: Duration::from_millis(2_500),
+            ..UpdateOutcome::default()
+        };
+        let template = Template::parse(
+            "TEST",
+            "{validator_count}/{transactions}/{signatures}/{duration}",
+            ALL,
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&context(Some(&outcome))),
+            "12/2/sig1, sig2/2.5s"
+        );
+        // outcome variables render empty without an outcome
+        assert_eq!(template.render(&context(None)), "///");
+    }
+
+    #[test]
+    fn escaped_braces() {
+        assert_eq!(render("{{epoch}}"), "{epoch}");
+        assert_eq!(render("{{{epoch}}}"), "{700}");
+        assert_eq!(render("}}{{"), "}{");
+    }
+
+    #[test]
+    fn rejects_unknown_variables() {
+        let err = Template::parse("TEST", "{epcoh}", ALL).unwrap_err();
+        assert!(err.to_string().contains("unknown variable {epcoh}"));
+    }
+
+    #[test]
+    fn rejects_variables_not_allowed() {
+        let err = Template::parse("TEST", "{error}", &[Var::Epoch]).unwrap_err();
+        assert!(err.to_string().contains("available variables are: {epoch}"));
+    }
+
+    #[test]
+    fn rejects_unbalanced_braces() {
+        assert!(Template::parse("TEST", "epoch {epoch", ALL).is_err());
+        assert!(Template::parse("TEST", "{", ALL).is_err());
+        assert!(Template::parse("TEST", "epoch}", ALL).is_err());
+    }
+
+    #[test]
+    fn defaults_parse() {
+        let templates = MessageTemplates::new(None, None, None, None).unwrap();
+        assert_eq!(
+            templates.update_failed.render(&context(None)),
+            "Failed to run command to update stake pool jito"
+        );
+    }
+
+    #[test]
+    fn overrides_replace_defaults() {
+        let templates = MessageTemplates::new(
+            Some("{pool_name} is at {epoch}"),
+            None,
+            Some("{pool_name} failed: {error}"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            templates.epoch_changed.render(&context(None)),
+            "jito is at 700"
+        );
+        assert_eq!(
+            templates.update_failed.render(&context(None)),
+            "jito failed: rpc timeout"
+        );
+        assert!(
+            templates
+                .rpc_failed
+                .render(&context(None))
+                .starts_with("Rpc is failing")
+        );
+    }
+
+    #[test]
+    fn overrides_are_checked_against_their_variables() {
+        // the epoch changed message has no outcome to report
+        let err = MessageTemplates::new(Some("{fees}"), None, None, None).unwrap_err();
+        assert!(err.to_string().contains("TEMPLATE_EPOCH_CHANGED"));
+        let err = MessageTemplates::new(None, None, None, Some("{epoch")).unwrap_err();
+        assert!(err.to_string().contains("TEMPLATE_RPC_FAILED"));
+    }
+}
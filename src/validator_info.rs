@@ -0,0 +1,181 @@
+use {
+    crate::{
+        client::{get_stake_pool, get_validator_list},
+        config::StakePoolConfig,
+    },
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    solana_client::{
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_filter::{Memcmp, RpcFilterType},
+    },
+    solana_commitment_config::CommitmentConfig,
+    solana_pubkey::Pubkey,
+    solana_rpc_client::nonblocking::rpc_client::RpcClient,
+    std::{
+        collections::{HashMap, HashSet},
+        str::FromStr,
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    tokio::sync::RwLock,
+};
+
+const CONFIG_PROGRAM_ID: Pubkey =
+    Pubkey::from_str_const("Config1111111111111111111111111111111111111");
+
+/// First key of every validator-info config account
+const VALIDATOR_INFO_ID: Pubkey =
+    Pubkey::from_str_const("Va1idator1nfo111111111111111111111111111111");
+
+/// Length of a `ConfigKeys` entry, a pubkey and a signer flag
+const CONFIG_KEY_LEN: usize = 33;
+
+/// What the cluster knows about a pool validator.
+#[derive(Clone, Debug, Serialize)]
+pub struct ValidatorMetadata {
+    pub identity: String,
+    /// Name, website and details published with `solana validator-info`
+    pub name: Option<String>,
+    pub website: Option<String>,
+    pub details: Option<String>,
+    pub commission: u8,
+    pub activated_stake: u64,
+    pub last_vote: u64,
+    pub delinquent: bool,
+}
+
+#[derive(Default, Deserialize)]
+struct PublishedInfo {
+    name: Option<String>,
+    website: Option<String>,
+    details: Option<String>,
+}
+
+/// Parse a validator-info config account into the identity that signed it
+/// and the JSON it published. The account is `ConfigKeys` followed by a
+/// bincode string.
+fn parse_validator_info(data: &[u8]) -> Option<(Pubkey, PublishedInfo)> {
+    // the key count is a compact-u16, a single byte for the two keys used here
+    let key_count = *data.first()? as usize;
+    if key_count != 2 {
+        return None;
+    }
+    let keys_end = 1 + key_count * CONFIG_KEY_LEN;
+    let keys = data.get(1..keys_end)?;
+    if keys[..32] != VALIDATOR_INFO_ID.to_bytes() {
+        return None;
+    }
+    let identity = Pubkey::try_from(&keys[CONFIG_KEY_LEN..CONFIG_KEY_LEN + 32]).ok()?;
+    let json: String = bincode::deserialize(data.get(keys_end..)?).ok()?;
+    let info = serde_json::from_str(&json).unwrap_or_default();
+    Some((identity, info))
+}
+
+async fn fetch_validator_info(rpc_client: &RpcClient) -> Result<HashMap<Pubkey, PublishedInfo>> {
+    let accounts = rpc_client
+        .get_program_accounts_with_config(
+            &CONFIG_PROGRAM_ID,
+            RpcProgramAccountsConfig {
+                filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    1,
+                    VALIDATOR_INFO_ID.as_ref(),
+                ))]),
+                ..RpcProgramAccountsConfig::default()
+            },
+        )
+        .await?;
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(_, account)| parse_validator_info(&account.data))
+        .collect())
+}
+
+/// Metadata of every validator in the configured pools, keyed by vote
+/// account.
+pub async fn fetch_metadata(
+    config: &StakePoolConfig,
+) -> Result<HashMap<String, ValidatorMetadata>> {
+    let rpc_client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    let mut pool_validators = HashSet::new();
+    for address in &config.stake_pool_address {
+        let stake_pool = get_stake_pool(&rpc_client, &Pubkey::from_str(address)?).await?;
+        let validator_list = get_validator_list(&rpc_client, &stake_pool.validator_list).await?;
+        pool_validators.extend(
+            validator_list
+                .validators
+                .iter()
+                .map(|validator| validator.vote_account_address.to_string()),
+        );
+    }
+
+    let mut published = fetch_validator_info(&rpc_client).await?;
+    let vote_accounts = rpc_client.get_vote_accounts().await?;
+    let metadata = vote_accounts
+        .current
+        .into_iter()
+        .map(|info| (info, false))
+        .chain(
+            vote_accounts
+                .delinquent
+                .into_iter()
+                .map(|info| (info, true)),
+        )
+        .filter(|(info, _)| pool_validators.contains(&info.vote_pubkey))
+        .map(|(info, delinquent)| {
+            let info_for_identity = Pubkey::from_str(&info.node_pubkey)
+                .ok()
+                .and_then(|identity| published.remove(&identity))
+                .unwrap_or_default();
+            (
+                info.vote_pubkey,
+                ValidatorMetadata {
+                    identity: info.node_pubkey,
+                    name: info_for_identity.name,
+                    website: info_for_identity.website,
+                    details: info_for_identity.details,
+                    commission: info.commission,
+                    activated_stake: info.activated_stake,
+                    last_vote: info.last_vote,
+                    delinquent,
+                },
+            )
+        })
+        .collect();
+    Ok(metadata)
+}
+
+#[derive(Default)]
+struct CachedMetadata {
+    validators: HashMap<String, ValidatorMetadata>,
+    /// Unix timestamp of the last successful refresh
+    refreshed_at: Option<u64>,
+}
+
+/// Cloneable handle to the validator metadata served by the API, refreshed
+/// in the background so requests never wait on the RPC.
+#[derive(Clone, Default)]
+pub struct ValidatorInfoCache {
+    inner: Arc<RwLock<CachedMetadata>>,
+}
+
+impl ValidatorInfoCache {
+    pub async fn refresh(&self, config: &StakePoolConfig) -> Result<()> {
+        let validators = fetch_metadata(config).await?;
+        let mut cached = self.inner.write().await;
+        cached.validators = validators;
+        cached.refreshed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|duration| duration.as_secs());
+        Ok(())
+    }
+
+    /// Metadata of every cached vote account and the time of the last
+    /// refresh.
+    pub async fn all(&self) -> (HashMap<String, ValidatorMetadata>, Option<u64>) {
+        let cached = self.inner.read().await;
+        (cached.validators.clone(), cached.refreshed_at)
+    }
+}